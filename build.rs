@@ -1,6 +1,9 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // `sqlx::migrate!` embebe los archivos en tiempo de compilación
+    println!("cargo:rerun-if-changed=migrations");
+
     tonic_build::configure()
-        .build_server(false)
+        .build_server(true)
         .build_client(true)
         .compile_protos(
             &["proto/iot.proto"],
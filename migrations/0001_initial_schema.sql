@@ -0,0 +1,107 @@
+-- Esquema inicial del Data Saver.
+--
+-- Las columnas reflejan exactamente los binds de `src/database/tables/*.rs`.
+-- Cualquier cambio posterior debe agregarse como una nueva migración versionada
+-- (nunca editando este archivo, ya que sqlx valida el checksum).
+
+
+CREATE TABLE IF NOT EXISTS measurement (
+    id                  BIGSERIAL PRIMARY KEY,
+    timestamp           TIMESTAMPTZ NOT NULL,
+    network_id          TEXT        NOT NULL,
+    pulse_counter       BIGINT      NOT NULL,
+    pulse_max_duration  BIGINT      NOT NULL,
+    temperature         REAL,
+    humidity            REAL,
+    co2_ppm             REAL
+);
+
+CREATE INDEX IF NOT EXISTS idx_measurement_network_timestamp
+    ON measurement (network_id, timestamp);
+
+
+CREATE TABLE IF NOT EXISTS monitor (
+    id                   BIGSERIAL PRIMARY KEY,
+    sender_user_id       TEXT        NOT NULL,
+    destination_id       TEXT        NOT NULL,
+    timestamp            TIMESTAMPTZ NOT NULL,
+    network_id           TEXT        NOT NULL,
+    mem_free             BIGINT      NOT NULL,
+    mem_free_hm          BIGINT      NOT NULL,
+    mem_free_block       BIGINT      NOT NULL,
+    mem_free_internal    BIGINT      NOT NULL,
+    stack_free_min_coll  BIGINT      NOT NULL,
+    stack_free_min_pub   BIGINT      NOT NULL,
+    stack_free_min_mic   BIGINT      NOT NULL,
+    stack_free_min_th    BIGINT      NOT NULL,
+    stack_free_min_air   BIGINT      NOT NULL,
+    stack_free_min_mon   BIGINT      NOT NULL,
+    wifi_ssid            TEXT        NOT NULL,
+    wifi_rssi            INTEGER     NOT NULL,
+    active_time          BIGINT      NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_monitor_network_timestamp
+    ON monitor (network_id, timestamp);
+
+
+CREATE TABLE IF NOT EXISTS metric (
+    id                 BIGSERIAL PRIMARY KEY,
+    sender_user_id     TEXT        NOT NULL,
+    destination_id     TEXT        NOT NULL,
+    timestamp          TIMESTAMPTZ NOT NULL,
+    uptime_seconds     BIGINT      NOT NULL,
+    cpu_usage_percent  REAL        NOT NULL,
+    cpu_temp_celsius   REAL        NOT NULL,
+    ram_total_mb       BIGINT      NOT NULL,
+    ram_used_mb        BIGINT      NOT NULL,
+    sd_total_gb        BIGINT      NOT NULL,
+    sd_used_gb         BIGINT      NOT NULL,
+    sd_usage_percent   REAL        NOT NULL,
+    network_rx_bytes   BIGINT      NOT NULL,
+    network_tx_bytes   BIGINT      NOT NULL,
+    wifi_rssi          INTEGER,
+    wifi_signal_dbm    INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_metric_sender_timestamp
+    ON metric (sender_user_id, timestamp);
+
+
+CREATE TABLE IF NOT EXISTS alert_air (
+    id               BIGSERIAL PRIMARY KEY,
+    sender_user_id   TEXT        NOT NULL,
+    destination_id   TEXT        NOT NULL,
+    timestamp        TIMESTAMPTZ NOT NULL,
+    network_id       TEXT        NOT NULL,
+    co2_initial_ppm  REAL        NOT NULL,
+    co2_actual_ppm   REAL        NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_air_network_timestamp
+    ON alert_air (network_id, timestamp);
+
+
+CREATE TABLE IF NOT EXISTS alert_temp (
+    id              BIGSERIAL PRIMARY KEY,
+    sender_user_id  TEXT        NOT NULL,
+    destination_id  TEXT        NOT NULL,
+    timestamp       TIMESTAMPTZ NOT NULL,
+    network_id      TEXT        NOT NULL,
+    initial_temp    REAL        NOT NULL,
+    actual_temp     REAL        NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_temp_network_timestamp
+    ON alert_temp (network_id, timestamp);
+
+
+CREATE TABLE IF NOT EXISTS weather (
+    id           BIGSERIAL PRIMARY KEY,
+    timestamp    TIMESTAMPTZ NOT NULL,
+    temperature  REAL        NOT NULL,
+    humidity     REAL        NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_weather_timestamp
+    ON weather (timestamp);
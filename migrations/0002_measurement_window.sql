@@ -0,0 +1,8 @@
+-- Tamaño de la ventana de agregación de cada fila de `measurement`.
+--
+-- Las ventanas son configurables por red, por lo que la tabla mezcla resoluciones.
+-- Las filas anteriores a esta migración se agregaron con la ventana fija de 50 segundos.
+
+
+ALTER TABLE measurement
+    ADD COLUMN IF NOT EXISTS window_secs INTEGER NOT NULL DEFAULT 50;
@@ -0,0 +1,14 @@
+-- Muestras descartadas al agregar cada fila de `measurement`, por sensor y motivo.
+--
+-- `*_out_of_range`: fuera del rango plausible configurado para la red.
+-- `*_outliers`: dentro del rango, pero descartadas por el filtro de outliers.
+-- Las filas anteriores a esta migración no registraron descartes.
+
+
+ALTER TABLE measurement
+    ADD COLUMN IF NOT EXISTS temperature_out_of_range INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS temperature_outliers     INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS humidity_out_of_range    INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS humidity_outliers        INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS co2_out_of_range         INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS co2_outliers             INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+-- Estrategia de agregación aplicada a cada sensor de cada fila de `measurement`
+-- (ej. `iqr_mean`, `median`, `trimmed_mean:0.1`, `hampel:3`, `last_value`).
+--
+-- Las filas anteriores a esta migración se agregaron siempre con `iqr_mean`.
+
+
+ALTER TABLE measurement
+    ADD COLUMN IF NOT EXISTS temperature_strategy TEXT NOT NULL DEFAULT 'iqr_mean',
+    ADD COLUMN IF NOT EXISTS humidity_strategy    TEXT NOT NULL DEFAULT 'iqr_mean',
+    ADD COLUMN IF NOT EXISTS co2_strategy         TEXT NOT NULL DEFAULT 'iqr_mean';
@@ -0,0 +1,33 @@
+-- Dispersión y cobertura de cada sensor en cada fila de `measurement`.
+--
+-- `*_min`, `*_max`, `*_stddev`: sobre las muestras usadas por la estrategia de agregación
+--   (desvío estándar poblacional); NULL si no quedó ninguna.
+-- `*_samples_raw`: muestras recibidas en la ventana, antes de filtrar.
+-- `*_samples_kept`: muestras que quedaron tras los rangos plausibles y la estrategia.
+-- `*_first_at`, `*_last_at`: timestamps (del Edge) de la primera y la última muestra cruda.
+--
+-- Las filas anteriores a esta migración quedan con NULL y conteos en 0.
+
+
+ALTER TABLE measurement
+    ADD COLUMN IF NOT EXISTS temperature_min          REAL,
+    ADD COLUMN IF NOT EXISTS temperature_max          REAL,
+    ADD COLUMN IF NOT EXISTS temperature_stddev       REAL,
+    ADD COLUMN IF NOT EXISTS temperature_samples_raw  INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS temperature_samples_kept INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS temperature_first_at     TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS temperature_last_at      TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS humidity_min             REAL,
+    ADD COLUMN IF NOT EXISTS humidity_max             REAL,
+    ADD COLUMN IF NOT EXISTS humidity_stddev          REAL,
+    ADD COLUMN IF NOT EXISTS humidity_samples_raw     INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS humidity_samples_kept    INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS humidity_first_at        TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS humidity_last_at         TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS co2_min                  REAL,
+    ADD COLUMN IF NOT EXISTS co2_max                  REAL,
+    ADD COLUMN IF NOT EXISTS co2_stddev               REAL,
+    ADD COLUMN IF NOT EXISTS co2_samples_raw          INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS co2_samples_kept         INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS co2_first_at             TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS co2_last_at              TIMESTAMPTZ;
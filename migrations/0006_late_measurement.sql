@@ -0,0 +1,24 @@
+-- Muestras que llegaron después de cerrado su bucket (`LATE_DATA_POLICY=table`).
+--
+-- Se guardan crudas, sin agregar, para que los analistas decidan cómo reconciliarlas con la
+-- fila de `measurement` ya emitida para `(network_id, bucket_timestamp, window_secs)`.
+
+
+CREATE TABLE IF NOT EXISTS late_measurement (
+    id                  BIGSERIAL   PRIMARY KEY,
+    received_at         TIMESTAMPTZ NOT NULL,
+    timestamp           TIMESTAMPTZ NOT NULL,
+    network_id          TEXT        NOT NULL,
+    bucket_timestamp    TIMESTAMPTZ NOT NULL,
+    window_secs         INTEGER     NOT NULL,
+    sender_user_id      TEXT        NOT NULL,
+    sample              BIGINT      NOT NULL,
+    pulse_counter       BIGINT      NOT NULL,
+    pulse_max_duration  BIGINT      NOT NULL,
+    temperature         REAL        NOT NULL,
+    humidity            REAL        NOT NULL,
+    co2_ppm             REAL        NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_late_measurement_bucket
+    ON late_measurement (network_id, bucket_timestamp);
@@ -0,0 +1,165 @@
+-- Claves naturales únicas en todas las tablas, para que los reenvíos del Edge, los reintentos
+-- y la reproducción de la cola de desborde sean idempotentes.
+--
+-- * `measurement`: una fila por `(network_id, timestamp, window_secs)`. Las inserciones
+--   posteriores para la misma ventana se fusionan (ver `database::tables::measurement`).
+--   `aggregate_ids` registra los agregados ya fusionados para ignorar reintentos.
+-- * Tablas de eventos: una fila por emisor, red y timestamp; los duplicados se ignoran.
+-- * `weather`: una fila por timestamp.
+--
+-- Antes de crear cada índice se eliminan los duplicados existentes, conservando la fila más
+-- antigua (menor `id`). Las filas eliminadas se copian antes a `<tabla>_duplicates`, con las
+-- mismas columnas, para poder revisarlas o recuperarlas.
+
+
+ALTER TABLE measurement
+    ADD COLUMN IF NOT EXISTS aggregate_ids TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE TABLE IF NOT EXISTS measurement_duplicates (LIKE measurement);
+
+INSERT INTO measurement_duplicates
+    SELECT a.* FROM measurement a
+    WHERE EXISTS (
+        SELECT 1 FROM measurement b
+        WHERE a.id > b.id
+          AND a.network_id = b.network_id
+          AND a.timestamp = b.timestamp
+          AND a.window_secs = b.window_secs
+    );
+
+DELETE FROM measurement a USING measurement b
+    WHERE a.id > b.id
+      AND a.network_id = b.network_id
+      AND a.timestamp = b.timestamp
+      AND a.window_secs = b.window_secs;
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_measurement_window
+    ON measurement (network_id, timestamp, window_secs);
+
+
+CREATE TABLE IF NOT EXISTS monitor_duplicates (LIKE monitor);
+
+INSERT INTO monitor_duplicates
+    SELECT a.* FROM monitor a
+    WHERE EXISTS (
+        SELECT 1 FROM monitor b
+        WHERE a.id > b.id
+          AND a.sender_user_id = b.sender_user_id
+          AND a.network_id = b.network_id
+          AND a.timestamp = b.timestamp
+    );
+
+DELETE FROM monitor a USING monitor b
+    WHERE a.id > b.id
+      AND a.sender_user_id = b.sender_user_id
+      AND a.network_id = b.network_id
+      AND a.timestamp = b.timestamp;
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_monitor_sender_network_timestamp
+    ON monitor (sender_user_id, network_id, timestamp);
+
+
+CREATE TABLE IF NOT EXISTS metric_duplicates (LIKE metric);
+
+INSERT INTO metric_duplicates
+    SELECT a.* FROM metric a
+    WHERE EXISTS (
+        SELECT 1 FROM metric b
+        WHERE a.id > b.id
+          AND a.sender_user_id = b.sender_user_id
+          AND a.timestamp = b.timestamp
+    );
+
+DELETE FROM metric a USING metric b
+    WHERE a.id > b.id
+      AND a.sender_user_id = b.sender_user_id
+      AND a.timestamp = b.timestamp;
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_metric_sender_timestamp
+    ON metric (sender_user_id, timestamp);
+
+
+CREATE TABLE IF NOT EXISTS alert_air_duplicates (LIKE alert_air);
+
+INSERT INTO alert_air_duplicates
+    SELECT a.* FROM alert_air a
+    WHERE EXISTS (
+        SELECT 1 FROM alert_air b
+        WHERE a.id > b.id
+          AND a.sender_user_id = b.sender_user_id
+          AND a.network_id = b.network_id
+          AND a.timestamp = b.timestamp
+    );
+
+DELETE FROM alert_air a USING alert_air b
+    WHERE a.id > b.id
+      AND a.sender_user_id = b.sender_user_id
+      AND a.network_id = b.network_id
+      AND a.timestamp = b.timestamp;
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_alert_air_sender_network_timestamp
+    ON alert_air (sender_user_id, network_id, timestamp);
+
+
+CREATE TABLE IF NOT EXISTS alert_temp_duplicates (LIKE alert_temp);
+
+INSERT INTO alert_temp_duplicates
+    SELECT a.* FROM alert_temp a
+    WHERE EXISTS (
+        SELECT 1 FROM alert_temp b
+        WHERE a.id > b.id
+          AND a.sender_user_id = b.sender_user_id
+          AND a.network_id = b.network_id
+          AND a.timestamp = b.timestamp
+    );
+
+DELETE FROM alert_temp a USING alert_temp b
+    WHERE a.id > b.id
+      AND a.sender_user_id = b.sender_user_id
+      AND a.network_id = b.network_id
+      AND a.timestamp = b.timestamp;
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_alert_temp_sender_network_timestamp
+    ON alert_temp (sender_user_id, network_id, timestamp);
+
+
+CREATE TABLE IF NOT EXISTS weather_duplicates (LIKE weather);
+
+INSERT INTO weather_duplicates
+    SELECT a.* FROM weather a
+    WHERE EXISTS (
+        SELECT 1 FROM weather b
+        WHERE a.id > b.id
+          AND a.timestamp = b.timestamp
+    );
+
+DELETE FROM weather a USING weather b
+    WHERE a.id > b.id
+      AND a.timestamp = b.timestamp;
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_weather_timestamp
+    ON weather (timestamp);
+
+
+CREATE TABLE IF NOT EXISTS late_measurement_duplicates (LIKE late_measurement);
+
+INSERT INTO late_measurement_duplicates
+    SELECT a.* FROM late_measurement a
+    WHERE EXISTS (
+        SELECT 1 FROM late_measurement b
+        WHERE a.id > b.id
+          AND a.sender_user_id = b.sender_user_id
+          AND a.network_id = b.network_id
+          AND a.timestamp = b.timestamp
+          AND a.sample = b.sample
+    );
+
+DELETE FROM late_measurement a USING late_measurement b
+    WHERE a.id > b.id
+      AND a.sender_user_id = b.sender_user_id
+      AND a.network_id = b.network_id
+      AND a.timestamp = b.timestamp
+      AND a.sample = b.sample;
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_late_measurement_sample
+    ON late_measurement (sender_user_id, network_id, timestamp, sample);
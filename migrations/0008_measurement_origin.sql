@@ -0,0 +1,11 @@
+-- Origen de cada fila de `measurement`: hubs (`sender_user_id`) y destinos (`destination_id`)
+-- que aportaron muestras a la ventana, y mediciones repetidas (mismo `sender_user_id` y
+-- `sample`) descartadas antes de agregar.
+--
+-- Las filas anteriores a esta migración no registran su origen.
+
+
+ALTER TABLE measurement
+    ADD COLUMN IF NOT EXISTS senders           TEXT[]  NOT NULL DEFAULT '{}',
+    ADD COLUMN IF NOT EXISTS destinations      TEXT[]  NOT NULL DEFAULT '{}',
+    ADD COLUMN IF NOT EXISTS duplicate_samples INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,27 @@
+-- Archivo crudo de mediciones (`RAW_ARCHIVE_NETWORKS`).
+--
+-- Guarda cada medición recibida de las redes archivadas, antes de agregarla, para auditar una
+-- ventana o volver a agregarla con otros filtros (`POST /archive/reaggregate`). Las filas más
+-- antiguas que `RAW_ARCHIVE_RETENTION_DAYS` se eliminan periódicamente.
+
+
+CREATE TABLE IF NOT EXISTS raw_measurement (
+    id                  BIGSERIAL   PRIMARY KEY,
+    received_at         TIMESTAMPTZ NOT NULL DEFAULT now(),
+    timestamp           TIMESTAMPTZ NOT NULL,
+    network_id          TEXT        NOT NULL,
+    sender_user_id      TEXT        NOT NULL,
+    destination_id      TEXT        NOT NULL,
+    sample              BIGINT      NOT NULL,
+    pulse_counter       BIGINT      NOT NULL,
+    pulse_max_duration  BIGINT      NOT NULL,
+    temperature         REAL        NOT NULL,
+    humidity            REAL        NOT NULL,
+    co2_ppm             REAL        NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_raw_measurement_sample
+    ON raw_measurement (sender_user_id, network_id, timestamp, sample);
+
+CREATE INDEX IF NOT EXISTS idx_raw_measurement_network_timestamp
+    ON raw_measurement (network_id, timestamp);
@@ -1,7 +1,9 @@
-use std::env;
+use std::sync::Arc;
 use reqwest::Client;
 use serde_json::json;
 use tracing::{error, info};
+use crate::metrics::domain::Metrics;
+use crate::system::domain::System;
 
 
 /// Estructura para manejar el cliente de Telegram de forma reutilizable.
@@ -10,20 +12,20 @@ pub struct TelegramNotifier {
     client: Client,
     bot_token: String,
     chat_id: String,
+    metrics: Arc<Metrics>,
 }
 
 impl TelegramNotifier {
-    /// Inicializa el notificador.
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    /// Inicializa el notificador con `BOT_TOKEN` y `CHAT_ID` de `system`.
+    pub fn new(system: &System, metrics: Arc<Metrics>) -> Result<Self, Box<dyn std::error::Error>> {
 
         info!("Info: creando objeto telegram_notifier");
 
         Ok(TelegramNotifier {
             client: Client::new(),
-            bot_token: env::var("BOT_TOKEN")
-                .expect("BOT_TOKEN no está configurado"),
-            chat_id: env::var("CHAT_ID")
-                .expect("CHAT_ID no está configurado"),
+            bot_token: system.bot_token.clone(),
+            chat_id: system.chat_id.clone(),
+            metrics,
         })
     }
 
@@ -37,17 +39,21 @@ impl TelegramNotifier {
             "parse_mode": "Markdown"
         });
 
-        match self.client.post(&url).json(&payload).send().await {
+        let outcome = match self.client.post(&url).json(&payload).send().await {
             Ok(response) => {
                 if response.status().is_success() {
                     info!("Info: alerta de Telegram enviada con éxito");
+                    "success"
                 } else {
                     error!("Error: fallo al enviar alerta, código de estado: {}", response.status());
+                    "http_error"
                 }
             }
             Err(e) => {
                 error!("Error: fallo en la red al intentar enviar alerta a Telegram: {}", e);
+                "network_error"
             }
-        }
+        };
+        self.metrics.telegram_alerts.with_label_values(&[outcome]).inc();
     }
 }
\ No newline at end of file
@@ -0,0 +1,102 @@
+//! Configuración del archivo crudo de mediciones.
+
+
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+
+
+/// Redes cuyas mediciones crudas se archivan (`RAW_ARCHIVE_NETWORKS`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArchiveScope {
+    /// Archivo deshabilitado.
+    Off,
+    /// Todas las redes (`*`).
+    All,
+    /// Solo las redes listadas.
+    Networks(HashSet<String>),
+}
+
+
+impl ArchiveScope {
+
+    /// Interpreta `*`, una lista de redes separadas por comas o vacío (deshabilitado).
+    pub fn parse(spec: Option<&str>) -> Self {
+        let networks: HashSet<String> = spec.unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|network| !network.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        if networks.contains("*") {
+            ArchiveScope::All
+        } else if networks.is_empty() {
+            ArchiveScope::Off
+        } else {
+            ArchiveScope::Networks(networks)
+        }
+    }
+}
+
+
+/// Parámetros del archivo crudo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveSettings {
+    pub scope: ArchiveScope,
+    /// Días que se conservan las mediciones archivadas (por timestamp del Edge).
+    pub retention_days: i64,
+    /// Mediciones por inserción.
+    pub batch_size: usize,
+    /// Segundos máximos que una medición espera en el lote antes de insertarse.
+    pub flush_secs: u64,
+}
+
+
+impl ArchiveSettings {
+
+    pub fn is_enabled(&self) -> bool {
+        self.scope != ArchiveScope::Off
+    }
+
+    /// Indica si se archivan las mediciones de `network_id`.
+    pub fn archives(&self, network_id: &str) -> bool {
+        match &self.scope {
+            ArchiveScope::Off => false,
+            ArchiveScope::All => true,
+            ArchiveScope::Networks(networks) => networks.contains(network_id),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.retention_days <= 0 || self.batch_size == 0 || self.flush_secs == 0 {
+            return Err("la retención, el lote y el intervalo del archivo crudo deben ser mayores a cero".to_string());
+        }
+        Ok(())
+    }
+}
+
+
+/// Cuerpo de `POST /archive/reaggregate`. `from` y `to` son Unix en segundos; el rango se
+/// amplía a ventanas completas.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReaggregateRequest {
+    pub network_id: String,
+    pub from: i64,
+    pub to: i64,
+}
+
+
+/// Resultado de una reagregación.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReaggregateReport {
+    pub network_id: String,
+    /// Rango efectivo `[from, to)`: alineado a la ventana actual de la red y limitado a las
+    /// ventanas ya cerradas.
+    pub from: i64,
+    pub to: i64,
+    pub window_secs: i64,
+    /// Filas de `measurement` reemplazadas.
+    pub windows: usize,
+    /// Mediciones archivadas leídas.
+    pub samples: usize,
+}
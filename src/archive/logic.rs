@@ -0,0 +1,304 @@
+//! Archivo crudo de mediciones y reagregación.
+//!
+//! `bucket_task` envía a `archive_task` las mediciones de las redes archivadas
+//! (`RAW_ARCHIVE_NETWORKS`), que las inserta en `raw_measurement` por lotes de
+//! `RAW_ARCHIVE_BATCH_SIZE` o cada `RAW_ARCHIVE_FLUSH_SECS`, lo que ocurra primero. Cada hora
+//! elimina las filas más antiguas que `RAW_ARCHIVE_RETENTION_DAYS`.
+//!
+//! El archivo es independiente de los Ack: una medición se confirma al persistirse su
+//! agregado. Un lote que no se pudo insertar se reintenta en el siguiente vaciado.
+//!
+//! `POST /archive/reaggregate` vuelve a agregar un rango de ventanas cerradas con la
+//! configuración vigente (ventana, rangos plausibles y estrategias) y reemplaza sus filas
+//! de `measurement`.
+
+
+use std::collections::BTreeMap;
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use crate::archive::domain::{ReaggregateReport, ReaggregateRequest};
+use crate::bucket::domain::BucketConfig;
+use crate::bucket::logic::aggregate_bucket;
+use crate::context::domain::{AppContext, BucketKey};
+use crate::message::domain::Measurement;
+use crate::supervisor::domain::shared;
+use crate::supervisor::logic::supervise;
+
+
+/// Cada cuántos segundos se eliminan las filas vencidas.
+const RETENTION_INTERVAL_SECS: u64 = 3600;
+
+/// Lotes pendientes que se conservan mientras la base no responde; luego se descartan los
+/// más antiguos.
+const MAX_PENDING_BATCHES: usize = 10;
+
+/// Ventanas leídas del archivo por consulta al reagregar.
+const REAGGREGATE_SLICE_WINDOWS: i64 = 500;
+
+/// Ventanas por petición de reagregación (una semana de ventanas de un minuto). Un rango
+/// mayor se rechaza: se debe dividir en varias peticiones.
+const REAGGREGATE_MAX_WINDOWS: i64 = 10_080;
+
+
+/// Acumula las mediciones recibidas y las inserta por lotes.
+///
+/// # Retorno
+/// Cantidad de mediciones archivadas.
+pub async fn archive_task(rx: &mut mpsc::Receiver<Vec<Measurement>>,
+                          app_context: AppContext) -> usize {
+
+    let settings = &app_context.system.raw_archive;
+    let mut pending: Vec<Measurement> = Vec::new();
+    let mut archived = 0;
+
+    let mut flush_ticker = interval(Duration::from_secs(settings.flush_secs));
+    let mut retention_ticker = interval(Duration::from_secs(RETENTION_INTERVAL_SECS));
+
+    loop {
+        tokio::select! {
+            received = rx.recv() => match received {
+                Some(measurements) => {
+                    pending.extend(measurements);
+                    if pending.len() >= settings.batch_size {
+                        archived += flush(&app_context, &mut pending).await;
+                    }
+                }
+                None => break,
+            },
+            _ = flush_ticker.tick() => {
+                archived += flush(&app_context, &mut pending).await;
+            }
+            _ = retention_ticker.tick() => purge(&app_context).await,
+        }
+    }
+
+    // `bucket_task` soltó el canal: se inserta lo que quedó antes de terminar
+    archived += flush(&app_context, &mut pending).await;
+    info!("Info: archive_task finalizada. Mediciones sin archivar: {}", pending.len());
+    archived
+}
+
+
+/// Inserta lo pendiente en lotes de `RAW_ARCHIVE_BATCH_SIZE`. Lo que falla queda pendiente.
+///
+/// # Retorno
+/// Cantidad de mediciones insertadas.
+async fn flush(app_context: &AppContext, pending: &mut Vec<Measurement>) -> usize {
+    let batch_size = app_context.system.raw_archive.batch_size;
+    let mut inserted = 0;
+
+    while !pending.is_empty() {
+        let batch: Vec<Measurement> = pending.iter().take(batch_size).cloned().collect();
+        let rows = batch.len();
+        if let Err(e) = app_context.repo.insert_raw_measurements(batch).await {
+            error!("Error: no se pudo archivar un lote de {} mediciones. Se reintenta. {}", rows, e);
+            break;
+        }
+        pending.drain(..rows);
+        inserted += rows;
+    }
+
+    let limit = MAX_PENDING_BATCHES * batch_size;
+    if pending.len() > limit {
+        let dropped = pending.len() - limit;
+        pending.drain(..dropped);
+        app_context.metrics.archive_dropped.with_label_values(&["pending_limit"]).inc_by(dropped as u64);
+        warn!("Warning: archivo crudo saturado, se descartan {} mediciones sin archivar", dropped);
+    }
+    inserted
+}
+
+
+/// Elimina las mediciones archivadas más antiguas que `RAW_ARCHIVE_RETENTION_DAYS`.
+async fn purge(app_context: &AppContext) {
+    let retention_secs = app_context.system.raw_archive.retention_days * 24 * 3600;
+    let cutoff = chrono::Utc::now().timestamp() - retention_secs;
+    match app_context.repo.purge_raw_measurements(cutoff).await {
+        Ok(0) => {}
+        Ok(deleted) => info!("Info: {} mediciones archivadas vencidas eliminadas", deleted),
+        Err(e) => error!("Error: no se pudieron eliminar las mediciones archivadas vencidas. {}", e),
+    }
+}
+
+
+/// Rango efectivo `[from, to)` de una reagregación.
+///
+/// Se recorta a lo que puede haber en el archivo (desde `oldest`, el inicio de la retención,
+/// hasta `now`), se amplía a ventanas completas de `config` y se limita a las ventanas ya
+/// cerradas: hasta `closed_until` si la red tiene watermark, y nunca la ventana en curso.
+///
+/// # Retorno
+/// `Err` con el motivo si el pedido es inválido o abarca más de `REAGGREGATE_MAX_WINDOWS`.
+fn reaggregate_range(config: &BucketConfig,
+                     request: &ReaggregateRequest,
+                     closed_until: Option<i64>,
+                     oldest: i64,
+                     now: i64) -> Result<(i64, i64), String> {
+
+    if request.from >= request.to {
+        return Err("from debe ser menor que to".to_string());
+    }
+
+    let start = request.from.max(oldest);
+    let end = request.to.min(now);
+    let from = config.align(start.min(now));
+    if end <= start {
+        return Ok((from, from));
+    }
+
+    let mut to = config.align(end - 1)
+        .checked_add(config.window_secs)
+        .ok_or_else(|| format!("to fuera de rango: {}", request.to))?
+        .min(config.align(now));
+    if let Some(closed_until) = closed_until {
+        to = to.min(config.align(closed_until.max(from)));
+    }
+    let to = to.max(from);
+
+    let windows = (to - from) / config.window_secs;
+    if windows > REAGGREGATE_MAX_WINDOWS {
+        return Err(format!("el rango abarca {windows} ventanas; el máximo por petición es {REAGGREGATE_MAX_WINDOWS}"));
+    }
+    Ok((from, to))
+}
+
+
+/// [`reaggregate_range`] con la configuración, el watermark y la retención vigentes de la red.
+pub fn effective_range(app_context: &AppContext, request: &ReaggregateRequest) -> Result<(i64, i64), String> {
+    let config = app_context.system.bucket.for_network(&request.network_id);
+    let now = chrono::Utc::now().timestamp();
+    let retention_secs = app_context.system.raw_archive.retention_days.saturating_mul(24 * 3600);
+    let closed_until = app_context.watermarks.get(&request.network_id).map(|watermark| watermark.closed_until);
+    reaggregate_range(&config, request, closed_until, now.saturating_sub(retention_secs), now)
+}
+
+
+/// Vuelve a agregar las ventanas de `[from, to)` desde el archivo crudo y reemplaza sus filas.
+///
+/// `from` y `to` deben venir de [`effective_range`]: alineados a la ventana actual de la red y
+/// sin ventanas abiertas, que todavía van a emitir su propio agregado. Las ventanas sin
+/// mediciones archivadas no se modifican.
+pub async fn reaggregate(app_context: &AppContext,
+                         network_id: String,
+                         from: i64,
+                         to: i64) -> Result<ReaggregateReport, sqlx::Error> {
+
+    let config = app_context.system.bucket.for_network(&network_id);
+    let window_secs = config.window_secs;
+
+    let mut report = ReaggregateReport {
+        network_id: network_id.clone(),
+        from,
+        to,
+        window_secs,
+        ..Default::default()
+    };
+
+    let ranges = app_context.plausibility.current().for_network(&network_id);
+    let aggregators = app_context.system.aggregation.for_network(&network_id);
+
+    let mut slice_from = from;
+    while slice_from < to {
+        let slice_to = (slice_from + REAGGREGATE_SLICE_WINDOWS * window_secs).min(to);
+        let measurements = app_context.repo.raw_measurements(&network_id, slice_from, slice_to).await?;
+        report.samples += measurements.len();
+
+        let mut buckets: BTreeMap<i64, Vec<Measurement>> = BTreeMap::new();
+        for measurement in measurements {
+            buckets.entry(config.align(measurement.metadata.timestamp))
+                .or_default()
+                .push(measurement);
+        }
+
+        for (bucket_ts, vector) in buckets {
+            let key: BucketKey = (network_id.clone(), bucket_ts, window_secs);
+            let telemetry = aggregate_bucket(key, vector, ranges, aggregators.clone());
+            app_context.repo.replace_telemetry(telemetry).await?;
+            report.windows += 1;
+        }
+        slice_from = slice_to;
+    }
+
+    info!("Info: reagregadas {} ventanas de la red {} ({} mediciones archivadas)",
+        report.windows, network_id, report.samples);
+    Ok(report)
+}
+
+
+/// Handler de `POST /archive/reaggregate`.
+pub async fn reaggregate_handler(State(app_context): State<AppContext>,
+                                 Json(request): Json<ReaggregateRequest>) -> Response {
+    let (from, to) = match effective_range(&app_context, &request) {
+        Ok(range) => range,
+        Err(reason) => return (StatusCode::BAD_REQUEST, reason).into_response(),
+    };
+    match reaggregate(&app_context, request.network_id, from, to).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => {
+            error!("Error: no se pudo reagregar desde el archivo crudo. {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+
+pub fn start_archive(rx: mpsc::Receiver<Vec<Measurement>>,
+                     app_context: AppContext) -> JoinHandle<Option<usize>> {
+
+    info!("Info: iniciando tarea archive_task");
+    let rx = shared(rx);
+    supervise("archive_task", false, &app_context.clone(), move || {
+        let rx = rx.clone();
+        let app_context = app_context.clone();
+        async move {
+            archive_task(&mut *rx.lock().await, app_context).await
+        }
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_767_225_630;
+    const OLDEST: i64 = NOW - 30 * 24 * 3600;
+
+    fn config() -> BucketConfig {
+        BucketConfig { window_secs: 60, lateness_secs: 0, sweep_secs: 1 }
+    }
+
+    fn range(from: i64, to: i64, closed_until: Option<i64>) -> Result<(i64, i64), String> {
+        let request = ReaggregateRequest { network_id: "lab".to_string(), from, to };
+        reaggregate_range(&config(), &request, closed_until, OLDEST, NOW)
+    }
+
+    #[test]
+    fn widens_to_whole_closed_windows() {
+        assert_eq!(range(NOW - 3590, NOW - 3530, None), Ok((NOW - 3630, NOW - 3510)));
+        assert_eq!(range(NOW - 3600, NOW + 3600, None), Ok((NOW - 3630, NOW - 30)));
+        assert_eq!(range(NOW - 3600, NOW, Some(NOW - 1830)), Ok((NOW - 3630, NOW - 1830)));
+    }
+
+    #[test]
+    fn clamps_to_the_retention_and_to_now() {
+        assert_eq!(range(0, OLDEST + 120, None), Ok((OLDEST - 30, OLDEST + 150)));
+        assert_eq!(range(NOW + 60, NOW + 600, None), Ok((NOW - 30, NOW - 30)));
+        assert_eq!(range(i64::MIN, i64::MIN + 1, None), Ok((OLDEST - 30, OLDEST - 30)));
+        assert_eq!(range(i64::MAX - 1, i64::MAX, None), Ok((NOW - 30, NOW - 30)));
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_ranges() {
+        assert!(range(NOW, NOW, None).is_err());
+        assert!(range(NOW, NOW - 60, None).is_err());
+        assert!(range(0, NOW, None).is_err());
+        assert!(range(NOW - REAGGREGATE_MAX_WINDOWS * 60, NOW, None).is_ok());
+    }
+}
@@ -0,0 +1,496 @@
+//! Estrategias de agregación de las muestras de un bucket.
+//!
+//! Cada sensor de cada red se agrega con un [`Aggregator`] configurable. La estrategia por
+//! defecto (`AGGREGATION_STRATEGY`) se puede reemplazar por sensor, por red o por ambos
+//! (`AGGREGATION_OVERRIDES`). El nombre de la estrategia aplicada se guarda con cada fila.
+//!
+//! # Estrategias
+//! * `iqr_mean`: descarta outliers fuera de 1.5×IQR y promedia (con menos de 4 muestras solo promedia).
+//! * `median`: mediana de las muestras.
+//! * `trimmed_mean[:fracción]`: descarta la fracción más baja y más alta (por defecto `0.1`) y promedia.
+//! * `hampel[:k]`: descarta las muestras a más de `k` desvíos robustos (MAD) de la mediana
+//!   (por defecto `3`) y promedia. Si la MAD es cero usa la desviación media absoluta.
+//! * `last_value`: la muestra más reciente.
+
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+
+/// Resultado de agregar las muestras de un sensor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    /// Valor agregado, o `None` si no había muestras.
+    pub value: Option<f32>,
+    /// Muestras que la estrategia no descartó, en orden ascendente.
+    pub kept: Vec<f32>,
+}
+
+
+/// Estrategia de agregación de las muestras (ya filtradas por rango) de un sensor.
+pub trait Aggregator: Debug + Send + Sync {
+
+    /// Nombre con el que se configura y se persiste, incluidos sus parámetros.
+    fn name(&self) -> String;
+
+    /// Agrega las muestras, ordenadas de la más antigua a la más reciente.
+    fn aggregate(&self, samples: &[f32]) -> Aggregate;
+}
+
+
+/// Estrategia compartida entre el sweeper y sus workers.
+pub type SharedAggregator = Arc<dyn Aggregator>;
+
+
+/// Promedio tras descartar outliers por rango intercuartílico (comportamiento histórico).
+#[derive(Debug)]
+pub struct IqrMean;
+
+impl Aggregator for IqrMean {
+    fn name(&self) -> String {
+        "iqr_mean".to_string()
+    }
+
+    fn aggregate(&self, samples: &[f32]) -> Aggregate {
+        let sorted = sorted(samples);
+        if sorted.len() < 4 {
+            return Aggregate { value: mean(&sorted), kept: sorted };
+        }
+        let q1 = percentile(&sorted, 25.0);
+        let q3 = percentile(&sorted, 75.0);
+        let iqr = q3 - q1;
+        let kept: Vec<f32> = sorted.into_iter()
+            .filter(|&x| x >= q1 - 1.5*iqr && x <= q3 + 1.5*iqr)
+            .collect();
+        Aggregate { value: mean(&kept), kept }
+    }
+}
+
+
+/// Mediana de las muestras.
+#[derive(Debug)]
+pub struct Median;
+
+impl Aggregator for Median {
+    fn name(&self) -> String {
+        "median".to_string()
+    }
+
+    fn aggregate(&self, samples: &[f32]) -> Aggregate {
+        Aggregate { value: median(samples), kept: sorted(samples) }
+    }
+}
+
+
+/// Promedio tras descartar una fracción de las muestras en cada extremo.
+#[derive(Debug)]
+pub struct TrimmedMean {
+    /// Fracción descartada en cada extremo, en `[0, 0.5)`.
+    pub fraction: f32,
+}
+
+impl Aggregator for TrimmedMean {
+    fn name(&self) -> String {
+        format!("trimmed_mean:{}", self.fraction)
+    }
+
+    fn aggregate(&self, samples: &[f32]) -> Aggregate {
+        let sorted = sorted(samples);
+        // Con fracciones cercanas a 0.5 el redondeo de f32 podría descartarlo todo
+        let trim = ((sorted.len() as f32 * self.fraction).floor() as usize)
+            .min(sorted.len().saturating_sub(1) / 2);
+        let kept = sorted[trim..sorted.len() - trim].to_vec();
+        Aggregate { value: mean(&kept), kept }
+    }
+}
+
+
+/// Identificador de Hampel: descarta las muestras lejanas a la mediana en unidades de MAD
+/// (escalada por 1.4826 para estimar el desvío estándar) y promedia el resto.
+///
+/// Si más de la mitad de las muestras son idénticas la MAD es cero. En ese caso la escala es la
+/// desviación media absoluta respecto de la mediana (escalada por 1.2533): una muestra aislada
+/// lejos de la mediana se descarta, y si todas son idénticas se conservan todas.
+#[derive(Debug)]
+pub struct Hampel {
+    /// Cantidad de desvíos robustos tolerados.
+    pub k: f32,
+}
+
+impl Aggregator for Hampel {
+    fn name(&self) -> String {
+        format!("hampel:{}", self.k)
+    }
+
+    fn aggregate(&self, samples: &[f32]) -> Aggregate {
+        let Some(center) = median(samples) else {
+            return Aggregate { value: None, kept: Vec::new() };
+        };
+        let deviations: Vec<f32> = samples.iter().map(|x| (x - center).abs()).collect();
+        let mad = median(&deviations).unwrap_or_default();
+        let scale = if mad > 0.0 {
+            1.4826 * mad
+        } else {
+            1.2533 * mean(&deviations).unwrap_or_default()
+        };
+        let threshold = self.k * scale;
+
+        let kept: Vec<f32> = sorted(samples).into_iter()
+            .filter(|x| (x - center).abs() <= threshold)
+            .collect();
+        Aggregate { value: mean(&kept), kept }
+    }
+}
+
+
+/// Última muestra recibida (por timestamp del Edge).
+#[derive(Debug)]
+pub struct LastValue;
+
+impl Aggregator for LastValue {
+    fn name(&self) -> String {
+        "last_value".to_string()
+    }
+
+    fn aggregate(&self, samples: &[f32]) -> Aggregate {
+        Aggregate { value: samples.last().copied(), kept: sorted(samples) }
+    }
+}
+
+
+/// Estrategia registrada en un sensor cuyo valor fusiona agregados que no se pueden combinar
+/// exactamente (estrategias distintas, o `median`). El valor es el promedio ponderado por
+/// muestras de ambos agregados.
+pub const MERGED: &str = "merged";
+
+
+/// Indica si la estrategia devuelve el promedio de las muestras que conserva. Dos agregados
+/// suyos se fusionan exactamente ponderando por las muestras conservadas.
+pub fn is_mean_based(name: &str) -> bool {
+    let base = name.split_once(':').map_or(name, |(base, _)| base);
+    matches!(base, "iqr_mean" | "trimmed_mean" | "hampel")
+}
+
+
+/// Interpreta una estrategia con sus parámetros opcionales, ej. `trimmed_mean:0.2`.
+pub fn parse_aggregator(spec: &str) -> Result<SharedAggregator, String> {
+    let (name, param) = match spec.trim().split_once(':') {
+        Some((name, param)) => (name.trim(), Some(param.trim())),
+        None => (spec.trim(), None),
+    };
+    let number = |default: f32| -> Result<f32, String> {
+        param.map_or(Ok(default), |p| p.parse().map_err(|_| format!("parámetro inválido en {spec}")))
+    };
+    let no_param = || match param {
+        Some(_) => Err(format!("{name} no admite parámetros")),
+        None => Ok(()),
+    };
+
+    match name {
+        "iqr_mean" => no_param().map(|_| Arc::new(IqrMean) as SharedAggregator),
+        "median" => no_param().map(|_| Arc::new(Median) as SharedAggregator),
+        "last_value" => no_param().map(|_| Arc::new(LastValue) as SharedAggregator),
+        "trimmed_mean" => {
+            let fraction = number(0.1)?;
+            if !(0.0..0.5).contains(&fraction) {
+                return Err(format!("la fracción de trimmed_mean debe estar en [0, 0.5): {fraction}"));
+            }
+            Ok(Arc::new(TrimmedMean { fraction }))
+        }
+        "hampel" => {
+            let k = number(3.0)?;
+            if !(k.is_finite() && k > 0.0) {
+                return Err(format!("k de hampel debe ser un número finito mayor a cero: {k}"));
+            }
+            Ok(Arc::new(Hampel { k }))
+        }
+        other => Err(format!("estrategia de agregación desconocida: {other}")),
+    }
+}
+
+
+/// Sensores que se agregan por bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sensor {
+    Temperature,
+    Humidity,
+    Co2Ppm,
+}
+
+
+impl Sensor {
+    fn from_config(value: &str) -> Result<Self, String> {
+        match value {
+            "temperature" => Ok(Sensor::Temperature),
+            "humidity" => Ok(Sensor::Humidity),
+            "co2_ppm" => Ok(Sensor::Co2Ppm),
+            other => Err(format!("sensor desconocido: {other}")),
+        }
+    }
+}
+
+
+/// Estrategias resueltas para los tres sensores de una red.
+#[derive(Debug, Clone)]
+pub struct SensorAggregators {
+    pub temperature: SharedAggregator,
+    pub humidity: SharedAggregator,
+    pub co2_ppm: SharedAggregator,
+}
+
+
+/// Alcance de una excepción: red y/o sensor (`None` equivale a `*`).
+type Scope = (Option<String>, Option<Sensor>);
+
+
+/// Estrategia por defecto con excepciones por red y por sensor.
+#[derive(Debug, Clone)]
+pub struct AggregationSettings {
+    default: SharedAggregator,
+    overrides: HashMap<Scope, SharedAggregator>,
+}
+
+
+impl AggregationSettings {
+
+    /// Crea la configuración a partir de la estrategia global y la lista de excepciones
+    /// (`AGGREGATION_OVERRIDES`).
+    ///
+    /// Cada excepción tiene la forma `red.sensor=estrategia`, separadas por comas; `*` vale
+    /// para cualquier red o sensor. Ej. `*.co2_ppm=median,camara_fria.temperature=hampel:2.5,lab_b.*=last_value`.
+    pub fn new(default: &str, overrides: Option<&str>) -> Result<Self, String> {
+        let default = parse_aggregator(default)?;
+
+        let mut parsed = HashMap::new();
+        for item in overrides.unwrap_or_default().split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (scope, spec) = item.split_once('=')
+                .ok_or_else(|| format!("excepción sin '=': {item}"))?;
+            let (network, sensor) = scope.trim().rsplit_once('.')
+                .ok_or_else(|| format!("se esperaba red.sensor en {item}"))?;
+            let network = match network {
+                "*" => None,
+                "" => return Err(format!("excepción sin red: {item}")),
+                network => Some(network.to_string()),
+            };
+            let sensor = match sensor {
+                "*" => None,
+                sensor => Some(Sensor::from_config(sensor)?),
+            };
+            parsed.insert((network, sensor), parse_aggregator(spec)?);
+        }
+
+        Ok(Self { default, overrides: parsed })
+    }
+
+    /// Estrategia de un sensor en una red. Precedencia: `red.sensor`, `red.*`, `*.sensor`, global.
+    pub fn for_sensor(&self, network_id: &str, sensor: Sensor) -> SharedAggregator {
+        let network = Some(network_id.to_string());
+        [(network.clone(), Some(sensor)), (network, None), (None, Some(sensor))]
+            .iter()
+            .find_map(|scope| self.overrides.get(scope))
+            .unwrap_or(&self.default)
+            .clone()
+    }
+
+    /// Estrategias de los tres sensores de una red.
+    pub fn for_network(&self, network_id: &str) -> SensorAggregators {
+        SensorAggregators {
+            temperature: self.for_sensor(network_id, Sensor::Temperature),
+            humidity: self.for_sensor(network_id, Sensor::Humidity),
+            co2_ppm: self.for_sensor(network_id, Sensor::Co2Ppm),
+        }
+    }
+}
+
+
+fn mean(samples: &[f32]) -> Option<f32> {
+    if samples.is_empty() {
+        return None;
+    }
+    Some(samples.iter().sum::<f32>() / samples.len() as f32)
+}
+
+
+fn median(samples: &[f32]) -> Option<f32> {
+    if samples.is_empty() {
+        return None;
+    }
+    Some(percentile(&sorted(samples), 50.0))
+}
+
+
+fn sorted(samples: &[f32]) -> Vec<f32> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+    sorted
+}
+
+
+/// Percentil con interpolación lineal sobre muestras ordenadas.
+fn percentile(sorted: &[f32], percentile: f32) -> f32 {
+    let index = (percentile / 100.0) * (sorted.len() as f32 - 1.0);
+    let lower = index.floor() as usize;
+    let upper = index.ceil() as usize;
+
+    if lower == upper {
+        sorted[lower]
+    } else {
+        let weight = index - lower as f32;
+        sorted[lower] * (1.0 - weight) + sorted[upper] * weight
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(aggregator: &dyn Aggregator, samples: &[f32]) -> Option<f32> {
+        aggregator.aggregate(samples).value
+    }
+
+    fn assert_close(actual: Option<f32>, expected: f32) {
+        let actual = actual.expect("sin valor agregado");
+        assert!((actual - expected).abs() < 1e-4, "esperado {expected}, obtenido {actual}");
+    }
+
+    #[test]
+    fn empty_samples_have_no_value() {
+        let strategies: [SharedAggregator; 5] = [
+            Arc::new(IqrMean), Arc::new(Median), Arc::new(TrimmedMean { fraction: 0.1 }),
+            Arc::new(Hampel { k: 3.0 }), Arc::new(LastValue),
+        ];
+        for strategy in strategies {
+            assert_eq!(strategy.aggregate(&[]), Aggregate { value: None, kept: Vec::new() }, "{}", strategy.name());
+        }
+    }
+
+    #[test]
+    fn iqr_mean_averages_small_sets_and_drops_outliers() {
+        assert_close(value(&IqrMean, &[20.0]), 20.0);
+        assert_close(value(&IqrMean, &[20.0, 22.0]), 21.0);
+        assert_close(value(&IqrMean, &[20.0, 21.0, 90.0]), 131.0 / 3.0);
+
+        let aggregate = IqrMean.aggregate(&[20.0, 21.0, 22.0, 21.0, 90.0]);
+        assert_eq!(aggregate.kept, vec![20.0, 21.0, 21.0, 22.0]);
+        assert_close(aggregate.value, 21.0);
+    }
+
+    #[test]
+    fn median_interpolates_even_sizes() {
+        assert_close(value(&Median, &[20.0]), 20.0);
+        assert_close(value(&Median, &[22.0, 20.0]), 21.0);
+        assert_close(value(&Median, &[30.0, 20.0, 21.0]), 21.0);
+    }
+
+    #[test]
+    fn trimmed_mean_drops_each_end() {
+        let trimmed = TrimmedMean { fraction: 0.25 };
+        assert_close(value(&trimmed, &[20.0]), 20.0);
+        assert_close(value(&trimmed, &[20.0, 22.0]), 21.0);
+        assert_close(value(&trimmed, &[20.0, 21.0, 90.0]), 131.0 / 3.0);
+
+        let aggregate = trimmed.aggregate(&[1.0, 20.0, 22.0, 90.0]);
+        assert_eq!(aggregate.kept, vec![20.0, 22.0]);
+        assert_close(aggregate.value, 21.0);
+    }
+
+    #[test]
+    fn trimmed_mean_near_half_keeps_the_middle() {
+        let trimmed = TrimmedMean { fraction: 0.499_999_97 };
+        assert_close(value(&trimmed, &[1.0, 2.0]), 1.5);
+        assert_close(value(&trimmed, &[1.0, 2.0, 90.0]), 2.0);
+
+        let samples: Vec<f32> = (0..10_000).map(|x| x as f32).collect();
+        let aggregate = trimmed.aggregate(&samples);
+        assert!(!aggregate.kept.is_empty());
+        assert_close(aggregate.value, 4999.5);
+    }
+
+    #[test]
+    fn hampel_drops_samples_far_from_the_median() {
+        let hampel = Hampel { k: 3.0 };
+        assert_close(value(&hampel, &[20.0]), 20.0);
+        assert_close(value(&hampel, &[20.0, 22.0]), 21.0);
+        assert_close(value(&hampel, &[20.0, 21.0, 22.0]), 21.0);
+
+        let aggregate = hampel.aggregate(&[20.0, 21.0, 22.0, 21.0, 90.0]);
+        assert_eq!(aggregate.kept, vec![20.0, 21.0, 21.0, 22.0]);
+        assert_close(aggregate.value, 21.0);
+    }
+
+    #[test]
+    fn hampel_uses_the_mean_deviation_when_mad_is_zero() {
+        let hampel = Hampel { k: 3.0 };
+        let aggregate = hampel.aggregate(&[20.0, 20.0, 20.0, 90.0]);
+        assert_eq!(aggregate.kept, vec![20.0, 20.0, 20.0]);
+        assert_close(aggregate.value, 20.0);
+
+        let aggregate = hampel.aggregate(&[20.0, 20.0, 20.0, 20.0, 20.0, 21.0, 21.0]);
+        assert_eq!(aggregate.kept.len(), 7);
+        assert_close(aggregate.value, 142.0 / 7.0);
+
+        assert_eq!(hampel.aggregate(&[20.0, 20.0, 20.0]).kept, vec![20.0, 20.0, 20.0]);
+    }
+
+    #[test]
+    fn last_value_takes_the_newest_sample() {
+        assert_close(value(&LastValue, &[20.0]), 20.0);
+        assert_close(value(&LastValue, &[22.0, 20.0]), 20.0);
+        let aggregate = LastValue.aggregate(&[22.0, 20.0, 21.0]);
+        assert_close(aggregate.value, 21.0);
+        assert_eq!(aggregate.kept, vec![20.0, 21.0, 22.0]);
+    }
+
+    #[test]
+    fn parses_names_and_parameters() {
+        for (spec, name) in [
+            ("iqr_mean", "iqr_mean"), (" median ", "median"), ("last_value", "last_value"),
+            ("trimmed_mean", "trimmed_mean:0.1"), ("trimmed_mean: 0.2", "trimmed_mean:0.2"),
+            ("hampel", "hampel:3"), ("hampel:2.5", "hampel:2.5"),
+        ] {
+            assert_eq!(parse_aggregator(spec).map(|a| a.name()), Ok(name.to_string()), "{spec}");
+        }
+    }
+
+    #[test]
+    fn only_averaging_strategies_are_mean_based() {
+        for name in ["iqr_mean", "trimmed_mean:0.1", "hampel:3"] {
+            assert!(is_mean_based(name), "{name}");
+        }
+        for name in ["median", "last_value", MERGED] {
+            assert!(!is_mean_based(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        for spec in ["", "mean", "median:1", "trimmed_mean:0.5", "trimmed_mean:-0.1", "trimmed_mean:x",
+                     "hampel:0", "hampel:-1", "hampel:NaN", "hampel:inf"] {
+            assert!(parse_aggregator(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn overrides_follow_their_precedence() {
+        let settings = AggregationSettings::new(
+            "iqr_mean",
+            Some("*.co2_ppm=median, lab.*=last_value, lab.temperature=hampel:2"),
+        ).unwrap();
+
+        let name = |network: &str, sensor| settings.for_sensor(network, sensor).name();
+        assert_eq!(name("lab", Sensor::Temperature), "hampel:2");
+        assert_eq!(name("lab", Sensor::Co2Ppm), "last_value");
+        assert_eq!(name("lab", Sensor::Humidity), "last_value");
+        assert_eq!(name("otra", Sensor::Co2Ppm), "median");
+        assert_eq!(name("otra", Sensor::Humidity), "iqr_mean");
+    }
+
+    #[test]
+    fn rejects_malformed_overrides() {
+        for overrides in ["lab.temperature", "temperature=median", ".temperature=median", "lab.presion=median"] {
+            assert!(AggregationSettings::new("iqr_mean", Some(overrides)).is_err(), "{overrides}");
+        }
+    }
+}
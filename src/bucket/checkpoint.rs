@@ -0,0 +1,132 @@
+//! Checkpoint en disco de los buckets abiertos.
+//!
+//! `bucket_map` vive en memoria: sin checkpoint, un reinicio pierde las mediciones de las
+//! ventanas todavía abiertas. El sweeper escribe, tras cerrar los buckets vencidos, una
+//! instantánea de los buckets abiertos y de los watermarks de cada red en
+//! `BUCKET_CHECKPOINT_FILE`: en el mismo tick si se cerró algún bucket y, si solo llegaron
+//! muestras, como mucho una vez cada `BUCKET_CHECKPOINT_INTERVAL_SECS`. Nunca reescribe una
+//! instantánea sin cambios. Al arrancar, `start_bucket` la restaura antes de lanzar
+//! `bucket_task`, y los buckets restaurados se cierran según sus timestamps originales.
+//!
+//! La escritura es atómica (archivo temporal + `rename`): un corte durante la escritura deja
+//! el checkpoint anterior intacto. Ante un crash se pierden como mucho las mediciones
+//! recibidas en el último intervalo.
+
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::context::domain::{BucketKey, StateMap, WatermarkMap};
+use crate::message::domain::Measurement;
+
+
+/// Bucket abierto al momento del checkpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CheckpointBucket {
+    network_id: String,
+    bucket_ts: i64,
+    window_secs: i64,
+    measurements: Vec<Measurement>,
+}
+
+
+/// Watermark de una red al momento del checkpoint. `last_seen` no se guarda: el plazo de
+/// inactividad vuelve a contar desde el arranque.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CheckpointWatermark {
+    network_id: String,
+    max_event_ts: i64,
+    closed_until: i64,
+}
+
+
+/// Instantánea de `bucket_map` y de los watermarks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BucketCheckpoint {
+    buckets: Vec<CheckpointBucket>,
+    watermarks: Vec<CheckpointWatermark>,
+}
+
+
+impl BucketCheckpoint {
+
+    /// Copia el estado actual. Los watermarks se copian antes que los buckets, igual que en el
+    /// sweeper, para no invertir el orden de locks de `add_to_bucket`.
+    pub fn capture(bucket_map: &StateMap, watermarks: &WatermarkMap) -> Self {
+        let watermarks = watermarks.iter()
+            .map(|entry| CheckpointWatermark {
+                network_id: entry.key().clone(),
+                max_event_ts: entry.max_event_ts,
+                closed_until: entry.closed_until,
+            })
+            .collect();
+
+        let buckets = bucket_map.iter()
+            .map(|entry| {
+                let (network_id, bucket_ts, window_secs) = entry.key().clone();
+                CheckpointBucket { network_id, bucket_ts, window_secs, measurements: entry.value().clone() }
+            })
+            .collect();
+
+        Self { buckets, watermarks }
+    }
+
+    /// Cantidad de mediciones contenidas.
+    pub fn samples(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.measurements.len()).sum()
+    }
+
+    /// Escribe el checkpoint de forma atómica.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = tmp_path(path);
+        let mut file = File::create(&tmp)?;
+        serde_json::to_writer(&mut file, self)?;
+        file.flush()?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Lee el checkpoint. `Ok(None)` si todavía no existe.
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("no se pudo leer {}: {e}", path.display())),
+        };
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| format!("{} no es válido: {e}", path.display()))
+    }
+
+    /// Vuelca el checkpoint en `bucket_map` y en los watermarks.
+    ///
+    /// # Retorno
+    /// Cantidad de buckets restaurados.
+    pub fn restore(self, bucket_map: &StateMap, watermarks: &WatermarkMap) -> usize {
+        for saved in self.watermarks {
+            let mut watermark = watermarks.entry(saved.network_id).or_default();
+            watermark.max_event_ts = watermark.max_event_ts.max(saved.max_event_ts);
+            watermark.closed_until = watermark.closed_until.max(saved.closed_until);
+        }
+
+        let restored = self.buckets.len();
+        for bucket in self.buckets {
+            // Sin watermark guardado la red no se cerraría nunca por tiempo de evento
+            watermarks.entry(bucket.network_id.clone()).or_default();
+            let key: BucketKey = (bucket.network_id, bucket.bucket_ts, bucket.window_secs);
+            bucket_map.entry(key).or_default().extend(bucket.measurements);
+        }
+        restored
+    }
+}
+
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
@@ -0,0 +1,461 @@
+//! Configuración de las ventanas de agregación (buckets).
+//!
+//! Cada red (`network_id`) agrupa sus mediciones en ventanas de `window_secs` alineadas a
+//! la época Unix, de modo que el inicio de un bucket no depende del momento de arranque ni
+//! de la primera medición recibida.
+//!
+//! # Watermarks
+//! Los buckets se cierran por tiempo de evento (el timestamp del Edge), no por el reloj del
+//! Data Saver. Cada red lleva un [`Watermark`]: el mayor timestamp recibido menos la demora
+//! tolerada (`lateness_secs`). Un bucket se cierra cuando el watermark supera el fin de su
+//! ventana; el sweeper lo revisa cada `sweep_secs`. Si una red deja de enviar durante
+//! `idle_secs` (reloj local) se cierran todos sus buckets abiertos.
+//!
+//! Una muestra cuyo bucket ya se cerró es tardía y se trata según [`LatePolicy`].
+//!
+//! # Límites
+//! [`BucketLimits`] acota la memoria de `bucket_map`: rechaza timestamps demasiado lejos del
+//! reloj local y, al superar los buckets abiertos por red o las muestras totales, fuerza el
+//! cierre de los buckets más antiguos o rechaza la muestra según [`OverflowPolicy`].
+//!
+//! También define los rangos plausibles de cada sensor: las muestras fuera de rango se
+//! descartan antes de agregar el bucket.
+
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+
+/// Parámetros de agregación de una red.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketConfig {
+    /// Tamaño de la ventana, en segundos.
+    pub window_secs: i64,
+    /// Demora tolerada: el watermark queda esta cantidad de segundos detrás del mayor
+    /// timestamp recibido.
+    pub lateness_secs: i64,
+    /// Cada cuántos segundos el sweeper revisa los buckets de la red.
+    pub sweep_secs: u64,
+}
+
+
+impl BucketConfig {
+
+    /// Inicio del bucket que contiene `timestamp`.
+    pub fn align(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.window_secs)
+    }
+
+    /// Fin (exclusivo) del bucket que empieza en `bucket_ts`.
+    pub fn bucket_end(&self, bucket_ts: i64) -> i64 {
+        bucket_ts + self.window_secs
+    }
+
+
+    fn validate(&self) -> Result<(), String> {
+        if self.window_secs <= 0 {
+            return Err(format!("la ventana debe ser mayor a cero: {}", self.window_secs));
+        }
+        if self.lateness_secs < 0 {
+            return Err(format!("la demora tolerada no puede ser negativa: {}", self.lateness_secs));
+        }
+        if self.sweep_secs == 0 {
+            return Err("el intervalo del sweeper debe ser mayor a cero".to_string());
+        }
+        Ok(())
+    }
+}
+
+
+/// Configuración global de buckets con excepciones por red.
+#[derive(Debug, Clone)]
+pub struct BucketSettings {
+    /// Valores para las redes sin excepción.
+    pub default: BucketConfig,
+    /// Excepciones por `network_id`.
+    pub networks: HashMap<String, BucketConfig>,
+    /// Segundos sin recibir muestras de una red tras los cuales se cierran sus buckets.
+    pub idle_secs: u64,
+}
+
+
+impl BucketSettings {
+
+    /// Crea la configuración a partir de los valores globales y la lista de excepciones
+    /// (`BUCKET_NETWORK_OVERRIDES`).
+    ///
+    /// Cada excepción tiene la forma `red=ventana:demora[:sweep]`, separadas por comas,
+    /// ej. `sala_a=10:5:2,archivo=300:60`. Si se omite `sweep` se usa el global.
+    pub fn new(default: BucketConfig, idle_secs: u64, overrides: Option<&str>) -> Result<Self, String> {
+        default.validate()?;
+        if idle_secs == 0 {
+            return Err("el plazo de inactividad debe ser mayor a cero".to_string());
+        }
+
+        let mut networks = HashMap::new();
+        for item in overrides.unwrap_or_default().split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (network, values) = item.split_once('=')
+                .ok_or_else(|| format!("excepción sin '=': {item}"))?;
+            let network = network.trim();
+            if network.is_empty() {
+                return Err(format!("excepción sin red: {item}"));
+            }
+
+            let values: Vec<&str> = values.split(':').map(str::trim).collect();
+            if values.len() < 2 || values.len() > 3 {
+                return Err(format!("se esperaba ventana:demora[:sweep] en {item}"));
+            }
+            let config = BucketConfig {
+                window_secs: values[0].parse().map_err(|_| format!("ventana inválida en {item}"))?,
+                lateness_secs: values[1].parse().map_err(|_| format!("demora inválida en {item}"))?,
+                sweep_secs: match values.get(2) {
+                    Some(sweep) => sweep.parse().map_err(|_| format!("sweep inválido en {item}"))?,
+                    None => default.sweep_secs,
+                },
+            };
+            config.validate().map_err(|e| format!("{network}: {e}"))?;
+            networks.insert(network.to_string(), config);
+        }
+
+        Ok(Self { default, networks, idle_secs })
+    }
+
+    /// Parámetros que aplican a una red.
+    pub fn for_network(&self, network_id: &str) -> BucketConfig {
+        self.networks.get(network_id).copied().unwrap_or(self.default)
+    }
+
+    /// Período del temporizador del sweeper: el menor `sweep_secs` configurado.
+    pub fn tick_secs(&self) -> u64 {
+        self.networks.values()
+            .map(|config| config.sweep_secs)
+            .fold(self.default.sweep_secs, u64::min)
+    }
+}
+
+
+/// Progreso del tiempo de evento de una red.
+#[derive(Debug, Clone, Copy)]
+pub struct Watermark {
+    /// Mayor timestamp recibido, acotado al reloj local para que un Edge adelantado no
+    /// cierre buckets antes de tiempo.
+    pub max_event_ts: i64,
+    /// Momento (reloj local) de la última muestra recibida.
+    pub last_seen: Instant,
+    /// Fin del último bucket cerrado: las muestras de ventanas que terminan antes son tardías.
+    pub closed_until: i64,
+}
+
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Self { max_event_ts: i64::MIN, last_seen: Instant::now(), closed_until: i64::MIN }
+    }
+}
+
+
+impl Watermark {
+
+    /// Registra una muestra con timestamp `event_ts` recibida en el instante `now` (Unix).
+    pub fn observe(&mut self, event_ts: i64, now: i64) {
+        self.max_event_ts = self.max_event_ts.max(event_ts.min(now));
+        self.last_seen = Instant::now();
+    }
+
+    /// Watermark vigente: hasta dónde se pueden cerrar buckets. Si la red está inactiva
+    /// desde hace `idle_secs` se pueden cerrar todos.
+    pub fn current(&self, config: &BucketConfig, idle_secs: u64) -> i64 {
+        if self.last_seen.elapsed() >= Duration::from_secs(idle_secs) {
+            i64::MAX
+        } else {
+            self.max_event_ts.saturating_sub(config.lateness_secs)
+        }
+    }
+
+    /// Indica si el bucket que termina en `bucket_end` ya se cerró.
+    pub fn is_late(&self, bucket_end: i64) -> bool {
+        bucket_end <= self.closed_until
+    }
+}
+
+
+/// Tratamiento de las muestras que llegan después de cerrado su bucket (`LATE_DATA_POLICY`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LatePolicy {
+    /// Se guardan crudas en `late_measurement` y se confirman al Edge.
+    Table,
+    /// Se descartan, se cuentan y se informan al Edge con un Nack.
+    Drop,
+}
+
+
+impl LatePolicy {
+    pub fn from_config(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "table" => Ok(LatePolicy::Table),
+            "drop" => Ok(LatePolicy::Drop),
+            other => Err(format!("política de datos tardíos desconocida: {other}")),
+        }
+    }
+}
+
+
+/// Qué hacer con una muestra nueva cuando `bucket_map` está lleno (`BUCKET_OVERFLOW_POLICY`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    /// Se cierran y emiten los buckets más antiguos para hacer lugar.
+    ForceClose,
+    /// Se rechaza la muestra con un Nack.
+    Reject,
+}
+
+
+impl OverflowPolicy {
+    pub fn from_config(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "force_close" => Ok(OverflowPolicy::ForceClose),
+            "reject" => Ok(OverflowPolicy::Reject),
+            other => Err(format!("política de desborde de buckets desconocida: {other}")),
+        }
+    }
+}
+
+
+/// Cotas de ocupación de `bucket_map`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketLimits {
+    /// Buckets abiertos por red.
+    pub max_open_per_network: usize,
+    /// Muestras en todos los buckets abiertos.
+    pub max_samples: usize,
+    /// Segundos que un timestamp puede estar adelantado respecto del reloj local.
+    pub max_future_secs: i64,
+    /// Segundos que un timestamp puede estar atrasado respecto del reloj local.
+    pub max_past_secs: i64,
+    pub overflow: OverflowPolicy,
+}
+
+
+impl BucketLimits {
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_open_per_network == 0 || self.max_samples == 0 {
+            return Err("los límites de buckets y muestras deben ser mayores a cero".to_string());
+        }
+        if self.max_future_secs < 0 || self.max_past_secs < 0 {
+            return Err("los márgenes de timestamp no pueden ser negativos".to_string());
+        }
+        Ok(())
+    }
+
+    /// Muestras a las que se baja al forzar el cierre por `max_samples`: se libera un 10 % de
+    /// margen de una vez, para no recorrer `bucket_map` con cada muestra que llega al tope.
+    pub fn eviction_target(&self) -> usize {
+        self.max_samples - self.max_samples / 10
+    }
+
+    /// Motivo de rechazo si `event_ts` está fuera del margen aceptado alrededor de `now`.
+    pub fn check_timestamp(&self, event_ts: i64, now: i64) -> Option<&'static str> {
+        if event_ts > now.saturating_add(self.max_future_secs) {
+            Some("future")
+        } else if event_ts < now.saturating_sub(self.max_past_secs) {
+            Some("past")
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Intervalo de valores plausibles de un sensor (extremos incluidos).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Range {
+    pub min: f32,
+    pub max: f32,
+}
+
+
+impl Range {
+    pub fn contains(&self, value: f32) -> bool {
+        value >= self.min && value <= self.max
+    }
+}
+
+
+/// Rangos plausibles de los tres sensores que se agregan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SensorRanges {
+    pub temperature: Range,
+    pub humidity: Range,
+    pub co2_ppm: Range,
+}
+
+
+impl Default for SensorRanges {
+    /// Rangos usados antes de que fueran configurables.
+    fn default() -> Self {
+        Self {
+            temperature: Range { min: 10.0, max: 35.0 },
+            humidity: Range { min: 20.0, max: 90.0 },
+            co2_ppm: Range { min: 400.0, max: 1800.0 },
+        }
+    }
+}
+
+
+/// Rangos tal como aparecen en el archivo: cada sensor es opcional y hereda el valor
+/// del nivel superior (red -> `default` -> valores históricos).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PartialRanges {
+    temperature: Option<Range>,
+    humidity: Option<Range>,
+    co2_ppm: Option<Range>,
+}
+
+
+impl PartialRanges {
+    fn over(&self, base: SensorRanges) -> SensorRanges {
+        SensorRanges {
+            temperature: self.temperature.unwrap_or(base.temperature),
+            humidity: self.humidity.unwrap_or(base.humidity),
+            co2_ppm: self.co2_ppm.unwrap_or(base.co2_ppm),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (name, range) in [("temperature", self.temperature), ("humidity", self.humidity), ("co2_ppm", self.co2_ppm)] {
+            if let Some(range) = range
+                && range.min > range.max {
+                return Err(format!("{name}: min ({}) mayor que max ({})", range.min, range.max));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Contenido del archivo `PLAUSIBILITY_CONFIG`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlausibilityFile {
+    #[serde(default)]
+    default: PartialRanges,
+    #[serde(default)]
+    networks: HashMap<String, PartialRanges>,
+}
+
+
+/// Rangos plausibles vigentes: globales y por red.
+#[derive(Debug, Clone, Default)]
+pub struct PlausibilityRanges {
+    default: SensorRanges,
+    networks: HashMap<String, SensorRanges>,
+}
+
+
+impl PlausibilityRanges {
+
+    fn from_file(file: PlausibilityFile) -> Result<Self, String> {
+        file.default.validate().map_err(|e| format!("default: {e}"))?;
+        let default = file.default.over(SensorRanges::default());
+
+        let mut networks = HashMap::new();
+        for (network, ranges) in file.networks {
+            ranges.validate().map_err(|e| format!("{network}: {e}"))?;
+            networks.insert(network, ranges.over(default));
+        }
+        Ok(Self { default, networks })
+    }
+
+    /// Rangos que aplican a una red.
+    pub fn for_network(&self, network_id: &str) -> SensorRanges {
+        self.networks.get(network_id).copied().unwrap_or(self.default)
+    }
+}
+
+
+/// Rangos vigentes y fecha de modificación del archivo del que se leyeron.
+#[derive(Debug)]
+struct RangesState {
+    ranges: Arc<PlausibilityRanges>,
+    modified: Option<SystemTime>,
+}
+
+
+/// Fuente de los rangos plausibles, recargable en caliente.
+///
+/// Si los rangos provienen de `PLAUSIBILITY_CONFIG`, cada consulta compara la fecha de
+/// modificación del archivo y lo relee si cambió, por lo que los nuevos rangos se aplican
+/// en el siguiente tick del sweeper sin reiniciar el servicio.
+#[derive(Debug)]
+pub struct PlausibilityStore {
+    path: Option<PathBuf>,
+    state: Mutex<RangesState>,
+}
+
+
+impl PlausibilityStore {
+
+    /// Carga los rangos iniciales. Sin archivo se usan los valores históricos.
+    ///
+    /// # Retorno
+    /// `Err` si el archivo no existe o es inválido: al arrancar no se adivinan los rangos.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let path = path.map(PathBuf::from);
+        let state = match &path {
+            Some(path) => {
+                let state = read_ranges_file(path)?;
+                info!("Info: rangos plausibles cargados desde {}", path.display());
+                state
+            }
+            None => RangesState { ranges: Arc::new(PlausibilityRanges::default()), modified: None },
+        };
+        Ok(Self { path, state: Mutex::new(state) })
+    }
+
+    /// Rangos vigentes, releyendo el archivo si cambió.
+    ///
+    /// Si la relectura falla (ej. JSON a medio escribir) se conservan los rangos anteriores.
+    pub fn current(&self) -> Arc<PlausibilityRanges> {
+        let mut state = match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(path) = &self.path {
+            let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+            if modified.is_some() && modified != state.modified {
+                match read_ranges_file(path) {
+                    Ok(new_state) => {
+                        info!("Info: rangos plausibles recargados desde {}", path.display());
+                        *state = new_state;
+                    }
+                    Err(e) => {
+                        warn!("Warning: no se pudieron recargar los rangos plausibles; se conservan los anteriores. {}", e);
+                        // Evita reintentar (y advertir) en cada tick hasta que el archivo vuelva a cambiar
+                        state.modified = modified;
+                    }
+                }
+            }
+        }
+        state.ranges.clone()
+    }
+}
+
+
+fn read_ranges_file(path: &PathBuf) -> Result<RangesState, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("no se pudo leer {}: {e}", path.display()))?;
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    let file: PlausibilityFile = serde_json::from_str(&content)
+        .map_err(|e| format!("{} no es válido: {e}", path.display()))?;
+    let ranges = PlausibilityRanges::from_file(file)
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(RangesState { ranges: Arc::new(ranges), modified })
+}
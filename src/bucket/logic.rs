@@ -1,10 +1,29 @@
 use tokio::sync::mpsc;
-use tokio::time::{interval, Duration};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use std::collections::{HashMap, HashSet};
+use tokio::time::{interval, Duration, Instant};
 use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::FromRow;
-use tracing::{error, info};
-use crate::context::domain::{AppContext, BucketKey};
-use crate::message::domain::Measurement;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use tracing::{debug, error, info, warn};
+use crate::bucket::aggregation::{is_mean_based, Aggregator, LastValue, Sensor, SensorAggregators, MERGED};
+use crate::bucket::checkpoint::BucketCheckpoint;
+use crate::bucket::domain::{LatePolicy, OverflowPolicy, PlausibilityRanges, SensorRanges, Watermark};
+use crate::context::domain::{AppContext, BucketKey, SensorDataVector};
+use crate::message::domain::{Delivery, Measurement};
+use crate::supervisor::domain::shared;
+use crate::supervisor::logic::supervise;
+
+
+/// Nombre de `bucket_task` en el supervisor; el sweeper espera su fin al apagar.
+const BUCKET_TASK: &str = "bucket_task";
+
+/// Nombre del sweeper en el supervisor; `/readyz` verifica que su tick sea reciente.
+pub const SWEEPER_TASK: &str = "sweeper_task";
 
 
 pub enum BucketData {
@@ -21,7 +40,7 @@ pub struct ToProcess {
 }
 
 
-#[derive(Default, Debug, Clone, FromRow, PartialEq)]
+#[derive(Default, Debug, Clone, FromRow, PartialEq, Serialize, Deserialize)]
 pub struct ProcessedTelemetry {
     #[sqlx(flatten)]
     pub network_id: String,
@@ -31,207 +50,984 @@ pub struct ProcessedTelemetry {
     pub co2_ppm: Option<f32>,
     pub pulse_counter_total: i64,
     pub pulse_max_duration: i64,
+    /// Tamaño de la ventana agregada, en segundos. Permite interpretar filas de redes con
+    /// distinta resolución.
+    pub window_secs: i64,
+    /// Muestras descartadas al agregar, por sensor y motivo.
+    #[sqlx(flatten)]
+    pub rejected: RejectedSamples,
+    /// Dispersión y cobertura de cada sensor en la ventana.
+    #[sqlx(flatten)]
+    pub stats: BucketStats,
+    /// Estrategia de agregación aplicada a cada sensor.
+    #[sqlx(flatten)]
+    pub strategies: AggregationStrategies,
+    /// Hubs (`sender_user_id`) que aportaron muestras a la ventana, ordenados.
+    pub senders: Vec<String>,
+    /// Destinos (`destination_id`) de esas muestras, ordenados.
+    pub destinations: Vec<String>,
+    /// Identificador de este agregado, derivado de su clave y de sus muestras (ver
+    /// [`aggregate_id`]). Permite que un reintento de la misma inserción, o un bucket que se
+    /// vuelve a emitir tras restaurar un checkpoint, no se fusione dos veces con la fila existente.
+    #[sqlx(skip)]
+    pub aggregate_id: String,
+    /// Entregas del Edge agregadas en este bucket, a confirmar tras la inserción.
+    #[sqlx(skip)]
+    pub deliveries: Vec<Delivery>,
+}
+
+
+/// Muestras descartadas de un bucket, por sensor y motivo.
+///
+/// * `*_out_of_range`: fuera del rango plausible configurado para la red.
+/// * `*_outliers`: dentro del rango, pero descartadas por la estrategia de agregación.
+/// * `duplicate_samples`: mediciones repetidas (mismo `sender_user_id` y `sample`), descartadas
+///   antes de agregar.
+#[derive(Default, Debug, Clone, FromRow, PartialEq, Serialize, Deserialize)]
+pub struct RejectedSamples {
+    pub temperature_out_of_range: i32,
+    pub temperature_outliers: i32,
+    pub humidity_out_of_range: i32,
+    pub humidity_outliers: i32,
+    pub co2_out_of_range: i32,
+    pub co2_outliers: i32,
+    pub duplicate_samples: i32,
+}
+
+
+/// Dispersión y cobertura de cada sensor en un bucket.
+///
+/// * `*_min`, `*_max`, `*_stddev`: sobre las muestras que usó la estrategia de agregación
+///   (desvío estándar poblacional). `None` si no quedó ninguna.
+/// * `*_samples_raw`: muestras recibidas en la ventana, antes de filtrar.
+/// * `*_samples_kept`: muestras que quedaron tras los rangos plausibles y la estrategia.
+/// * `*_first_at`, `*_last_at`: timestamps (del Edge) de la primera y la última muestra cruda.
+#[derive(Default, Debug, Clone, FromRow, PartialEq, Serialize, Deserialize)]
+pub struct BucketStats {
+    pub temperature_min: Option<f32>,
+    pub temperature_max: Option<f32>,
+    pub temperature_stddev: Option<f32>,
+    pub temperature_samples_raw: i32,
+    pub temperature_samples_kept: i32,
+    pub temperature_first_at: Option<i64>,
+    pub temperature_last_at: Option<i64>,
+    pub humidity_min: Option<f32>,
+    pub humidity_max: Option<f32>,
+    pub humidity_stddev: Option<f32>,
+    pub humidity_samples_raw: i32,
+    pub humidity_samples_kept: i32,
+    pub humidity_first_at: Option<i64>,
+    pub humidity_last_at: Option<i64>,
+    pub co2_min: Option<f32>,
+    pub co2_max: Option<f32>,
+    pub co2_stddev: Option<f32>,
+    pub co2_samples_raw: i32,
+    pub co2_samples_kept: i32,
+    pub co2_first_at: Option<i64>,
+    pub co2_last_at: Option<i64>,
+}
+
+
+/// Nombre de la estrategia de agregación aplicada a cada sensor (ver [`Aggregator::name`]).
+#[derive(Default, Debug, Clone, FromRow, PartialEq, Serialize, Deserialize)]
+pub struct AggregationStrategies {
+    pub temperature_strategy: String,
+    pub humidity_strategy: String,
+    pub co2_strategy: String,
+}
+
+
+impl ProcessedTelemetry {
+
+    /// Fusiona otro agregado de la misma ventana (ej. un bucket reabierto tras un reinicio).
+    ///
+    /// Conteos y pulsos se suman; mínimos, máximos y la cobertura se combinan, y el desvío
+    /// estándar se combina como varianza agrupada. El valor de cada sensor depende de las
+    /// estrategias de ambos agregados:
+    /// * Misma estrategia basada en promedio (ver [`is_mean_based`]): promedio ponderado por las
+    ///   muestras conservadas, exacto. Se conserva la estrategia.
+    /// * Ambos `last_value`: gana el agregado con la muestra más reciente.
+    /// * Cualquier otro caso (`median`, estrategias distintas): promedio ponderado, que es solo
+    ///   una aproximación, y la estrategia pasa a ser [`MERGED`].
+    ///
+    /// Los hubs y destinos se unen; los duplicados entre ambos agregados no se detectan.
+    pub fn merge(&mut self, other: &ProcessedTelemetry) {
+        self.pulse_counter_total += other.pulse_counter_total;
+        self.pulse_max_duration = self.pulse_max_duration.max(other.pulse_max_duration);
+        self.rejected.duplicate_samples += other.rejected.duplicate_samples;
+        self.senders = distinct(self.senders.iter().chain(&other.senders).cloned());
+        self.destinations = distinct(self.destinations.iter().chain(&other.destinations).cloned());
+
+        for sensor in [Sensor::Temperature, Sensor::Humidity, Sensor::Co2Ppm] {
+            let mut merged = SensorSummary::of(self, sensor);
+            merged.merge(&SensorSummary::of(other, sensor));
+            merged.apply(self, sensor);
+        }
+    }
+}
+
+
+/// Valores sin repetir, ordenados.
+fn distinct(values: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut values: Vec<String> = values.into_iter().collect();
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
+
+/// Vista de los campos de un sensor de [`ProcessedTelemetry`], para fusionarlos.
+#[derive(Debug, Clone)]
+struct SensorSummary {
+    value: Option<f32>,
+    strategy: String,
+    min: Option<f32>,
+    max: Option<f32>,
+    stddev: Option<f32>,
+    samples_raw: i32,
+    samples_kept: i32,
+    first_at: Option<i64>,
+    last_at: Option<i64>,
+    out_of_range: i32,
+    outliers: i32,
+}
+
+
+impl SensorSummary {
+
+    fn of(t: &ProcessedTelemetry, sensor: Sensor) -> Self {
+        let (s, r) = (&t.stats, &t.rejected);
+        match sensor {
+            Sensor::Temperature => Self {
+                value: t.temperature,
+                strategy: t.strategies.temperature_strategy.clone(),
+                min: s.temperature_min,
+                max: s.temperature_max,
+                stddev: s.temperature_stddev,
+                samples_raw: s.temperature_samples_raw,
+                samples_kept: s.temperature_samples_kept,
+                first_at: s.temperature_first_at,
+                last_at: s.temperature_last_at,
+                out_of_range: r.temperature_out_of_range,
+                outliers: r.temperature_outliers,
+            },
+            Sensor::Humidity => Self {
+                value: t.humidity,
+                strategy: t.strategies.humidity_strategy.clone(),
+                min: s.humidity_min,
+                max: s.humidity_max,
+                stddev: s.humidity_stddev,
+                samples_raw: s.humidity_samples_raw,
+                samples_kept: s.humidity_samples_kept,
+                first_at: s.humidity_first_at,
+                last_at: s.humidity_last_at,
+                out_of_range: r.humidity_out_of_range,
+                outliers: r.humidity_outliers,
+            },
+            Sensor::Co2Ppm => Self {
+                value: t.co2_ppm,
+                strategy: t.strategies.co2_strategy.clone(),
+                min: s.co2_min,
+                max: s.co2_max,
+                stddev: s.co2_stddev,
+                samples_raw: s.co2_samples_raw,
+                samples_kept: s.co2_samples_kept,
+                first_at: s.co2_first_at,
+                last_at: s.co2_last_at,
+                out_of_range: r.co2_out_of_range,
+                outliers: r.co2_outliers,
+            },
+        }
+    }
+
+    fn apply(self, t: &mut ProcessedTelemetry, sensor: Sensor) {
+        let (s, r) = (&mut t.stats, &mut t.rejected);
+        match sensor {
+            Sensor::Temperature => {
+                t.temperature = self.value;
+                t.strategies.temperature_strategy = self.strategy;
+                s.temperature_min = self.min;
+                s.temperature_max = self.max;
+                s.temperature_stddev = self.stddev;
+                s.temperature_samples_raw = self.samples_raw;
+                s.temperature_samples_kept = self.samples_kept;
+                s.temperature_first_at = self.first_at;
+                s.temperature_last_at = self.last_at;
+                r.temperature_out_of_range = self.out_of_range;
+                r.temperature_outliers = self.outliers;
+            }
+            Sensor::Humidity => {
+                t.humidity = self.value;
+                t.strategies.humidity_strategy = self.strategy;
+                s.humidity_min = self.min;
+                s.humidity_max = self.max;
+                s.humidity_stddev = self.stddev;
+                s.humidity_samples_raw = self.samples_raw;
+                s.humidity_samples_kept = self.samples_kept;
+                s.humidity_first_at = self.first_at;
+                s.humidity_last_at = self.last_at;
+                r.humidity_out_of_range = self.out_of_range;
+                r.humidity_outliers = self.outliers;
+            }
+            Sensor::Co2Ppm => {
+                t.co2_ppm = self.value;
+                t.strategies.co2_strategy = self.strategy;
+                s.co2_min = self.min;
+                s.co2_max = self.max;
+                s.co2_stddev = self.stddev;
+                s.co2_samples_raw = self.samples_raw;
+                s.co2_samples_kept = self.samples_kept;
+                s.co2_first_at = self.first_at;
+                s.co2_last_at = self.last_at;
+                r.co2_out_of_range = self.out_of_range;
+                r.co2_outliers = self.outliers;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &SensorSummary) {
+        match (self.value, other.value) {
+            (_, None) => {}
+            (None, Some(_)) => {
+                self.value = other.value;
+                self.strategy = other.strategy.clone();
+                self.min = other.min;
+                self.max = other.max;
+                self.stddev = other.stddev;
+            }
+            (Some(v1), Some(v2)) => {
+                // Las filas anteriores a las estadísticas no registran muestras: pesan como una
+                let w1 = self.samples_kept.max(1) as f32;
+                let w2 = other.samples_kept.max(1) as f32;
+                let mean = (v1*w1 + v2*w2) / (w1 + w2);
+                let s1 = self.stddev.unwrap_or_default();
+                let s2 = other.stddev.unwrap_or_default();
+                let variance = (w1*(s1.powi(2) + (v1 - mean).powi(2)) + w2*(s2.powi(2) + (v2 - mean).powi(2))) / (w1 + w2);
+
+                let same = self.strategy == other.strategy;
+                self.value = if same && self.strategy == LastValue.name() {
+                    if other.last_at >= self.last_at { Some(v2) } else { Some(v1) }
+                } else {
+                    Some(mean)
+                };
+                if !(same && (is_mean_based(&self.strategy) || self.strategy == LastValue.name())) {
+                    self.strategy = MERGED.to_string();
+                }
+                self.min = [self.min, other.min].into_iter().flatten().reduce(f32::min);
+                self.max = [self.max, other.max].into_iter().flatten().reduce(f32::max);
+                self.stddev = Some(variance.sqrt());
+            }
+        }
+
+        self.samples_raw += other.samples_raw;
+        self.samples_kept += other.samples_kept;
+        self.first_at = [self.first_at, other.first_at].into_iter().flatten().min();
+        self.last_at = [self.last_at, other.last_at].into_iter().flatten().max();
+        self.out_of_range += other.out_of_range;
+        self.outliers += other.outliers;
+    }
+}
+
+
+/// Muestra que llegó después de cerrado su bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LateSample {
+    pub measurement: Measurement,
+    /// Inicio del bucket (ya cerrado) al que pertenecía.
+    pub bucket_ts: i64,
+    pub window_secs: i64,
+    /// Momento (Unix, reloj local) en que se recibió.
+    pub received_at: i64,
 }
 
 
-pub async fn bucket_task(mut rx: mpsc::Receiver<BucketData>,
+/// Muestra que no se agregó a ningún bucket y que `dba_task` guarda o rechaza.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unbucketed {
+    Late(LateSample),
+    /// Fuera de los límites de `BUCKET_MAX_*`; se informa al Edge con un Nack.
+    Rejected { measurement: Measurement, reason: String },
+}
+
+
+/// Resultado de [`add_to_bucket`].
+#[derive(Debug, Default)]
+struct Admission {
+    /// Buckets cerrados por la fuerza para hacer lugar a la muestra.
+    forced: Vec<(BucketKey, SensorDataVector)>,
+    /// La muestra, si no se agregó.
+    unbucketed: Option<Unbucketed>,
+}
+
+
+/// Identificador determinista de un bucket: hash de su clave y de los `(sender_user_id, sample)`
+/// de sus muestras, ordenados. El mismo bucket emitido dos veces produce el mismo identificador.
+fn aggregate_id(key: &BucketKey, vector: &SensorDataVector) -> String {
+    let mut samples: Vec<(&str, u32)> = vector.iter()
+        .map(|data| (data.metadata.sender_user_id.as_str(), data.sample))
+        .collect();
+    samples.sort_unstable();
+
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\n{}\n{}\n", key.0, key.1, key.2));
+    for (sender, sample) in samples {
+        hasher.update(format!("{sender}\0{sample}\n"));
+    }
+    hex::encode(&hasher.finalize()[..16])
+}
+
+
+pub async fn bucket_task(rx: &mut mpsc::Receiver<BucketData>,
+                         tx_dba: mpsc::Sender<Unbucketed>,
+                         tx_telemetry: mpsc::Sender<ProcessedTelemetry>,
+                         tx_archive: Option<mpsc::Sender<Vec<Measurement>>>,
                          app_context: AppContext) {
 
+    let archive = &app_context.system.raw_archive;
+
     while let Some(msg) = rx.recv().await {
-        match msg {
-            BucketData::Measurement(measurement) => {
-                let network_id = measurement.network.clone();
-                let raw_timestamp = measurement.metadata.timestamp;
-                let bucket_ts = raw_timestamp - (raw_timestamp % 50);
-                let key: BucketKey = (network_id, bucket_ts);
-
-                app_context.bucket_map.entry(key)
-                    .or_insert_with(Vec::new)
-                    .push(measurement);
-            },
-            BucketData::VecMeasurement(measurements) => {
-                for measurement in measurements {
-                    let network_id = measurement.network.clone();
-                    let raw_timestamp = measurement.metadata.timestamp;
-                    let bucket_ts = raw_timestamp - (raw_timestamp % 50);
-                    let key: BucketKey = (network_id, bucket_ts);
-
-                    app_context.bucket_map.entry(key)
-                        .or_insert_with(Vec::new)
-                        .push(measurement);
+        let measurements = match msg {
+            BucketData::Measurement(measurement) => vec![measurement],
+            BucketData::VecMeasurement(measurements) => measurements,
+        };
+
+        // Se archivan todas las mediciones recibidas, incluso las tardías o rechazadas. El
+        // archivo no frena la agregación: con su canal lleno las mediciones se descartan
+        if let Some(tx_archive) = &tx_archive {
+            let archived: Vec<Measurement> = measurements.iter()
+                .filter(|measurement| archive.archives(&measurement.network))
+                .cloned()
+                .collect();
+            if !archived.is_empty() {
+                match tx_archive.try_send(archived) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(archived)) => {
+                        app_context.metrics.archive_dropped.with_label_values(&["channel_full"]).inc_by(archived.len() as u64);
+                        warn!("Warning: canal del archivo crudo lleno, se descartan {} mediciones sin archivar", archived.len());
+                    }
+                    Err(TrySendError::Closed(_)) => error!("Error: el receptor (archive task) se ha cerrado o caído."),
                 }
             }
         }
+        for measurement in measurements {
+            let admission = add_to_bucket(&app_context, measurement);
+            if !admission.forced.is_empty() {
+                let ranges = app_context.plausibility.current();
+                for (key, vector) in admission.forced {
+                    emit_bucket(&tx_telemetry, &app_context, &ranges, key, vector);
+                }
+            }
+            if let Some(unbucketed) = admission.unbucketed {
+                handle_unbucketed(&tx_dba, &app_context, unbucketed).await;
+            }
+        }
     }
 }
 
 
+/// Agrega la medición al bucket de su red que contiene su timestamp y avanza el watermark,
+/// respetando `BUCKET_MAX_*`.
+///
+/// # Retorno
+/// Los buckets cerrados por la fuerza para hacerle lugar y la muestra, si no se agregó
+/// (tardía o rechazada).
+fn add_to_bucket(app_context: &AppContext, measurement: Measurement) -> Admission {
+    let limits = &app_context.system.bucket_limits;
+    let config = app_context.system.bucket.for_network(&measurement.network);
+    let event_ts = measurement.metadata.timestamp;
+    let bucket_ts = config.align(event_ts);
+    let now = unix_now();
+
+    if let Some(reason) = limits.check_timestamp(event_ts, now) {
+        return reject(app_context, measurement, reason);
+    }
+
+    // El tope global se resuelve antes de tomar el watermark de la red: cerrar el bucket más
+    // antiguo requiere el watermark de su propia red, y nunca se toman dos a la vez
+    let mut admission = Admission::default();
+    if app_context.bucket_samples.load(Ordering::Relaxed) >= limits.max_samples {
+        if limits.overflow == OverflowPolicy::Reject {
+            return reject(app_context, measurement, "capacity");
+        }
+        admission.forced = evict_oldest(app_context, limits.eviction_target());
+    }
+
+    // El lock del watermark de la red se mantiene mientras se agrega la muestra: el sweeper
+    // toma el mismo lock para cerrar buckets, así que una muestra nunca reabre un bucket cerrado
+    let mut watermark = app_context.watermarks.entry(measurement.network.clone()).or_default();
+    let late = LateSample { measurement, bucket_ts, window_secs: config.window_secs, received_at: now };
+    if watermark.is_late(config.bucket_end(bucket_ts)) {
+        admission.unbucketed = Some(Unbucketed::Late(late));
+        return admission;
+    }
+
+    let key: BucketKey = (late.measurement.network.clone(), bucket_ts, config.window_secs);
+    let is_new = !app_context.bucket_map.contains_key(&key);
+    if is_new {
+        let open = app_context.open_buckets.get(&key.0).map_or(0, |open| *open);
+        if open >= limits.max_open_per_network {
+            if limits.overflow == OverflowPolicy::Reject {
+                admission.unbucketed = reject(app_context, late.measurement, "network_buckets").unbucketed;
+                return admission;
+            }
+            let surplus = open + 1 - limits.max_open_per_network;
+            let mut oldest: Vec<BucketKey> = app_context.bucket_map.iter()
+                .filter(|entry| entry.key().0 == key.0)
+                .map(|entry| entry.key().clone())
+                .collect();
+            oldest.sort_by_key(|(_, bucket_ts, _)| *bucket_ts);
+            oldest.truncate(surplus);
+            admission.forced.extend(close_locked(app_context, &mut watermark, oldest));
+            app_context.metrics.bucket_forced_closes.with_label_values(&["network_buckets"]).inc_by(surplus as u64);
+
+            // Si la muestra era más antigua que los buckets cerrados, ahora es tardía
+            if watermark.is_late(config.bucket_end(bucket_ts)) {
+                admission.unbucketed = Some(Unbucketed::Late(late));
+                return admission;
+            }
+        }
+    }
+
+    watermark.observe(event_ts, now);
+    if is_new {
+        *app_context.open_buckets.entry(key.0.clone()).or_default() += 1;
+    }
+    app_context.bucket_map.entry(key)
+        .or_default()
+        .push(late.measurement);
+    app_context.bucket_samples.fetch_add(1, Ordering::Relaxed);
+    admission
+}
+
+
+/// Cuenta la muestra rechazada y la devuelve para que `dba_task` envíe el Nack.
+fn reject(app_context: &AppContext, measurement: Measurement, reason: &str) -> Admission {
+    app_context.metrics.bucket_rejected_samples.with_label_values(&[reason]).inc();
+    debug!("Debug: muestra de la red {} rechazada (timestamp {}). Motivo: {}",
+        measurement.network, measurement.metadata.timestamp, reason);
+    Admission {
+        forced: Vec::new(),
+        unbucketed: Some(Unbucketed::Rejected {
+            measurement,
+            reason: format!("muestra rechazada por límite de buckets: {reason}"),
+        }),
+    }
+}
+
+
+/// Cierra los buckets más antiguos, entre todas las redes, hasta que queden menos de `target`
+/// muestras. Ordena las claves una sola vez por tanda de cierres.
+fn evict_oldest(app_context: &AppContext, target: usize) -> Vec<(BucketKey, SensorDataVector)> {
+    let mut oldest: Vec<BucketKey> = app_context.bucket_map.iter()
+        .map(|entry| entry.key().clone())
+        .collect();
+    oldest.sort_by_key(|(_, bucket_ts, _)| *bucket_ts);
+
+    let mut forced = Vec::new();
+    for key in oldest {
+        if app_context.bucket_samples.load(Ordering::Relaxed) < target {
+            break;
+        }
+        let network_id = key.0.clone();
+        let closed = close_buckets(app_context, &network_id, vec![key]);
+        app_context.metrics.bucket_forced_closes.with_label_values(&["capacity"]).inc_by(closed.len() as u64);
+        forced.extend(closed);
+    }
+    forced
+}
+
+
+/// Envía la muestra no agregada a `dba_task`. Las tardías se guardan o se descartan según
+/// `LATE_DATA_POLICY`; las rechazadas reciben un Nack.
+async fn handle_unbucketed(tx_dba: &mpsc::Sender<Unbucketed>, app_context: &AppContext, unbucketed: Unbucketed) {
+    if let Unbucketed::Late(late) = &unbucketed {
+        let policy = match app_context.system.late_data_policy {
+            LatePolicy::Table => "table",
+            LatePolicy::Drop => "drop",
+        };
+        app_context.metrics.late_samples.with_label_values(&[policy]).inc();
+        debug!("Debug: muestra tardía de la red {} (timestamp {}, bucket {}). Política: {}",
+            late.measurement.network, late.measurement.metadata.timestamp, late.bucket_ts, policy);
+    }
+
+    if tx_dba.send(unbucketed).await.is_err() {
+        error!("Error: el receptor (dba task) se ha cerrado o caído.");
+    }
+}
+
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+
 pub async fn sweeper_task(tx_dba: mpsc::Sender<ProcessedTelemetry>,
-                          app_context: AppContext) {
+                          app_context: AppContext) -> usize {
 
-    // El temporizador se despierta cada 5 segundos
-    let mut ticker = interval(Duration::from_secs(5));
-    let window_grace = 50;   // Segundos de espera para los datos
+    let settings = &app_context.system.bucket;
+
+    // El temporizador late al ritmo de la red más exigente; cada red se revisa según su `sweep_secs`
+    let mut ticker = interval(Duration::from_secs(settings.tick_secs()));
+    let mut last_sweep: HashMap<String, Instant> = HashMap::new();
+    let checkpoint_interval = Duration::from_secs(app_context.system.bucket_checkpoint_interval_secs);
+    let mut last_checkpoint_at: Option<Instant> = None;
+    let mut written: Option<BucketCheckpoint> = None;
+    let mut checkpointed_closes = app_context.bucket_closes.load(Ordering::Relaxed);
 
     loop {
-        ticker.tick().await;
+        tokio::select! {
+            _ = ticker.tick() => {},
+            _ = app_context.shutdown.wait() => break,
+        }
+        app_context.tasks.beat(SWEEPER_TASK);
+
+        let tick = Instant::now();
 
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
+        // Watermarks vigentes al inicio del tick. Se copian para no mantener sus locks mientras
+        // se recorre `bucket_map` (`add_to_bucket` los toma en el orden inverso)
+        let watermarks: HashMap<String, Watermark> = app_context.watermarks.iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect();
 
-        let mut expired_keys = Vec::new();
+        // Redes cuyo intervalo de revisión se cumplió en este tick
+        let mut due: HashMap<String, bool> = HashMap::new();
+        let mut closable: HashMap<String, Vec<BucketKey>> = HashMap::new();
 
-        // Iteramos solo para encontrar claves vencidas
+        // Iteramos solo para encontrar claves cerrables
         for entry in app_context.bucket_map.iter() {
-            let bucket_ts = entry.key().1;
-            if now > (bucket_ts + window_grace) {
-                expired_keys.push(entry.key().clone());
+            let (network_id, bucket_ts, window_secs) = entry.key();
+            let config = settings.for_network(network_id);
+
+            let is_due = *due.entry(network_id.clone()).or_insert_with(|| {
+                last_sweep.get(network_id)
+                    .is_none_or(|last| tick.duration_since(*last) >= Duration::from_secs(config.sweep_secs))
+            });
+            let Some(watermark) = watermarks.get(network_id) else { continue };
+
+            // El fin se calcula con la ventana de la clave: un bucket restaurado de un checkpoint
+            // conserva la ventana con la que se abrió aunque la configuración haya cambiado
+            if is_due && bucket_ts + window_secs <= watermark.current(&config, settings.idle_secs) {
+                closable.entry(network_id.clone()).or_default().push(entry.key().clone());
             }
         }
 
+        for (network_id, is_due) in due {
+            if is_due {
+                last_sweep.insert(network_id, tick);
+            }
+        }
+
+        // Rangos vigentes en este tick (se recargan si cambió el archivo de configuración)
+        let ranges = app_context.plausibility.current();
+
         // Extraemos el vector y lo procesamos
-        for key in expired_keys {
-            //let network_id = key.clone();
-            if let Some((_, vector)) = app_context.bucket_map.remove(&key) {
-                // Se lanza un worker independiente para hacer el filtrado sin frenar el bucle del Sweeper
-                let tx_worker = tx_dba.clone();
-                tokio::spawn(async move {
-                    let mut to_process = ToProcess::default();
-                    let mut pulse_max_duration: i64 = 0;
-                    let mut pulse_counter: i64 = 0;
-
-                    let temperature: Option<f32>;
-                    let humidity: Option<f32>;
-                    let co2_ppm: Option<f32>;
-
-                    for data in vector {
-                        if data.temperature >= 10.0 && data.temperature <= 35.0 {
-                            to_process.temperature.push(data.temperature);
-                        }
-                        if data.humidity >= 20.0 && data.humidity <= 90.0 {
-                            to_process.humidity.push(data.humidity);
-                        }
-                        if data.co2_ppm >= 400.0 && data.co2_ppm <= 1800.0 {
-                            to_process.co2_ppm.push(data.co2_ppm);
-                        }
-
-                        pulse_counter += data.pulse_counter;
-
-                        if data.pulse_max_duration > pulse_max_duration {
-                            pulse_max_duration = data.pulse_max_duration;
-                        }
-                    }
+        for (network_id, keys) in closable {
+            for (key, vector) in close_buckets(&app_context, &network_id, keys) {
+                emit_bucket(&tx_dba, &app_context, &ranges, key, vector);
+            }
+        }
 
-                    if !to_process.temperature.is_empty() && to_process.temperature.len() >= 4 {
-                        to_process.temperature.sort_unstable_by(|a, b| a.total_cmp(b));
-                        let quartiles_temp = quartiles(&to_process.temperature);
-                        let iqr_temp = quartiles_temp.1 - quartiles_temp.0;
-                        to_process.temperature.retain(|&x| {
-                            x >= quartiles_temp.0 - 1.5*iqr_temp && x <= quartiles_temp.1 + 1.5*iqr_temp
-                        });
-                        temperature = Some(to_process.temperature.iter().sum::<f32>() / to_process.temperature.len() as f32);
-                    }
-                    else if !to_process.temperature.is_empty() && to_process.temperature.len() < 4 {
-                        temperature = Some(to_process.temperature.iter().sum::<f32>() / to_process.temperature.len() as f32);
-                    }
-                    else {
-                        temperature = None;
-                    }
+        // Un bucket cerrado (aquí o por la fuerza en `bucket_task`) se quita del checkpoint en
+        // este mismo tick; solo las muestras nuevas esperan a `BUCKET_CHECKPOINT_INTERVAL_SECS`
+        let closes = app_context.bucket_closes.load(Ordering::Relaxed);
+        if closes != checkpointed_closes
+            || last_checkpoint_at.is_none_or(|at| tick.duration_since(at) >= checkpoint_interval) {
+            if write_checkpoint(&app_context, &mut written) {
+                checkpointed_closes = closes;
+            }
+            last_checkpoint_at = Some(tick);
+        }
+    }
 
-                    if !to_process.humidity.is_empty() && to_process.humidity.len() >= 4 {
-                        to_process.humidity.sort_unstable_by(|a, b| a.total_cmp(b));
-                        let quartiles_hum = quartiles(&to_process.humidity);
-                        let iqr_hum = quartiles_hum.1 - quartiles_hum.0;
-                        to_process.humidity.retain(|&x| {
-                            x >= quartiles_hum.0 - 1.5*iqr_hum && x <= quartiles_hum.1 + 1.5*iqr_hum
-                        });
-                        humidity = Some(to_process.humidity.iter().sum::<f32>() / to_process.humidity.len() as f32);
-                    }
-                    else if !to_process.humidity.is_empty() && to_process.humidity.len() < 4 {
-                        humidity = Some(to_process.humidity.iter().sum::<f32>() / to_process.humidity.len() as f32);
-                    }
-                    else {
-                        humidity = None;
-                    }
+    // Apagado: `bucket_task` termina cuando `message_download` suelta su canal, y recién
+    // entonces el mapa contiene todas las mediciones recibidas.
+    info!("Info: apagado en curso, esperando a que bucket_task vacíe su canal");
+    app_context.tasks.wait_finished(BUCKET_TASK).await;
+    let flushed = flush_all_buckets(&tx_dba, &app_context).await;
 
-                    if !to_process.co2_ppm.is_empty() && to_process.co2_ppm.len() >= 4 {
-                        to_process.co2_ppm.sort_unstable_by(|a, b| a.total_cmp(b));
-                        let quartiles_co2 = quartiles(&to_process.co2_ppm);
-                        let iqr_co2 = quartiles_co2.1 - quartiles_co2.0;
-                        to_process.co2_ppm.retain(|&x| {
-                            x >= quartiles_co2.0 - 1.5*iqr_co2 && x <= quartiles_co2.1 + 1.5*iqr_co2
-                        });
-                        co2_ppm = Some(to_process.co2_ppm.iter().sum::<f32>() / to_process.co2_ppm.len() as f32);
-                    }
-                    else if !to_process.co2_ppm.is_empty() && to_process.co2_ppm.len() < 4 {
-                        co2_ppm = Some(to_process.co2_ppm.iter().sum::<f32>() / to_process.co2_ppm.len() as f32);
-                    }
-                    else {
-                        co2_ppm = None;
-                    }
+    // Los buckets ya se enviaron a `dba_task`: el checkpoint queda sin buckets para que el
+    // próximo arranque no los vuelva a emitir
+    write_checkpoint(&app_context, &mut written);
+    flushed
+}
 
-                    let processed = ProcessedTelemetry {
-                        network_id: key.0,
-                        timestamp: key.1,
-                        temperature,
-                        humidity,
-                        co2_ppm,
-                        pulse_counter_total: pulse_counter,
-                        pulse_max_duration
-                    };
-
-                    if tx_worker.send(processed).await.is_err() {
-                        error!("Error: el receptor (dab task) se ha cerrado o caído.");
-                    }
-                });
+
+/// Guarda los buckets abiertos y los watermarks en `BUCKET_CHECKPOINT_FILE`, si está habilitado.
+///
+/// `written` es el último checkpoint escrito: si el estado no cambió desde entonces no se
+/// vuelve a escribir.
+///
+/// # Retorno
+/// `false` si la escritura falló, para reintentarla en el próximo tick.
+fn write_checkpoint(app_context: &AppContext, written: &mut Option<BucketCheckpoint>) -> bool {
+    let Some(path) = &app_context.system.bucket_checkpoint_file else {
+        return true;
+    };
+    let checkpoint = BucketCheckpoint::capture(&app_context.bucket_map, &app_context.watermarks);
+    if written.as_ref() == Some(&checkpoint) {
+        debug!("Debug: checkpoint de buckets sin cambios, no se reescribe");
+        return true;
+    }
+    match checkpoint.save(Path::new(path)) {
+        Ok(()) => {
+            *written = Some(checkpoint);
+            true
+        }
+        Err(e) => {
+            error!("Error: no se pudo escribir el checkpoint de buckets en {}. {}", path, e);
+            false
+        }
+    }
+}
+
+
+/// Restaura los buckets abiertos del último checkpoint. Un checkpoint ilegible no impide el
+/// arranque: se informa y se empieza sin buckets.
+fn restore_checkpoint(app_context: &AppContext) {
+    let Some(path) = &app_context.system.bucket_checkpoint_file else {
+        return;
+    };
+    match BucketCheckpoint::load(Path::new(path)) {
+        Ok(Some(checkpoint)) => {
+            let samples = checkpoint.samples();
+            let buckets = checkpoint.restore(&app_context.bucket_map, &app_context.watermarks);
+            app_context.bucket_samples.fetch_add(samples, Ordering::Relaxed);
+            for entry in app_context.bucket_map.iter() {
+                *app_context.open_buckets.entry(entry.key().0.clone()).or_default() += 1;
             }
+            info!("Info: checkpoint restaurado desde {}: {} buckets abiertos, {} muestras", path, buckets, samples);
         }
+        Ok(None) => info!("Info: no hay checkpoint de buckets en {}", path),
+        Err(e) => warn!("Warning: se descarta el checkpoint de buckets. {}", e),
+    }
+}
+
+
+/// Quita de `bucket_map` los buckets de una red y marca su fin en el watermark, de modo
+/// que las muestras que lleguen después para esas ventanas se traten como tardías.
+fn close_buckets(app_context: &AppContext, network_id: &str, keys: Vec<BucketKey>) -> Vec<(BucketKey, SensorDataVector)> {
+    let mut watermark = app_context.watermarks.entry(network_id.to_string()).or_default();
+    close_locked(app_context, &mut watermark, keys)
+}
+
+
+/// [`close_buckets`] con el watermark de la red ya tomado.
+fn close_locked(app_context: &AppContext, watermark: &mut Watermark, keys: Vec<BucketKey>) -> Vec<(BucketKey, SensorDataVector)> {
+    keys.into_iter()
+        .filter_map(|key| app_context.bucket_map.remove(&key))
+        .inspect(|(key, vector)| {
+            watermark.closed_until = watermark.closed_until.max(key.1 + key.2);
+            forget_bucket(app_context, key, vector);
+        })
+        .collect()
+}
+
+
+/// Descuenta de los contadores de `AppContext` un bucket recién quitado de `bucket_map`.
+/// Todo punto que quita buckets del mapa pasa por aquí.
+fn forget_bucket(app_context: &AppContext, key: &BucketKey, vector: &SensorDataVector) {
+    app_context.bucket_samples.fetch_sub(vector.len(), Ordering::Relaxed);
+    app_context.bucket_closes.fetch_add(1, Ordering::Relaxed);
+    if let Some(mut open) = app_context.open_buckets.get_mut(&key.0) {
+        *open = open.saturating_sub(1);
     }
+    app_context.open_buckets.remove_if(&key.0, |_, open| *open == 0);
+}
+
+
+/// Agrega un bucket cerrado en un worker independiente, para no frenar al llamador, y lo
+/// envía a `dba_task`.
+fn emit_bucket(tx_dba: &mpsc::Sender<ProcessedTelemetry>,
+               app_context: &AppContext,
+               ranges: &PlausibilityRanges,
+               key: BucketKey,
+               vector: SensorDataVector) {
+
+    let tx_worker = tx_dba.clone();
+    let network_ranges = ranges.for_network(&key.0);
+    let aggregators = app_context.system.aggregation.for_network(&key.0);
+    tokio::spawn(async move {
+        let processed = aggregate_bucket(key, vector, network_ranges, aggregators);
+        if tx_worker.send(processed).await.is_err() {
+            error!("Error: el receptor (dab task) se ha cerrado o caído.");
+        }
+    });
 }
 
 
-fn quartiles(data: &[f32]) -> (f32, f32) {
-    let q1 = percentile(data, 25.0);
-    let q3 = percentile(data, 75.0);
-    (q1, q3)
+/// Cierra y agrega todos los buckets abiertos, vencidos o no, y los envía a `dba_task`.
+///
+/// # Retorno
+/// Cantidad de buckets enviados.
+async fn flush_all_buckets(tx_dba: &mpsc::Sender<ProcessedTelemetry>, app_context: &AppContext) -> usize {
+    let keys: Vec<BucketKey> = app_context.bucket_map.iter()
+        .map(|entry| entry.key().clone())
+        .collect();
+
+    let ranges = app_context.plausibility.current();
+    let mut flushed = 0;
+    for key in keys {
+        if let Some((key, vector)) = app_context.bucket_map.remove(&key) {
+            forget_bucket(app_context, &key, &vector);
+            let network_ranges = ranges.for_network(&key.0);
+            let aggregators = app_context.system.aggregation.for_network(&key.0);
+            if tx_dba.send(aggregate_bucket(key, vector, network_ranges, aggregators)).await.is_err() {
+                error!("Error: el receptor (dab task) se ha cerrado o caído.");
+                break;
+            }
+            flushed += 1;
+        }
+    }
+    info!("Info: {} buckets cerrados por apagado", flushed);
+    flushed
 }
 
 
-fn percentile(sorted: &[f32], percentile: f32) -> f32 {
-    let index = (percentile / 100.0) * (sorted.len() as f32 - 1.0);
-    let lower = index.floor() as usize;
-    let upper = index.ceil() as usize;
+/// Filtra las mediciones de un bucket por rangos plausibles, agrega cada sensor con la
+/// estrategia configurada para la red y calcula los totales que se persisten, junto con la
+/// cantidad de muestras descartadas y el nombre de cada estrategia.
+pub fn aggregate_bucket(key: BucketKey,
+                    mut vector: SensorDataVector,
+                    ranges: SensorRanges,
+                    aggregators: SensorAggregators) -> ProcessedTelemetry {
+
+    let mut to_process = ToProcess::default();
+    let mut rejected = RejectedSamples::default();
+    let mut pulse_max_duration: i64 = 0;
+    let mut pulse_counter: i64 = 0;
 
-    if lower == upper {
-        sorted[lower]
-    } else {
-        let weight = index - lower as f32;
-        sorted[lower] * (1.0 - weight) + sorted[upper] * weight
+    let mut deliveries: Vec<Delivery> = vector.iter()
+        .filter_map(|data| data.metadata.delivery())
+        .collect();
+    deliveries.sort_unstable_by(|a, b| {
+        (&a.sender_user_id, a.sequence_id).cmp(&(&b.sender_user_id, b.sequence_id))
+    });
+    deliveries.dedup();
+
+    // Orden cronológico: algunas estrategias (ej. `last_value`) dependen de él
+    vector.sort_by_key(|data| data.metadata.timestamp);
+
+    // Un reenvío del Edge repite `sample`: se conserva la primera copia. Sus entregas ya se
+    // tomaron arriba, así que igual se confirman
+    let received = vector.len();
+    let mut seen: HashSet<(String, u32)> = HashSet::with_capacity(received);
+    vector.retain(|data| seen.insert((data.metadata.sender_user_id.clone(), data.sample)));
+    rejected.duplicate_samples = (received - vector.len()) as i32;
+    let aggregate_id = aggregate_id(&key, &vector);
+
+    let senders = distinct(vector.iter().map(|data| data.metadata.sender_user_id.clone()));
+    let destinations = distinct(vector.iter().map(|data| data.metadata.destination_id.clone()));
+
+    // Todas las mediciones traen los tres sensores: la cobertura cruda es la misma para cada uno
+    let samples_raw = vector.len() as i32;
+    let first_at = vector.first().map(|data| data.metadata.timestamp);
+    let last_at = vector.last().map(|data| data.metadata.timestamp);
+
+    for data in vector {
+        if ranges.temperature.contains(data.temperature) {
+            to_process.temperature.push(data.temperature);
+        } else {
+            rejected.temperature_out_of_range += 1;
+        }
+        if ranges.humidity.contains(data.humidity) {
+            to_process.humidity.push(data.humidity);
+        } else {
+            rejected.humidity_out_of_range += 1;
+        }
+        if ranges.co2_ppm.contains(data.co2_ppm) {
+            to_process.co2_ppm.push(data.co2_ppm);
+        } else {
+            rejected.co2_out_of_range += 1;
+        }
+
+        pulse_counter += data.pulse_counter;
+
+        if data.pulse_max_duration > pulse_max_duration {
+            pulse_max_duration = data.pulse_max_duration;
+        }
+    }
+
+    let temperature = aggregators.temperature.aggregate(&to_process.temperature);
+    let humidity = aggregators.humidity.aggregate(&to_process.humidity);
+    let co2_ppm = aggregators.co2_ppm.aggregate(&to_process.co2_ppm);
+
+    rejected.temperature_outliers = (to_process.temperature.len() - temperature.kept.len()) as i32;
+    rejected.humidity_outliers = (to_process.humidity.len() - humidity.kept.len()) as i32;
+    rejected.co2_outliers = (to_process.co2_ppm.len() - co2_ppm.kept.len()) as i32;
+
+    let (temperature_min, temperature_max, temperature_stddev) = spread(&temperature.kept);
+    let (humidity_min, humidity_max, humidity_stddev) = spread(&humidity.kept);
+    let (co2_min, co2_max, co2_stddev) = spread(&co2_ppm.kept);
+
+    let stats = BucketStats {
+        temperature_min,
+        temperature_max,
+        temperature_stddev,
+        temperature_samples_raw: samples_raw,
+        temperature_samples_kept: temperature.kept.len() as i32,
+        temperature_first_at: first_at,
+        temperature_last_at: last_at,
+        humidity_min,
+        humidity_max,
+        humidity_stddev,
+        humidity_samples_raw: samples_raw,
+        humidity_samples_kept: humidity.kept.len() as i32,
+        humidity_first_at: first_at,
+        humidity_last_at: last_at,
+        co2_min,
+        co2_max,
+        co2_stddev,
+        co2_samples_raw: samples_raw,
+        co2_samples_kept: co2_ppm.kept.len() as i32,
+        co2_first_at: first_at,
+        co2_last_at: last_at,
+    };
+
+    ProcessedTelemetry {
+        network_id: key.0,
+        timestamp: key.1,
+        window_secs: key.2,
+        temperature: temperature.value,
+        humidity: humidity.value,
+        co2_ppm: co2_ppm.value,
+        pulse_counter_total: pulse_counter,
+        pulse_max_duration,
+        rejected,
+        stats,
+        strategies: AggregationStrategies {
+            temperature_strategy: aggregators.temperature.name(),
+            humidity_strategy: aggregators.humidity.name(),
+            co2_strategy: aggregators.co2_ppm.name(),
+        },
+        senders,
+        destinations,
+        aggregate_id,
+        deliveries,
     }
 }
 
 
+/// Mínimo, máximo y desvío estándar poblacional de muestras ordenadas de forma ascendente.
+fn spread(sorted: &[f32]) -> (Option<f32>, Option<f32>, Option<f32>) {
+    if sorted.is_empty() {
+        return (None, None, None);
+    }
+    let n = sorted.len() as f32;
+    let mean = sorted.iter().sum::<f32>() / n;
+    let variance = sorted.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / n;
+    (sorted.first().copied(), sorted.last().copied(), Some(variance.sqrt()))
+}
+
+
 pub fn start_bucket(rx: mpsc::Receiver<BucketData>,
+                    tx_dba: mpsc::Sender<Unbucketed>,
+                    tx_telemetry: mpsc::Sender<ProcessedTelemetry>,
+                    tx_archive: Option<mpsc::Sender<Vec<Measurement>>>,
                     app_context: AppContext) {
 
+    // Antes de lanzar bucket_task (y el sweeper), para que las nuevas muestras se sumen a
+    // los buckets restaurados
+    restore_checkpoint(&app_context);
+
     info!("Info: iniciando tarea bucket_task");
-    tokio::spawn(async move {
-        bucket_task(rx,
-                    app_context
-        ).await;
+    let rx = shared(rx);
+    supervise(BUCKET_TASK, true, &app_context.clone(), move || {
+        let rx = rx.clone();
+        let tx_dba = tx_dba.clone();
+        let tx_telemetry = tx_telemetry.clone();
+        let tx_archive = tx_archive.clone();
+        let app_context = app_context.clone();
+        async move {
+            bucket_task(&mut *rx.lock().await,
+                        tx_dba,
+                        tx_telemetry,
+                        tx_archive,
+                        app_context
+            ).await;
+        }
     });
 }
 
 
 pub fn start_sweeper(tx_dba: mpsc::Sender<ProcessedTelemetry>,
-                     app_context: AppContext) {
+                     app_context: AppContext) -> JoinHandle<Option<usize>> {
 
     info!("Info: iniciando tarea sweeper_task");
-    tokio::spawn(async move {
-        sweeper_task(tx_dba,
-                     app_context
-        ).await;
-    });
-}
\ No newline at end of file
+    supervise(SWEEPER_TASK, true, &app_context.clone(), move || {
+        sweeper_task(tx_dba.clone(),
+                     app_context.clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn telemetry(temperature: f32, strategy: &str, samples: i32, last_at: i64) -> ProcessedTelemetry {
+        let mut telemetry = ProcessedTelemetry {
+            temperature: Some(temperature),
+            ..Default::default()
+        };
+        telemetry.strategies.temperature_strategy = strategy.to_string();
+        telemetry.stats.temperature_samples_kept = samples;
+        telemetry.stats.temperature_last_at = Some(last_at);
+        telemetry
+    }
+
+    #[test]
+    fn merging_the_same_mean_strategy_weights_by_samples() {
+        let mut merged = telemetry(20.0, "hampel:3", 3, 10);
+        merged.merge(&telemetry(24.0, "hampel:3", 1, 20));
+        assert_eq!(merged.temperature, Some(21.0));
+        assert_eq!(merged.strategies.temperature_strategy, "hampel:3");
+        assert_eq!(merged.stats.temperature_samples_kept, 4);
+    }
+
+    #[test]
+    fn merging_last_values_keeps_the_newest() {
+        let mut merged = telemetry(20.0, "last_value", 3, 30);
+        merged.merge(&telemetry(24.0, "last_value", 1, 20));
+        assert_eq!(merged.temperature, Some(20.0));
+        assert_eq!(merged.strategies.temperature_strategy, "last_value");
+    }
+
+    #[test]
+    fn merging_inexact_strategies_marks_the_row() {
+        let mut merged = telemetry(20.0, "median", 3, 10);
+        merged.merge(&telemetry(24.0, "median", 1, 20));
+        assert_eq!(merged.temperature, Some(21.0));
+        assert_eq!(merged.strategies.temperature_strategy, MERGED);
+
+        let mut merged = telemetry(20.0, "last_value", 1, 30);
+        merged.merge(&telemetry(24.0, "iqr_mean", 1, 20));
+        assert_eq!(merged.temperature, Some(22.0));
+        assert_eq!(merged.strategies.temperature_strategy, MERGED);
+    }
+
+    #[test]
+    fn merging_into_an_empty_sensor_takes_the_other_side() {
+        let mut merged = ProcessedTelemetry::default();
+        merged.merge(&telemetry(24.0, "median", 1, 20));
+        assert_eq!(merged.temperature, Some(24.0));
+        assert_eq!(merged.strategies.temperature_strategy, "median");
+    }
+
+    fn sample(sender: &str, sample: u32) -> Measurement {
+        let mut measurement = Measurement { sample, ..Default::default() };
+        measurement.metadata.sender_user_id = sender.to_string();
+        measurement
+    }
+
+    #[test]
+    fn aggregate_ids_depend_on_the_key_and_samples_only() {
+        let key: BucketKey = ("red".to_string(), 100, 50);
+        let id = aggregate_id(&key, &vec![sample("a", 1), sample("b", 1)]);
+
+        assert_eq!(id, aggregate_id(&key, &vec![sample("b", 1), sample("a", 1)]));
+        assert_ne!(id, aggregate_id(&key, &vec![sample("a", 1), sample("b", 2)]));
+        assert_ne!(id, aggregate_id(&("red".to_string(), 150, 50), &vec![sample("a", 1), sample("b", 1)]));
+    }
+}
@@ -1 +1,4 @@
+pub mod aggregation;
+pub mod checkpoint;
+pub mod domain;
 pub mod logic;
\ No newline at end of file
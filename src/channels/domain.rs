@@ -13,10 +13,11 @@
 
 use tokio::sync::mpsc;
 use tracing::info;
-use crate::bucket::logic::{BucketData, ProcessedTelemetry};
+use crate::bucket::logic::{BucketData, Unbucketed, ProcessedTelemetry};
 use crate::grpc::{FromDataSaver};
 use crate::heartbeat::domain::Event;
-use crate::message::domain::Message;
+use crate::message::domain::{Measurement, Message};
+use crate::metrics::domain::Metrics;
 use crate::system::domain::InternalEvent;
 use crate::weather::domain::{Weather};
 
@@ -32,6 +33,8 @@ pub struct Channels {
     pub heartbeat_from_watchdog: mpsc::Receiver<Event>,
     pub heartbeat_to_upload_message: mpsc::Sender<Message>,
     pub upload_message_from_heartbeat: mpsc::Receiver<Message>,
+    pub dba_to_upload_message: mpsc::Sender<Message>,
+    pub upload_message_from_dba: mpsc::Receiver<Message>,
     pub upload_message_to_grpc: mpsc::Sender<FromDataSaver>,
     pub grpc_from_upload_message: mpsc::Receiver<FromDataSaver>,
     pub download_message_to_bucket: mpsc::Sender<BucketData>,
@@ -44,6 +47,10 @@ pub struct Channels {
     pub dba_from_download_message: mpsc::Receiver<Message>,
     pub sweeper_to_dba: mpsc::Sender<ProcessedTelemetry>,
     pub dba_from_sweeper: mpsc::Receiver<ProcessedTelemetry>,
+    pub bucket_to_dba: mpsc::Sender<Unbucketed>,
+    pub dba_from_bucket: mpsc::Receiver<Unbucketed>,
+    pub bucket_to_archive: mpsc::Sender<Vec<Measurement>>,
+    pub archive_from_bucket: mpsc::Receiver<Vec<Measurement>>,
 }
 
 
@@ -57,17 +64,23 @@ impl Channels {
     /// * **Buffer Grande (200):** Para el flujo de datos principal (gRPC/DB). Permite absorber
     ///   picos de tráfico (bursts) provenientes de la red sin bloquear inmediatamente al emisor,
     ///   mejorando el rendimiento general (throughput).
+    /// * **Confirmaciones (1000):** Los Ack/Nack de `dba_task` tienen su propio canal, separado
+    ///   de los heartbeats: se encolan con `try_send` y un vaciado grande genera uno por emisor
+    ///   de golpe. Un Ack descartado obliga al Edge a reenviar todo lo pendiente.
     pub fn new() -> Channels {
         info!("Info: creando canales de comunicación");
         let (heartbeat_to_watchdog, watchdog_from_heartbeat) = mpsc::channel::<Event>(50);
         let (watchdog_to_heartbeat, heartbeat_from_watchdog) = mpsc::channel::<Event>(50);
         let (heartbeat_to_upload_message, upload_message_from_heartbeat) = mpsc::channel::<Message>(50);
+        let (dba_to_upload_message, upload_message_from_dba) = mpsc::channel::<Message>(1000);
         let (upload_message_to_grpc, grpc_from_upload_message) = mpsc::channel::<FromDataSaver>(200);
         let (download_message_to_bucket, bucket_from_download_message) = mpsc::channel::<BucketData>(200);
         let (grpc_to_download_message, download_message_from_grpc) = mpsc::channel::<InternalEvent>(200);
         let (weather_to_dba, dba_from_weather) = mpsc::channel::<Weather>(10);
         let (sweeper_to_dba, dba_from_sweeper) = mpsc::channel::<ProcessedTelemetry>(10);
         let (download_message_to_dba, dba_from_download_message) = mpsc::channel::<Message>(50);
+        let (bucket_to_dba, dba_from_bucket) = mpsc::channel::<Unbucketed>(50);
+        let (bucket_to_archive, archive_from_bucket) = mpsc::channel::<Vec<Measurement>>(200);
 
         Self {
             heartbeat_to_watchdog,
@@ -76,6 +89,8 @@ impl Channels {
             heartbeat_from_watchdog,
             heartbeat_to_upload_message,
             upload_message_from_heartbeat,
+            dba_to_upload_message,
+            upload_message_from_dba,
             upload_message_to_grpc,
             grpc_from_upload_message,
             download_message_to_bucket,
@@ -87,7 +102,29 @@ impl Channels {
             sweeper_to_dba,
             dba_from_sweeper,
             download_message_to_dba,
-            dba_from_download_message
+            dba_from_download_message,
+            bucket_to_dba,
+            dba_from_bucket,
+            bucket_to_archive,
+            archive_from_bucket
         }
     }
-}
\ No newline at end of file
+
+    /// Registra los canales en las métricas para publicar su profundidad (`channel_depth`).
+    ///
+    /// Debe llamarse antes de repartir los extremos entre las tareas.
+    pub fn register_metrics(&self, metrics: &Metrics) {
+        metrics.register_channel("heartbeat_to_watchdog", &self.heartbeat_to_watchdog);
+        metrics.register_channel("watchdog_to_heartbeat", &self.watchdog_to_heartbeat);
+        metrics.register_channel("heartbeat_to_upload_message", &self.heartbeat_to_upload_message);
+        metrics.register_channel("dba_to_upload_message", &self.dba_to_upload_message);
+        metrics.register_channel("upload_message_to_grpc", &self.upload_message_to_grpc);
+        metrics.register_channel("download_message_to_bucket", &self.download_message_to_bucket);
+        metrics.register_channel("grpc_to_download_message", &self.grpc_to_download_message);
+        metrics.register_channel("weather_to_dba", &self.weather_to_dba);
+        metrics.register_channel("download_message_to_dba", &self.download_message_to_dba);
+        metrics.register_channel("sweeper_to_dba", &self.sweeper_to_dba);
+        metrics.register_channel("bucket_to_dba", &self.bucket_to_dba);
+        metrics.register_channel("bucket_to_archive", &self.bucket_to_archive);
+    }
+}
@@ -6,50 +6,145 @@
 //! (Base de datos, Configuración, Caché en memoria).
 
 
+use std::future::Future;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize};
 use tracing::info;
 use crate::alert_issuer::domain::TelegramNotifier;
+use crate::bucket::domain::{PlausibilityStore, Watermark};
 use crate::database::repository::Repository;
+use crate::database::storage::Storage;
+use crate::grpc_service::domain::{load_server_tls_config, load_tls_config, AuthCredentials, ClientSecrets, ConnectionStatusMap, GrpcMode};
+use tonic::transport::{ClientTlsConfig, ServerTlsConfig};
 use crate::system::domain::{System};
 use dashmap::DashMap;
 use crate::message::domain::Measurement;
+use crate::metrics::domain::Metrics;
+use crate::shutdown::domain::ShutdownSignal;
+use crate::spill::domain::SpillStats;
+use crate::supervisor::domain::TaskRegistry;
 
 
-pub type BucketKey = (String, i64);
+/// Clave de un bucket: `(network_id, inicio de la ventana, tamaño de la ventana en segundos)`.
+pub type BucketKey = (String, i64, i64);
 pub type SensorDataVector = Vec<Measurement>;
 pub type StateMap = Arc<DashMap<BucketKey, SensorDataVector>>;
+/// Watermark de tiempo de evento por `network_id`.
+pub type WatermarkMap = Arc<DashMap<String, Watermark>>;
 
 
 #[derive(Clone, Debug)]
 pub struct AppContext {
-    pub repo: Repository,
+    /// Persistencia: Postgres en producción, `MemoryStorage` en los tests.
+    pub repo: Arc<dyn Storage>,
     pub system: Arc<System>,
     pub telegram_notifier: TelegramNotifier,
     pub bucket_map: Arc<DashMap<BucketKey, SensorDataVector>>,
+    pub watermarks: WatermarkMap,
+    /// Muestras acumuladas en `bucket_map`, para aplicar `BUCKET_MAX_SAMPLES` sin recorrerlo.
+    pub bucket_samples: Arc<AtomicUsize>,
+    /// Buckets abiertos en `bucket_map` por red, para aplicar `BUCKET_MAX_OPEN_PER_NETWORK`
+    /// sin recorrerlo.
+    pub open_buckets: Arc<DashMap<String, usize>>,
+    /// Buckets quitados de `bucket_map` desde el arranque. El sweeper reescribe el checkpoint
+    /// apenas cambia, para que un bucket ya emitido no se restaure tras una caída.
+    pub bucket_closes: Arc<AtomicU64>,
+    pub spill_stats: Arc<SpillStats>,
+    pub grpc_status: ConnectionStatusMap,
+    /// TLS hacia el Edge (`GRPC_MODE=client`).
+    pub grpc_tls: Option<ClientTlsConfig>,
+    /// TLS del servidor de ingesta (`GRPC_MODE=server`).
+    pub grpc_server_tls: Option<ServerTlsConfig>,
+    /// Credenciales hacia el Edge (`GRPC_MODE=client`).
+    pub grpc_auth: Option<Arc<AuthCredentials>>,
+    /// Secretos de cada Edge del servidor de ingesta (`GRPC_MODE=server`).
+    pub grpc_clients: Option<Arc<ClientSecrets>>,
+    pub shutdown: ShutdownSignal,
+    pub tasks: TaskRegistry,
+    pub metrics: Arc<Metrics>,
+    pub plausibility: Arc<PlausibilityStore>,
 }
 
 
 impl AppContext {
+
+    /// Crea el contexto de producción: lee la configuración del entorno y espera a que
+    /// Postgres esté disponible (ver [`Repository::create_repository`]).
     pub async fn new() -> Self {
-        
+        let system = match System::new() {
+            Ok(system) => system,
+            Err(e) => panic!("Error: no se pudo crear system. {}", e),
+        };
+
+        Self::with_storage(system, |system, metrics| async move {
+            Arc::new(Repository::create_repository(&system, metrics).await) as Arc<dyn Storage>
+        }).await
+    }
+
+    /// Crea el contexto con la configuración `system` y la persistencia que arma `storage`.
+    ///
+    /// `storage` se invoca después de validar el resto de la configuración, de modo que un
+    /// error de configuración se informa sin esperar a la base de datos.
+    pub async fn with_storage<F, Fut>(system: System, storage: F) -> Self
+    where
+        F: FnOnce(Arc<System>, Arc<Metrics>) -> Fut,
+        Fut: Future<Output = Arc<dyn Storage>>,
+    {
         info!("Info: creando app context");
 
         let bucket_map: StateMap = Arc::new(DashMap::new());
-        
-        let system = Arc::new(
-            match System::new() {
-                Ok(system) => system,
-                Err(e) => panic!("Error: no se pudo crear system. {}", e),
+        let watermarks: WatermarkMap = Arc::new(DashMap::new());
+        let bucket_samples = Arc::new(AtomicUsize::new(0));
+        let open_buckets = Arc::new(DashMap::new());
+        let bucket_closes = Arc::new(AtomicU64::new(0));
+
+        let system = Arc::new(system);
+
+        let tls = match system.grpc_mode {
+            GrpcMode::Client => load_tls_config(&system).map(|tls| (tls, None)),
+            GrpcMode::Server => load_server_tls_config(&system).map(|tls| (None, tls)),
+        };
+        let (grpc_tls, grpc_server_tls) = match tls {
+            Ok(tls) => tls,
+            Err(e) => panic!("Error: configuración TLS de gRPC inválida. {}", e),
+        };
+
+        let metrics = Arc::new(
+            match Metrics::new() {
+                Ok(metrics) => metrics,
+                Err(e) => panic!("Error: no se pudieron registrar las métricas. {}", e),
             }
         );
+
+        let plausibility = Arc::new(
+            match PlausibilityStore::load(system.plausibility_config.as_deref()) {
+                Ok(plausibility) => plausibility,
+                Err(e) => panic!("Error: rangos plausibles inválidos. {}", e),
+            }
+        );
+
+        let auth = match system.grpc_mode {
+            GrpcMode::Client => AuthCredentials::from_system(&system).map(|auth| (auth, None)),
+            GrpcMode::Server => ClientSecrets::from_system(&system).map(|clients| (None, clients)),
+        };
+        let (grpc_auth, grpc_clients) = match auth {
+            Ok(auth) => auth,
+            Err(e) => panic!("Error: configuración de autenticación gRPC inválida. {}", e),
+        };
+
+        let repo = storage(system.clone(), metrics.clone()).await;
         
-        let repo = Repository::create_repository(&system).await;
-        
-        let telegram_notifier = match TelegramNotifier::new() {
+        let telegram_notifier = match TelegramNotifier::new(&system, metrics.clone()) {
             Ok(telegram_notifier) => telegram_notifier,
             Err(e) => panic!("Error: no se pudo crear telegram_notifier. {}", e),
         };
         
-        Self { repo, system, telegram_notifier, bucket_map }
+        let spill_stats = Arc::new(SpillStats::default());
+        let grpc_status: ConnectionStatusMap = Arc::new(DashMap::new());
+
+        let shutdown = ShutdownSignal::default();
+        let tasks = TaskRegistry::default();
+
+        Self { repo, system, telegram_notifier, bucket_map, watermarks, bucket_samples, open_buckets, bucket_closes, spill_stats, grpc_status, grpc_tls, grpc_server_tls, grpc_auth, grpc_clients, shutdown, tasks, metrics, plausibility }
     }
 }
\ No newline at end of file
@@ -0,0 +1,120 @@
+//! Benchmark de inserción masiva, como test ignorado:
+//!
+//! ```bash
+//! BENCH_DATABASE_URL=postgres://... BENCH_ROWS=100000 \
+//!     cargo test --release bulk_insert -- --ignored --nocapture
+//! ```
+//!
+//! Inserta lotes sintéticos de `monitor` y `metric` por los dos caminos de lotes grandes
+//! ([`insert_chunked`] e [`insert_copy`]), informa el tiempo y las filas por segundo de cada uno
+//! y elimina las filas insertadas. Usa su propio pool contra `BENCH_DATABASE_URL`, al que le
+//! aplica las migraciones; las filas usan emisores `bench-*`, pero conviene apuntarlo a una base
+//! de pruebas.
+
+
+use std::env;
+use std::time::Instant;
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+use crate::database::repository::MIGRATOR;
+use crate::database::tables::bulk::{insert_chunked, insert_copy, BulkRow, InsertPath};
+use crate::message::domain::{Metadata, Monitor, SystemMetrics};
+
+
+/// Filas por lote si no se define `BENCH_ROWS`.
+const DEFAULT_ROWS: usize = 20_000;
+
+
+/// Corre el benchmark para `monitor` y `metric` con lotes de `BENCH_ROWS` filas.
+#[tokio::test]
+#[ignore = "requiere BENCH_DATABASE_URL apuntando a un Postgres de pruebas"]
+async fn bulk_insert() {
+    let url = env::var("BENCH_DATABASE_URL").expect("BENCH_DATABASE_URL no está definida");
+    let rows = env::var("BENCH_ROWS").map_or(DEFAULT_ROWS, |rows| rows.parse().expect("BENCH_ROWS debe ser un número"));
+
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&url)
+        .await
+        .expect("no se pudo conectar a BENCH_DATABASE_URL");
+    MIGRATOR.run(&pool).await.expect("no se pudieron aplicar las migraciones");
+
+    println!("benchmark de inserción masiva con lotes de {rows} filas");
+
+    bench_table(&pool, rows, |run, i| Monitor {
+        metadata: metadata(run, i),
+        network: format!("bench-net-{}", i % 8),
+        mem_free: 120_000 + i as i64,
+        mem_free_hm: 90_000,
+        mem_free_block: 60_000,
+        mem_free_internal: 30_000,
+        stack_free_min_coll: 1_024,
+        stack_free_min_pub: 2_048,
+        stack_free_min_mic: 512,
+        stack_free_min_th: 768,
+        stack_free_min_air: 896,
+        stack_free_min_mon: 640,
+        wifi_ssid: "bench".to_string(),
+        wifi_rssi: -60,
+        active_time: i as i64,
+    }).await;
+
+    bench_table(&pool, rows, |run, i| SystemMetrics {
+        metadata: metadata(run, i),
+        uptime_seconds: i as u64,
+        cpu_usage_percent: 12.5,
+        cpu_temp_celsius: 48.0,
+        ram_total_mb: 4_096,
+        ram_used_mb: 1_024,
+        sd_total_gb: 64,
+        sd_used_gb: 12,
+        sd_usage_percent: 18.75,
+        network_rx_bytes: 1_000_000 + i as u64,
+        network_tx_bytes: 500_000 + i as u64,
+        wifi_rssi: (i % 2 == 0).then_some(-60),
+        wifi_signal_dbm: None,
+    }).await;
+}
+
+
+/// Inserta un lote por cada camino, con emisores distintos para que ninguna fila choque con
+/// la clave natural, y luego las elimina.
+async fn bench_table<T, F>(pool: &PgPool, rows: usize, make: F)
+where
+    T: BulkRow,
+    F: Fn(&str, usize) -> T,
+{
+    for (path, label) in [(InsertPath::Chunked, "chunked"), (InsertPath::Copy, "copy")] {
+        let run = format!("bench-{label}");
+        let batch: Vec<T> = (0..rows).map(|i| make(&run, i)).collect();
+
+        let mut conn = pool.acquire().await.expect("no se pudo obtener una conexión");
+
+        let started = Instant::now();
+        let result = match path {
+            InsertPath::Copy => insert_copy(&mut conn, batch).await,
+            _ => insert_chunked(&mut conn, batch).await,
+        };
+        let elapsed = started.elapsed();
+
+        if let Err(e) = result {
+            panic!("el benchmark de {} por {} falló. {}", T::TABLE, label, e);
+        }
+        println!("{:<7} {:<8} {:>8} filas en {:>8.1} ms ({:>10.0} filas/s)",
+            T::TABLE, label, rows, elapsed.as_secs_f64() * 1000.0, rows as f64 / elapsed.as_secs_f64());
+    }
+
+    let cleanup = format!("DELETE FROM {} WHERE sender_user_id LIKE 'bench-%'", T::TABLE);
+    sqlx::query(&cleanup).execute(pool).await.expect("no se pudieron eliminar las filas del benchmark");
+}
+
+
+/// Emisor propio de cada camino y timestamps únicos por fila.
+fn metadata(run: &str, i: usize) -> Metadata {
+    Metadata {
+        sender_user_id: format!("{}-{}", run, i % 16),
+        destination_id: "bench".to_string(),
+        timestamp: 1_700_000_000 + i as i64,
+        sequence_id: 0,
+    }
+}
@@ -0,0 +1,251 @@
+//! Buffer de escritura de `dba_task`.
+//!
+//! Los mensajes de las tablas de eventos (`monitor`, `alert_air`, `alert_temp`, `metric`) se
+//! acumulan por tabla y se insertan juntos en una transacción cuando el buffer alcanza
+//! `DB_BATCH_MAX_ROWS` filas o su mensaje más antiguo cumple `DB_BATCH_MAX_AGE_MS`.
+
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{Duration, Instant};
+use crate::message::domain::{AlertAir, AlertTh, Delivery, Message, Monitor, SystemMetrics};
+
+
+/// Filas de las tablas de eventos que se confirman en una misma transacción.
+///
+/// Es serializable para poder escribirse completa en la cola de desborde.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WriteBatch {
+    pub monitor: Vec<Monitor>,
+    pub alert_air: Vec<AlertAir>,
+    pub alert_temp: Vec<AlertTh>,
+    pub metrics: Vec<SystemMetrics>,
+}
+
+
+impl WriteBatch {
+
+    /// Agrega las filas del mensaje a su tabla.
+    ///
+    /// # Retorno
+    /// `false` si el mensaje no corresponde a una tabla de eventos (ej. `Heartbeat`, `Ack`).
+    pub fn push(&mut self, msg: Message) -> bool {
+        match msg {
+            Message::Monitor(m) => self.monitor.push(m),
+            Message::AlertAir(m) => self.alert_air.push(m),
+            Message::AlertTem(m) => self.alert_temp.push(m),
+            Message::Metrics(m) => self.metrics.push(m),
+            Message::MonitorBatch(b) => self.monitor.extend(b),
+            Message::AlertAirBatch(b) => self.alert_air.extend(b),
+            Message::AlertTemBatch(b) => self.alert_temp.extend(b),
+            Message::Heartbeat(_) | Message::Ack(_) | Message::Nack(_) => return false,
+        }
+        true
+    }
+
+    /// Agrega las filas de otro lote a continuación de las propias.
+    pub fn append(&mut self, other: WriteBatch) {
+        self.monitor.extend(other.monitor);
+        self.alert_air.extend(other.alert_air);
+        self.alert_temp.extend(other.alert_temp);
+        self.metrics.extend(other.metrics);
+    }
+
+    pub fn rows(&self) -> usize {
+        self.monitor.len() + self.alert_air.len() + self.alert_temp.len() + self.metrics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows() == 0
+    }
+
+    /// Filas por tabla, con el nombre de la tabla en la base de datos.
+    pub fn table_rows(&self) -> [(&'static str, usize); 4] {
+        [
+            ("monitor", self.monitor.len()),
+            ("alert_air", self.alert_air.len()),
+            ("alert_temp", self.alert_temp.len()),
+            ("metric", self.metrics.len()),
+        ]
+    }
+
+    /// Entregas del Edge que se confirman al persistir el lote.
+    pub fn deliveries(&self) -> Vec<Delivery> {
+        self.monitor.iter().map(|m| &m.metadata)
+            .chain(self.alert_air.iter().map(|m| &m.metadata))
+            .chain(self.alert_temp.iter().map(|m| &m.metadata))
+            .chain(self.metrics.iter().map(|m| &m.metadata))
+            .filter_map(|metadata| metadata.delivery())
+            .collect()
+    }
+
+    /// Separa el lote en lotes de una fila, para aislar la que la base de datos rechaza.
+    pub fn into_rows(self) -> Vec<WriteBatch> {
+        let mut rows = Vec::with_capacity(self.rows());
+        rows.extend(self.monitor.into_iter().map(|m| WriteBatch { monitor: vec![m], ..Default::default() }));
+        rows.extend(self.alert_air.into_iter().map(|m| WriteBatch { alert_air: vec![m], ..Default::default() }));
+        rows.extend(self.alert_temp.into_iter().map(|m| WriteBatch { alert_temp: vec![m], ..Default::default() }));
+        rows.extend(self.metrics.into_iter().map(|m| WriteBatch { metrics: vec![m], ..Default::default() }));
+        rows
+    }
+}
+
+
+impl From<Message> for WriteBatch {
+    fn from(msg: Message) -> Self {
+        let mut batch = WriteBatch::default();
+        batch.push(msg);
+        batch
+    }
+}
+
+
+/// Motivo del vaciado del buffer (etiqueta `trigger` de `db_flushes_total`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushTrigger {
+    /// Se alcanzó `DB_BATCH_MAX_ROWS`.
+    Size,
+    /// El mensaje más antiguo cumplió `DB_BATCH_MAX_AGE_MS`.
+    Age,
+}
+
+
+impl FlushTrigger {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FlushTrigger::Size => "size",
+            FlushTrigger::Age => "age",
+        }
+    }
+}
+
+
+/// Acumulador por tabla de `dba_task`.
+#[derive(Debug)]
+pub struct WriteBuffer {
+    batch: WriteBatch,
+    /// Mensajes acumulados; cada uno cuenta como una operación en el `DrainReport`.
+    messages: u64,
+    /// Llegada del mensaje más antiguo del buffer.
+    oldest: Option<Instant>,
+    max_rows: usize,
+    max_age: Duration,
+}
+
+
+impl WriteBuffer {
+
+    pub fn new(max_rows: usize, max_age: Duration) -> Self {
+        Self {
+            batch: WriteBatch::default(),
+            messages: 0,
+            oldest: None,
+            max_rows,
+            max_age,
+        }
+    }
+
+    /// Acumula el mensaje. Devuelve `false` si no tiene filas que persistir.
+    pub fn push(&mut self, msg: Message) -> bool {
+        if !self.batch.push(msg) {
+            return false;
+        }
+        self.messages += 1;
+        self.oldest.get_or_insert_with(Instant::now);
+        true
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.batch.rows() >= self.max_rows
+    }
+
+    /// Momento en que vence el mensaje más antiguo, o `None` si el buffer está vacío.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.oldest.map(|oldest| oldest + self.max_age)
+    }
+
+    /// Vacía el buffer y devuelve el lote junto con la cantidad de mensajes que contenía.
+    pub fn take(&mut self) -> (WriteBatch, u64) {
+        self.oldest = None;
+        (std::mem::take(&mut self.batch), std::mem::take(&mut self.messages))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::domain::{Ack, Metadata};
+
+    fn metadata(sequence_id: u64) -> Metadata {
+        Metadata { sender_user_id: "hub-a".to_string(), sequence_id, ..Default::default() }
+    }
+
+    fn monitor(sequence_id: u64) -> Monitor {
+        Monitor { metadata: metadata(sequence_id), ..Default::default() }
+    }
+
+    fn alert(sequence_id: u64) -> AlertAir {
+        AlertAir { metadata: metadata(sequence_id), ..Default::default() }
+    }
+
+    fn sequence_ids(batch: &WriteBatch) -> Vec<u64> {
+        batch.deliveries().iter().map(|delivery| delivery.sequence_id).collect()
+    }
+
+    #[test]
+    fn fills_by_rows_not_messages() {
+        let mut buffer = WriteBuffer::new(3, Duration::from_secs(60));
+        assert!(buffer.push(Message::MonitorBatch(vec![monitor(1), monitor(2)])));
+        assert!(!buffer.is_full());
+        assert!(buffer.push(Message::AlertAir(alert(3))));
+        assert!(buffer.is_full());
+
+        let (batch, messages) = buffer.take();
+        assert_eq!((batch.rows(), messages), (3, 2));
+        assert!(!buffer.is_full());
+    }
+
+    #[test]
+    fn deadline_follows_the_oldest_message() {
+        let max_age = Duration::from_millis(500);
+        let mut buffer = WriteBuffer::new(10, max_age);
+        assert_eq!(buffer.deadline(), None);
+
+        let before = Instant::now();
+        buffer.push(Message::Monitor(monitor(1)));
+        let deadline = buffer.deadline().expect("sin plazo con un mensaje");
+        assert!(deadline >= before + max_age && deadline <= Instant::now() + max_age);
+
+        std::thread::sleep(Duration::from_millis(5));
+        buffer.push(Message::Monitor(monitor(2)));
+        assert_eq!(buffer.deadline(), Some(deadline));
+
+        buffer.take();
+        assert_eq!(buffer.deadline(), None);
+    }
+
+    #[test]
+    fn ignores_messages_without_rows() {
+        let mut buffer = WriteBuffer::new(1, Duration::from_secs(60));
+        let ack = Message::Ack(Ack { metadata: metadata(1), sequence_ids: vec![1] });
+        assert!(!buffer.push(ack));
+        assert_eq!(buffer.deadline(), None);
+        assert_eq!(buffer.take().1, 0);
+    }
+
+    #[test]
+    fn splits_into_single_row_batches_in_table_order() {
+        let batch = WriteBatch {
+            monitor: vec![monitor(1), monitor(2)],
+            alert_air: vec![alert(3)],
+            ..Default::default()
+        };
+        let rows = batch.clone().into_rows();
+
+        assert_eq!(rows.len(), batch.rows());
+        assert!(rows.iter().all(|row| row.rows() == 1));
+        assert_eq!(rows.iter().flat_map(sequence_ids).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(rows[2].alert_air.len(), 1);
+        assert!(WriteBatch::default().into_rows().is_empty());
+    }
+}
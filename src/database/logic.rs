@@ -1,98 +1,545 @@
 //! Tarea administradora de base de datos (DBA).
 //!
-//! Este módulo implementa un patrón de **Buffering** o **Batching**.
-//! En lugar de realizar una transacción SQL por cada mensaje recibido (lo cual sería lento e ineficiente),
-//! esta tarea acumula los mensajes en memoria y los inserta en lotes (chunks) cuando alcanzan
-//! cierto tamaño.
+//! # Buffer de escritura
+//! Los mensajes de las tablas de eventos (`Monitor`, `AlertAir`, `AlertTem`, `Metrics` y sus
+//! lotes) no se insertan de a uno: se acumulan por tabla en un [`WriteBuffer`] y se vacían
+//! cuando el buffer alcanza `DB_BATCH_MAX_ROWS` filas o cuando su mensaje más antiguo cumple
+//! `DB_BATCH_MAX_AGE_MS`. Cada vaciado se confirma en una única transacción: sus Ack se envían
+//! juntos o el lote completo sigue el camino de reintentos y cola de desborde. Si la base de
+//! datos rechaza alguna fila, el lote se reinserta fila por fila para descartar solo las
+//! rechazadas. La telemetría agregada, las muestras tardías y el clima se insertan al recibirse.
+//!
+//! # Cola de desborde
+//! Las operaciones que agotan los reintentos no se descartan: se escriben en la cola de
+//! desborde en disco (`spill::domain::SpillQueue`) y se reproducen en orden cuando la base
+//! de datos vuelve a responder. Mientras queden registros pendientes en disco, las nuevas
+//! operaciones se encolan detrás de ellos para preservar el orden de llegada.
+//!
+//! # Confirmaciones
+//! Cuando una operación se confirma en la base de datos, se envía un `Message::Ack` con los
+//! `sequence_id` que contenía hacia `message_upload`. Si el dato se descarta definitivamente
+//! se envía un `Message::Nack`. Un dato en la cola de desborde todavía no se confirma: el Ack
+//! llega cuando se reproduce.
+//!
+//! # Apagado
+//! Tras la señal de apagado no se esperan reintentos: una inserción fallida va directo a la
+//! cola de desborde. La tarea termina cuando se cierran todos sus canales de entrada y
+//! devuelve un [`DrainReport`] con lo procesado desde la señal.
 
 
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
-use tokio::time::{Duration};
-use tracing::{error, info, instrument};
-use crate::bucket::logic::{ProcessedTelemetry};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep_until, Duration, Instant};
+use tracing::{debug, error, info, instrument, warn};
+use crate::bucket::domain::LatePolicy;
+use crate::bucket::logic::{LateSample, ProcessedTelemetry, Unbucketed};
 use crate::context::domain::AppContext;
-use crate::message::domain::Message;
+use crate::database::domain::{FlushTrigger, WriteBatch, WriteBuffer};
+use crate::message::domain::{Ack, Delivery, Message, Metadata, Nack};
+use crate::shutdown::domain::DrainReport;
+use crate::spill::domain::{SpillQueue, SpillStats};
+use crate::supervisor::domain::shared;
+use crate::supervisor::logic::supervise;
+use crate::system::domain::System;
 use crate::weather::domain::Weather;
 
 
+/// Unidad de trabajo de `dba_task`. Es serializable para poder persistirse en la
+/// cola de desborde.
+#[derive(Debug, Serialize, Deserialize)]
 enum DbOperation {
+    /// Mensaje individual; se persiste como un lote de un mensaje.
     Msg(Message),
-    Telemetry(ProcessedTelemetry),
+    Batch(WriteBatch),
+    Telemetry(Box<ProcessedTelemetry>),
+    Late(LateSample),
     Weather(Weather),
 }
 
 
+impl DbOperation {
+    /// Entregas del Edge que se confirman al persistir esta operación.
+    fn deliveries(&self) -> Vec<Delivery> {
+        match self {
+            DbOperation::Msg(msg) => msg.deliveries(),
+            DbOperation::Batch(batch) => batch.deliveries(),
+            DbOperation::Telemetry(telemetry) => telemetry.deliveries.clone(),
+            DbOperation::Late(late) => late.measurement.metadata.delivery().into_iter().collect(),
+            DbOperation::Weather(_) => Vec::new(),
+        }
+    }
+}
+
+
 
 #[instrument(
     name = "dba_task",
-    skip(rx, app_context)
+    skip(rx, rx_from_sweeper, rx_from_bucket, rx_from_weather, tx_ack, app_context)
 )]
-pub async fn dba_task(mut rx: mpsc::Receiver<Message>,
-                      mut rx_from_sweeper: mpsc::Receiver<ProcessedTelemetry>,
-                      mut rx_from_weather: mpsc::Receiver<Weather>,
-                      app_context: AppContext) {
+pub async fn dba_task(rx: &mut mpsc::Receiver<Message>,
+                      rx_from_sweeper: &mut mpsc::Receiver<ProcessedTelemetry>,
+                      rx_from_bucket: &mut mpsc::Receiver<Unbucketed>,
+                      rx_from_weather: &mut mpsc::Receiver<Weather>,
+                      tx_ack: mpsc::Sender<Message>,
+                      app_context: AppContext) -> DrainReport {
 
     info!("Info: dba task creada");
 
+    let mut drain = DrainReport::default();
+
+    let mut spill = open_spill(&app_context.system, app_context.spill_stats.clone());
+    let mut replay_ticker = interval(Duration::from_secs(app_context.system.spill_replay_interval_secs));
+    let mut buffer = WriteBuffer::new(app_context.system.db_batch_max_rows,
+                                      Duration::from_millis(app_context.system.db_batch_max_age_ms));
+
     loop {
+        let has_pending = spill.as_ref().is_some_and(|queue| queue.has_pending());
+        let shutting_down = app_context.shutdown.is_triggered();
+        let flush_deadline = buffer.deadline();
+
         let operation = tokio::select! {
-            Some(msg) = rx.recv() => DbOperation::Msg(msg),
-            Some(telemetry) = rx_from_sweeper.recv() => DbOperation::Telemetry(telemetry),
+            Some(msg) = rx.recv() => {
+                if buffer.push(msg) && buffer.is_full() {
+                    flush_buffer(&app_context, &mut buffer, FlushTrigger::Size, &mut spill, &tx_ack, &mut drain).await;
+                }
+                continue;
+            }
+            Some(telemetry) = rx_from_sweeper.recv() => DbOperation::Telemetry(Box::new(telemetry)),
+            Some(unbucketed) = rx_from_bucket.recv() => match (unbucketed, app_context.system.late_data_policy) {
+                (Unbucketed::Late(late), LatePolicy::Table) => DbOperation::Late(late),
+                (Unbucketed::Late(late), LatePolicy::Drop) => {
+                    send_nack(&tx_ack, late.measurement.metadata.delivery().into_iter().collect(),
+                              "muestra tardía: su bucket ya se cerró".to_string());
+                    continue;
+                }
+                (Unbucketed::Rejected { measurement, reason }, _) => {
+                    send_nack(&tx_ack, measurement.metadata.delivery().into_iter().collect(), reason);
+                    continue;
+                }
+            },
             Some(weather) = rx_from_weather.recv() => DbOperation::Weather(weather),
+            // Con el buffer vacío no hay plazo; al cerrarse los canales se vacía por antigüedad
+            _ = sleep_until(flush_deadline.unwrap_or_else(Instant::now)), if flush_deadline.is_some() => {
+                flush_buffer(&app_context, &mut buffer, FlushTrigger::Age, &mut spill, &tx_ack, &mut drain).await;
+                continue;
+            }
+            // Durante el apagado no se reproduce: lo pendiente queda en disco para el próximo arranque
+            _ = replay_ticker.tick(), if has_pending && !shutting_down => {
+                if let Some(queue) = spill.as_mut() {
+                    replay_spill(&app_context, queue, &tx_ack).await;
+                }
+                continue;
+            }
             else => {
                 info!("Info: canales cerrados, finalizando dba_task");
                 break;
             }
         };
 
-        execute_with_retry(&app_context, operation).await;
+        persist(&app_context, operation, 1, &mut spill, &tx_ack, &mut drain).await;
+    }
+
+    drain
+}
+
+
+/// Vacía el buffer en una transacción y registra la duración, las filas y el ritmo del vaciado.
+async fn flush_buffer(app_context: &AppContext,
+                      buffer: &mut WriteBuffer,
+                      trigger: FlushTrigger,
+                      spill: &mut Option<SpillQueue<DbOperation>>,
+                      tx_ack: &mpsc::Sender<Message>,
+                      drain: &mut DrainReport) {
+    let (batch, messages) = buffer.take();
+    let rows = batch.rows();
+
+    let started = Instant::now();
+    let persisted = persist(app_context, DbOperation::Batch(batch), messages, spill, tx_ack, drain).await;
+    let elapsed = started.elapsed();
+
+    debug!("Debug: vaciado del buffer por {}: {} mensajes, {} filas en {:?}", trigger.label(), messages, rows, elapsed);
+    app_context.metrics.record_flush(trigger.label(), rows, elapsed, persisted);
+}
+
+
+/// Persiste la operación y envía sus Ack. Si quedan registros pendientes en disco o se
+/// agotan los reintentos, la escribe en la cola de desborde.
+///
+/// Tras la señal de apagado, cuenta el resultado `operations` veces en el `DrainReport`
+/// (un lote cuenta cada mensaje que contiene).
+///
+/// # Retorno
+/// `true` si la operación quedó confirmada en la base de datos.
+async fn persist(app_context: &AppContext,
+                 operation: DbOperation,
+                 operations: u64,
+                 spill: &mut Option<SpillQueue<DbOperation>>,
+                 tx_ack: &mpsc::Sender<Message>,
+                 drain: &mut DrainReport) -> bool {
+    let has_pending = spill.as_ref().is_some_and(|queue| queue.has_pending());
+
+    let leftover = if has_pending {
+        Some(operation)
+    } else {
+        match execute_with_retry(app_context, &operation).await {
+            Ok(_) => {
+                send_ack(tx_ack, operation.deliveries());
+                None
+            }
+            Err(sqlx::Error::Database(e)) => match operation {
+                DbOperation::Batch(batch) if batch.rows() > 1 => {
+                    warn!("Warning: la base de datos rechazó el lote, se inserta fila por fila. {}", e);
+                    isolate_rejected(app_context, batch, tx_ack).await.map(DbOperation::Batch)
+                }
+                // Reintentarla desde la cola de desborde fallaría igual: se descarta con Nack
+                operation @ (DbOperation::Batch(_) | DbOperation::Telemetry(_) | DbOperation::Late(_)) => {
+                    error!("Error: la base de datos rechazó la fila. Dato descartado. {}", e);
+                    send_nack(tx_ack, operation.deliveries(), e.to_string());
+                    None
+                }
+                operation => Some(operation),
+            },
+            Err(_) => Some(operation),
+        }
+    };
+
+    let persisted = leftover.is_none();
+    let spilled = leftover.is_some_and(|operation| spill_operation(spill, operation, tx_ack));
+
+    if app_context.shutdown.is_triggered() {
+        match (persisted, spilled) {
+            (true, _) => drain.persisted += operations,
+            (false, true) => drain.spilled += operations,
+            (false, false) => drain.dropped += operations,
+        }
     }
+    persisted
 }
 
 
-/// Helper para intentar la inserción hasta 5 veces antes de descartar el dato
-async fn execute_with_retry(app_context: &AppContext, op: DbOperation) {
+/// Inserta el lote fila por fila, con un único intento por fila. Confirma las aceptadas y
+/// descarta con Nack las que la base de datos rechaza.
+///
+/// # Retorno
+/// Las filas que fallaron por otro motivo (ej. conexión perdida), para la cola de desborde.
+async fn isolate_rejected(app_context: &AppContext,
+                          batch: WriteBatch,
+                          tx_ack: &mpsc::Sender<Message>) -> Option<WriteBatch> {
+    let mut leftover = WriteBatch::default();
+
+    for row in batch.into_rows() {
+        match app_context.repo.insert_write_batch(row.clone()).await {
+            Ok(_) => send_ack(tx_ack, row.deliveries()),
+            Err(sqlx::Error::Database(e)) => {
+                error!("Error: la base de datos rechazó una fila del lote. Dato descartado. {}", e);
+                send_nack(tx_ack, row.deliveries(), e.to_string());
+            }
+            Err(_) => leftover.append(row),
+        }
+    }
+
+    (!leftover.is_empty()).then_some(leftover)
+}
+
+
+/// Ejecuta una única vez la inserción correspondiente a la operación.
+async fn execute(app_context: &AppContext, op: &DbOperation) -> Result<(), sqlx::Error> {
+    match op {
+        DbOperation::Msg(msg) => app_context.repo.insert_write_batch(WriteBatch::from(msg.clone())).await,
+        DbOperation::Batch(batch) => app_context.repo.insert_write_batch(batch.clone()).await,
+        DbOperation::Telemetry(telemetry) => app_context.repo.insert_telemetry((**telemetry).clone()).await,
+        DbOperation::Late(late) => app_context.repo.insert_late_sample(late.clone()).await,
+        DbOperation::Weather(weather) => app_context.repo.insert_weather_data(weather.clone()).await,
+    }
+}
+
+
+/// Helper para intentar la inserción hasta 5 veces.
+///
+/// Si se agotan los intentos, devuelve el último error para que la operación sea enviada
+/// a la cola de desborde. Durante el apagado deja de reintentar para no agotar el plazo.
+async fn execute_with_retry(app_context: &AppContext, op: &DbOperation) -> Result<(), sqlx::Error> {
     let mut counter: u8 = 1;
 
     loop {
-        let result = match &op {
-            DbOperation::Msg(msg) => app_context.repo.insert_message(msg.clone()).await,
-            DbOperation::Telemetry(telemetry) => app_context.repo.insert_telemetry(telemetry.clone()).await,
-            DbOperation::Weather(weather) => app_context.repo.insert_weather_data(weather.clone()).await,
-        };
-
-        match result {
-            Ok(_) => break, // Éxito, salimos del reintento
+        match execute(app_context, op).await {
+            Ok(_) => return Ok(()), // Éxito, salimos del reintento
             Err(e) => {
                 if counter == 5 {
-                    error!("Error: se acabaron los 5 intentos para insertar en DB. Dato descartado.");
-                    break;
+                    error!("Error: se acabaron los 5 intentos para insertar en DB. Enviando a la cola de desborde.");
+                    return Err(e);
+                }
+                if app_context.shutdown.is_triggered() {
+                    warn!("Warning: apagado en curso, no se reintenta la inserción. Enviando a la cola de desborde. {e}");
+                    return Err(e);
                 }
                 error!("Error al insertar en DB. Intento {counter}. Reintentando en 5s. Detalle: {e}");
+                app_context.metrics.db_retries.inc();
                 counter += 1;
-                tokio::time::sleep(Duration::from_secs(5)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(5)) => {},
+                    _ = app_context.shutdown.wait() => {},
+                }
+            }
+        }
+    }
+}
+
+
+/// Abre la cola de desborde configurada en `System`.
+///
+/// Si no se puede abrir (ej. permisos del directorio), `dba_task` sigue funcionando
+/// pero descarta los datos que agoten los reintentos, como antes de existir la cola.
+fn open_spill(system: &System, stats: Arc<SpillStats>) -> Option<SpillQueue<DbOperation>> {
+    match SpillQueue::open(&system.spill_dir,
+                           system.spill_max_segment_bytes,
+                           system.spill_max_total_bytes,
+                           system.spill_fsync,
+                           stats) {
+        Ok(queue) => Some(queue),
+        Err(e) => {
+            error!("Error: no se pudo abrir la cola de desborde en {}. Los datos que agoten los reintentos se descartarán. {}", system.spill_dir, e);
+            None
+        }
+    }
+}
+
+
+/// Escribe la operación en la cola de desborde o la descarta (con Nack) si no es posible.
+///
+/// # Retorno
+/// `true` si la operación quedó en disco, `false` si se descartó.
+fn spill_operation(spill: &mut Option<SpillQueue<DbOperation>>,
+                   op: DbOperation,
+                   tx_ack: &mpsc::Sender<Message>) -> bool {
+    match spill {
+        Some(queue) => match queue.append(&op) {
+            Ok(_) => {
+                debug!("Debug: operación escrita en la cola de desborde");
+                true
             }
+            Err(e) => {
+                error!("Error: no se pudo escribir en la cola de desborde. Dato descartado. {}", e);
+                send_nack(tx_ack, op.deliveries(), e.to_string());
+                false
+            }
+        },
+        None => {
+            error!("Error: cola de desborde no disponible. Dato descartado.");
+            send_nack(tx_ack, op.deliveries(), "cola de desborde no disponible".to_string());
+            false
+        }
+    }
+}
+
+
+/// Reproduce la cola de desborde contra la base de datos, del registro más antiguo al más nuevo.
+///
+/// Antes de empezar verifica que el repositorio responda (`SELECT 1`). Ante el primer fallo
+/// transitorio se detiene y conserva el resto de los registros para el próximo intento, de
+/// modo que el orden original nunca se altera. Un registro rechazado por la propia base de
+/// datos (`sqlx::Error::Database`, ej. violación de restricción) se descarta para no bloquear
+/// la cola indefinidamente.
+async fn replay_spill(app_context: &AppContext,
+                      queue: &mut SpillQueue<DbOperation>,
+                      tx_ack: &mpsc::Sender<Message>) {
+    if let Err(e) = app_context.repo.ping().await {
+        debug!("Debug: base de datos aún no disponible, se pospone la reproducción de la cola de desborde. {}", e);
+        return;
+    }
+
+    info!("Info: reproduciendo cola de desborde");
+
+    loop {
+        let records = match queue.read_oldest() {
+            Ok(Some(records)) => records,
+            Ok(None) => break,
+            Err(e) => {
+                error!("Error: no se pudo leer la cola de desborde. {}", e);
+                return;
+            }
+        };
+
+        let mut replayed = 0;
+        let mut rejected = 0;
+        for op in &records {
+            match execute(app_context, op).await {
+                Ok(_) => {
+                    replayed += 1;
+                    send_ack(tx_ack, op.deliveries());
+                }
+                Err(sqlx::Error::Database(e)) => {
+                    error!("Error: la base de datos rechazó un registro de la cola de desborde. Dato descartado. {}", e);
+                    rejected += 1;
+                    send_nack(tx_ack, op.deliveries(), e.to_string());
+                }
+                Err(e) => {
+                    warn!("Warning: falló la reproducción de la cola de desborde, se reintentará. {}", e);
+                    break;
+                }
+            }
+        }
+
+        let consumed = replayed + rejected;
+        if let Err(e) = queue.complete_oldest(replayed, rejected, &records[consumed..]) {
+            error!("Error: no se pudo actualizar la cola de desborde. {}", e);
+            return;
+        }
+        if consumed < records.len() {
+            return;
+        }
+    }
+
+    let stats = &app_context.spill_stats;
+    info!("Info: cola de desborde vacía. Desbordados: {}, reproducidos: {}, descartados: {}",
+        stats.spilled.load(Ordering::Relaxed),
+        stats.replayed.load(Ordering::Relaxed),
+        stats.dropped.load(Ordering::Relaxed));
+}
+
+
+/// Envía un `Message::Ack` por cada emisor con los `sequence_id` persistidos.
+///
+/// Usa `try_send`: si `message_upload` no da abasto (ej. gRPC desconectado) la confirmación
+/// se pierde en lugar de frenar las inserciones. El Edge conserva su buffer y reenvía, por lo
+/// que la semántica resultante es *al menos una vez*.
+fn send_ack(tx_ack: &mpsc::Sender<Message>, deliveries: Vec<Delivery>) {
+    for (sender, sequence_ids) in group_by_sender(deliveries) {
+        let ack = Ack {
+            metadata: ack_metadata(sender),
+            sequence_ids,
+        };
+        if tx_ack.try_send(Message::Ack(ack)).is_err() {
+            warn!("Warning: no se pudo encolar el Ack hacia message_upload");
+        }
+    }
+}
+
+
+/// Envía un `Message::Nack` por cada emisor con los `sequence_id` descartados.
+fn send_nack(tx_ack: &mpsc::Sender<Message>, deliveries: Vec<Delivery>, reason: String) {
+    for (sender, sequence_ids) in group_by_sender(deliveries) {
+        let nack = Nack {
+            metadata: ack_metadata(sender),
+            sequence_ids,
+            reason: reason.clone(),
+        };
+        if tx_ack.try_send(Message::Nack(nack)).is_err() {
+            warn!("Warning: no se pudo encolar el Nack hacia message_upload");
         }
     }
 }
 
 
+fn group_by_sender(deliveries: Vec<Delivery>) -> BTreeMap<String, Vec<u64>> {
+    let mut grouped: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for delivery in deliveries {
+        grouped.entry(delivery.sender_user_id)
+            .or_default()
+            .push(delivery.sequence_id);
+    }
+    grouped
+}
+
+
+fn ack_metadata(destination_id: String) -> Metadata {
+    Metadata {
+        sender_user_id: "data_saver".to_string(),
+        destination_id,
+        timestamp: Utc::now().timestamp(),
+        sequence_id: 0,
+    }
+}
+
+
 /// Inicializa y lanza la tarea DBA en segundo plano.
 ///
 /// # Argumentos
 /// * `rx_from_msg`: Canal de entrada con los mensajes ya decodificados.
+/// * `tx_ack`: Canal hacia `message_upload` para las confirmaciones Ack/Nack.
 /// * `app_context`: Dependencias globales del sistema.
+///
+/// # Retorno
+/// El `JoinHandle` de la tarea supervisada, que finaliza al vaciarse durante el apagado.
 pub fn start_dba(rx_from_msg: mpsc::Receiver<Message>,
                  rx_from_sweeper: mpsc::Receiver<ProcessedTelemetry>,
+                 rx_from_bucket: mpsc::Receiver<Unbucketed>,
                  rx_from_weather: mpsc::Receiver<Weather>,
-                 app_context: AppContext) {
+                 tx_ack: mpsc::Sender<Message>,
+                 app_context: AppContext) -> JoinHandle<Option<DrainReport>> {
 
     info!("Info: iniciando tarea dba");
-    tokio::spawn(async move {
-        dba_task(rx_from_msg,
-                 rx_from_sweeper,
-                 rx_from_weather,
-                 app_context
-        ).await;
-    });
-}
\ No newline at end of file
+    let rx_from_msg = shared(rx_from_msg);
+    let rx_from_sweeper = shared(rx_from_sweeper);
+    let rx_from_bucket = shared(rx_from_bucket);
+    let rx_from_weather = shared(rx_from_weather);
+
+    supervise("dba_task", true, &app_context.clone(), move || {
+        let rx_from_msg = rx_from_msg.clone();
+        let rx_from_sweeper = rx_from_sweeper.clone();
+        let rx_from_bucket = rx_from_bucket.clone();
+        let rx_from_weather = rx_from_weather.clone();
+        let tx_ack = tx_ack.clone();
+        let app_context = app_context.clone();
+        async move {
+            dba_task(&mut *rx_from_msg.lock().await,
+                     &mut *rx_from_sweeper.lock().await,
+                     &mut *rx_from_bucket.lock().await,
+                     &mut *rx_from_weather.lock().await,
+                     tx_ack,
+                     app_context
+            ).await
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::domain::Monitor;
+
+    fn monitor() -> Monitor {
+        Monitor {
+            metadata: Metadata { sender_user_id: "hub-a".to_string(), destination_id: "data_saver".to_string(), timestamp: 1_700_000_000, sequence_id: 4 },
+            network: "net-a".to_string(),
+            mem_free: 1,
+            mem_free_hm: 2,
+            mem_free_block: 3,
+            mem_free_internal: 4,
+            stack_free_min_coll: 5,
+            stack_free_min_pub: 6,
+            stack_free_min_mic: 7,
+            stack_free_min_th: 8,
+            stack_free_min_air: 9,
+            stack_free_min_mon: 10,
+            wifi_ssid: "ssid".to_string(),
+            wifi_rssi: -60,
+            active_time: 11,
+        }
+    }
+
+    fn decode(json: &str) -> Message {
+        match serde_json::from_str::<DbOperation>(json).expect("registro ilegible") {
+            DbOperation::Msg(msg) => msg,
+            other => panic!("operación inesperada: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn spilled_messages_keep_their_variant() {
+        for msg in [Message::Monitor(monitor()), Message::MonitorBatch(vec![monitor(), monitor()])] {
+            let json = serde_json::to_string(&DbOperation::Msg(msg.clone())).unwrap();
+            assert_eq!(decode(&json), msg);
+        }
+
+        let ack = Message::Ack(Ack { metadata: monitor().metadata, sequence_ids: vec![1, 2] });
+        let json = serde_json::to_string(&DbOperation::Msg(ack.clone())).unwrap();
+        assert!(json.contains("\"Ack\""), "sin etiqueta de variante: {json}");
+        assert_eq!(decode(&json), ack);
+    }
+}
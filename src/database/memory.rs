@@ -0,0 +1,196 @@
+//! Implementación en memoria de [`Storage`] para los tests.
+//!
+//! Registra cada escritura tal como llega, sin claves únicas ni fusión de agregados, para que
+//! los tests verifiquen qué persistió cada tarea. [`MemoryStorage::set_available`] simula una
+//! base de datos caída y [`MemoryStorage::reject_sender`], filas que la base rechaza.
+
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use async_trait::async_trait;
+use sqlx::error::{DatabaseError, ErrorKind};
+use crate::bucket::logic::{LateSample, ProcessedTelemetry};
+use crate::database::domain::WriteBatch;
+use crate::database::storage::Storage;
+use crate::message::domain::Measurement;
+use crate::weather::domain::Weather;
+
+
+/// Escrituras registradas, en orden de llegada.
+#[derive(Debug, Default, Clone)]
+pub struct Recorded {
+    pub batches: Vec<WriteBatch>,
+    pub telemetry: Vec<ProcessedTelemetry>,
+    pub late_samples: Vec<LateSample>,
+    pub weather: Vec<Weather>,
+    pub raw_measurements: Vec<Measurement>,
+}
+
+
+#[derive(Debug)]
+pub struct MemoryStorage {
+    recorded: Mutex<Recorded>,
+    available: AtomicBool,
+    rejected_senders: Mutex<Vec<String>>,
+}
+
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self { recorded: Mutex::default(), available: AtomicBool::new(true), rejected_senders: Mutex::default() }
+    }
+}
+
+
+/// Rechazo permanente de una fila, como una violación de restricción en Postgres.
+#[derive(Debug)]
+struct Rejected(String);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for Rejected {}
+
+impl DatabaseError for Rejected {
+    fn message(&self) -> &str {
+        &self.0
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed("23514"))
+    }
+
+    fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::CheckViolation
+    }
+}
+
+
+impl MemoryStorage {
+
+    /// Copia de lo escrito hasta el momento.
+    pub fn recorded(&self) -> Recorded {
+        self.lock().clone()
+    }
+
+    /// Con `false`, todas las operaciones fallan con un error transitorio
+    /// (`sqlx::Error::PoolTimedOut`), como si la base de datos no respondiera.
+    pub fn set_available(&self, available: bool) {
+        self.available.store(available, Ordering::Relaxed);
+    }
+
+    /// Las escrituras de lotes con filas de `sender` fallan con `sqlx::Error::Database`.
+    pub fn reject_sender(&self, sender: &str) {
+        self.rejected_senders.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(sender.to_string());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Recorded> {
+        self.recorded.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn check(&self) -> Result<(), sqlx::Error> {
+        if self.available.load(Ordering::Relaxed) {
+            Ok(())
+        } else {
+            Err(sqlx::Error::PoolTimedOut)
+        }
+    }
+
+    /// Falla como Postgres ante una restricción violada si alguno de `senders` fue rechazado
+    /// con [`MemoryStorage::reject_sender`].
+    fn check_senders(&self, senders: impl IntoIterator<Item = String>) -> Result<(), sqlx::Error> {
+        let rejected = self.rejected_senders.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match senders.into_iter().find(|sender| rejected.contains(sender)) {
+            Some(sender) => Err(sqlx::Error::Database(Box::new(Rejected(format!("fila de {sender} rechazada"))))),
+            None => Ok(()),
+        }
+    }
+}
+
+
+#[async_trait]
+impl Storage for MemoryStorage {
+
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        self.check()
+    }
+
+    async fn insert_write_batch(&self, batch: WriteBatch) -> Result<(), sqlx::Error> {
+        self.check()?;
+        self.check_senders(batch.deliveries().into_iter().map(|delivery| delivery.sender_user_id))?;
+        self.lock().batches.push(batch);
+        Ok(())
+    }
+
+    async fn insert_telemetry(&self, telemetry: ProcessedTelemetry) -> Result<(), sqlx::Error> {
+        self.check()?;
+        self.check_senders(telemetry.senders.iter().cloned())?;
+        self.lock().telemetry.push(telemetry);
+        Ok(())
+    }
+
+    async fn replace_telemetry(&self, telemetry: ProcessedTelemetry) -> Result<(), sqlx::Error> {
+        self.check()?;
+        let mut recorded = self.lock();
+        recorded.telemetry.retain(|row| {
+            (&row.network_id, row.timestamp, row.window_secs)
+                != (&telemetry.network_id, telemetry.timestamp, telemetry.window_secs)
+        });
+        recorded.telemetry.push(telemetry);
+        Ok(())
+    }
+
+    async fn insert_late_sample(&self, sample: LateSample) -> Result<(), sqlx::Error> {
+        self.check()?;
+        self.check_senders([sample.measurement.metadata.sender_user_id.clone()])?;
+        self.lock().late_samples.push(sample);
+        Ok(())
+    }
+
+    async fn insert_weather_data(&self, weather: Weather) -> Result<(), sqlx::Error> {
+        self.check()?;
+        self.lock().weather.push(weather);
+        Ok(())
+    }
+
+    async fn insert_raw_measurements(&self, measurements: Vec<Measurement>) -> Result<(), sqlx::Error> {
+        self.check()?;
+        self.lock().raw_measurements.extend(measurements);
+        Ok(())
+    }
+
+    async fn raw_measurements(&self, network_id: &str, from: i64, to: i64) -> Result<Vec<Measurement>, sqlx::Error> {
+        self.check()?;
+        Ok(self.lock().raw_measurements.iter()
+            .filter(|measurement| measurement.network == network_id)
+            .filter(|measurement| (from..to).contains(&measurement.metadata.timestamp))
+            .cloned()
+            .collect())
+    }
+
+    async fn purge_raw_measurements(&self, cutoff: i64) -> Result<u64, sqlx::Error> {
+        self.check()?;
+        let mut recorded = self.lock();
+        let before = recorded.raw_measurements.len();
+        recorded.raw_measurements.retain(|measurement| measurement.metadata.timestamp >= cutoff);
+        Ok((before - recorded.raw_measurements.len()) as u64)
+    }
+}
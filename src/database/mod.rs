@@ -1,3 +1,9 @@
+#[cfg(test)]
+mod bench;
+pub mod domain;
 pub mod logic;
+#[cfg(test)]
+pub mod memory;
+pub mod storage;
 mod tables;
-pub(crate) mod repository;
\ No newline at end of file
+pub(crate) mod repository;
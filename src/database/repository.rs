@@ -6,28 +6,44 @@
 //!
 //! # Características
 //! * **Pool Management:** Gestiona el ciclo de vida del pool de conexiones `sqlx`.
+//! * **Migraciones:** Aplica las migraciones versionadas embebidas en el binario (`migrations/`).
 //! * **Resiliencia:** Implementa lógica de reintento (backoff) durante el inicio.
-//! * **Batch Routing:** Despacha los datos acumulados a las tablas correspondientes.
+//! * **Batch Routing:** Despacha los datos acumulados a las tablas correspondientes en una transacción.
 
 
+use std::sync::Arc;
+use async_trait::async_trait;
 use sqlx::PgPool;
+use sqlx::migrate::{MigrateError, Migrator};
 use sqlx::postgres::PgPoolOptions;
 use tracing::{debug, error, info};
 use tokio::time::sleep;
-use crate::bucket::logic::ProcessedTelemetry;
+use crate::bucket::logic::{LateSample, ProcessedTelemetry};
+use crate::database::domain::WriteBatch;
+use crate::database::storage::Storage;
 use crate::database::tables::alert_air::{insert_alert_air};
 use crate::database::tables::alert_temp::{insert_alert_temp};
-use crate::database::tables::measurement::{insert_measurement};
+use crate::database::tables::late_measurement::insert_late_measurement;
+use crate::database::tables::measurement::{insert_measurement, replace_measurement};
 use crate::database::tables::metrics::{insert_system_metrics};
 use crate::database::tables::monitor::{insert_monitor};
+use crate::database::tables::raw_measurement::{delete_raw_measurements_before, insert_raw_measurements, select_raw_measurements};
 use crate::database::tables::weather::insert_weather;
-use crate::message::domain::{Message};
+use crate::message::domain::Measurement;
+use crate::metrics::domain::Metrics;
 use crate::system::domain::database::WAIT_FOR;
 use crate::system::domain::System;
 use crate::weather::domain::Weather;
 
 
-/// Gestor principal de persistencia.
+/// Migraciones versionadas embebidas en tiempo de compilación.
+///
+/// `sqlx` registra cada versión aplicada (con su checksum) en la tabla `_sqlx_migrations`,
+/// que actúa como tabla de versión del esquema.
+pub(super) static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+
+/// Gestor principal de persistencia: implementación de [`Storage`] sobre Postgres.
 ///
 /// Es barato de clonar (`Clone`) ya que envuelve un `Arc<PgPool>` internamente.
 /// Está diseñado para ser compartido entre múltiples tareas asíncronas.
@@ -35,6 +51,10 @@ use crate::weather::domain::Weather;
 pub struct Repository {
     /// Pool de conexiones asíncronas a PostgreSQL.
     pool: PgPool,
+    /// Métricas de filas insertadas y fallidas por tabla.
+    metrics: Arc<Metrics>,
+    /// Filas a partir de las cuales un lote se inserta con `COPY` (`DB_COPY_THRESHOLD_ROWS`).
+    copy_threshold: usize,
 }
 
 
@@ -44,13 +64,15 @@ impl Repository {
     ///
     /// # Pasos
     /// 1. Crea el pool de conexiones según la configuración en `System`.
-    /// 2. Ejecuta las sentencias `CREATE TABLE IF NOT EXISTS` para todas las entidades.
+    /// 2. Aplica las migraciones pendientes de `migrations/` en orden de versión.
     ///
     /// # Retorno
-    /// Retorna `Err` si la base de datos no está disponible inmediatamente.
-    pub async fn new(system: &System) -> Result<Self, sqlx::Error> {
+    /// Retorna `Err` si la base de datos no está disponible inmediatamente o si
+    /// el esquema no es compatible con el binario (ver [`run_migrations`]).
+    pub async fn new(system: &System, metrics: Arc<Metrics>) -> Result<Self, sqlx::Error> {
         let pool = create_pool(system).await?;
-        Ok(Self { pool })
+        run_migrations(&pool).await?;
+        Ok(Self { pool, metrics, copy_threshold: system.db_copy_threshold_rows })
     }
 
     /// Constructor resiliente con bucle de reintento infinito.
@@ -59,13 +81,22 @@ impl Repository {
     /// no está lista (ej. contenedor levantándose), bloqueará la tarea actual y
     /// reintentará cada `WAIT_FOR` segundos hasta tener éxito.
     ///
+    /// # Panics
+    /// Si el esquema de la base de datos es incompatible con las migraciones embebidas
+    /// (versión más nueva que el binario, checksum distinto o migración a medio aplicar).
+    /// Reintentar no lo resolvería, por lo que el servicio se niega a arrancar.
+    ///
     /// # Argumentos
     /// * `system`: Configuración global del sistema.
-    pub async fn create_repository(system: &System) -> Self {
+    /// * `metrics`: Métricas compartidas del servicio.
+    pub async fn create_repository(system: &System, metrics: Arc<Metrics>) -> Self {
         info!("Info: creando repository");
         loop {
-            match Self::new(system).await {
+            match Self::new(system, metrics.clone()).await {
                 Ok(repo) => return repo,
+                Err(sqlx::Error::Migrate(e)) if is_schema_incompatible(&e) => {
+                    panic!("Error: esquema de base de datos incompatible con este binario. {}", e);
+                }
                 Err(e) => {
                     error!("Error: no se pudo crear repository. Reintentando. {:?}", e);
                     sleep(WAIT_FOR).await;
@@ -74,42 +105,82 @@ impl Repository {
         }
     }
 
-    /// Persiste un lote heterogéneo de datos en la base de datos.
-    ///
-    /// Recibe un `TableDataVector` (que actúa como buffer) e inspecciona sus campos.
-    /// Si un vector específico no está vacío, delega la inserción a la función correspondiente
-    /// del módulo de tablas.
-    ///
-    /// # Transaccionalidad
-    /// Las inserciones se ejecutan secuencialmente. Si ocurre un error a mitad de camino (ej. en `alert_air`),
-    /// las inserciones previas (ej. `measurement`) **permanecen confirmadas**.
+    async fn write_batch(&self, batch: WriteBatch) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        insert_monitor(&mut tx, batch.monitor, self.copy_threshold).await?;
+        insert_alert_air(&mut tx, batch.alert_air, self.copy_threshold).await?;
+        insert_alert_temp(&mut tx, batch.alert_temp, self.copy_threshold).await?;
+        insert_system_metrics(&mut tx, batch.metrics, self.copy_threshold).await?;
+        tx.commit().await
+    }
+}
+
+
+#[async_trait]
+impl Storage for Repository {
+
+    /// Verifica que la base de datos responda ejecutando `SELECT 1`.
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Inserta las filas del buffer de `dba_task` en una única transacción.
     ///
-    /// # Argumentos
-    /// * `tdv`: Estructura que contiene vectores de datos (`Vec<Measurement>`, `Vec<Monitor>`, etc.).
-    pub async fn insert_message(&self, msg: Message) -> Result<(), sqlx::Error> {
-        debug!("Debug: insertando dato de tipo Message en base de datos");
-
-        match msg {
-            Message::Monitor(m) => insert_monitor(&self.pool, vec![m.clone()]).await,
-            Message::AlertAir(m) => insert_alert_air(&self.pool, vec![m.clone()]).await,
-            Message::AlertTem(m) => insert_alert_temp(&self.pool, vec![m.clone()]).await,
-            Message::Metrics(m) => insert_system_metrics(&self.pool, vec![m.clone()]).await,
-            Message::MonitorBatch(b) => insert_monitor(&self.pool, b.clone()).await,
-            Message::AlertAirBatch(b) => insert_alert_air(&self.pool, b.clone()).await,
-            Message::AlertTemBatch(b) => insert_alert_temp(&self.pool, b.clone()).await,
-
-            _ => Ok(())
+    /// Cada tabla elige su camino de inserción según su cantidad de filas (ver
+    /// [`crate::database::tables::bulk`]). Si una tabla falla, no se confirma ninguna: el lote
+    /// completo se reintenta, se desborda o se confirma junto.
+    async fn insert_write_batch(&self, batch: WriteBatch) -> Result<(), sqlx::Error> {
+        debug!("Debug: insertando lote de {} filas en base de datos", batch.rows());
+
+        let table_rows = batch.table_rows();
+        let result = self.write_batch(batch).await;
+        for (table, rows) in table_rows {
+            if rows > 0 {
+                self.metrics.record_insert(table, rows, &result);
+            }
         }
+        result
     }
 
-    pub async fn insert_telemetry(&self, telemetry: ProcessedTelemetry) -> Result<(), sqlx::Error> {
-        insert_measurement(&self.pool, telemetry).await?;
-        Ok(())
+    async fn insert_telemetry(&self, telemetry: ProcessedTelemetry) -> Result<(), sqlx::Error> {
+        let result = insert_measurement(&self.pool, telemetry).await;
+        self.metrics.record_insert("measurement", 1, &result);
+        result
     }
 
-    pub async fn insert_weather_data(&self, weather: Weather) -> Result<(), sqlx::Error> {
-        insert_weather(&self.pool, weather).await?;
-        Ok(())
+    async fn insert_late_sample(&self, sample: LateSample) -> Result<(), sqlx::Error> {
+        let result = insert_late_measurement(&self.pool, sample).await;
+        self.metrics.record_insert("late_measurement", 1, &result);
+        result
+    }
+
+    /// Reemplaza la fila de la ventana con un agregado recalculado desde el archivo crudo.
+    async fn replace_telemetry(&self, telemetry: ProcessedTelemetry) -> Result<(), sqlx::Error> {
+        let result = replace_measurement(&self.pool, telemetry).await;
+        self.metrics.record_insert("measurement", 1, &result);
+        result
+    }
+
+    async fn insert_raw_measurements(&self, measurements: Vec<Measurement>) -> Result<(), sqlx::Error> {
+        let rows = measurements.len();
+        let result = insert_raw_measurements(&self.pool, measurements, self.copy_threshold).await;
+        self.metrics.record_insert("raw_measurement", rows, &result);
+        result
+    }
+
+    async fn raw_measurements(&self, network_id: &str, from: i64, to: i64) -> Result<Vec<Measurement>, sqlx::Error> {
+        select_raw_measurements(&self.pool, network_id, from, to).await
+    }
+
+    async fn purge_raw_measurements(&self, cutoff: i64) -> Result<u64, sqlx::Error> {
+        delete_raw_measurements_before(&self.pool, cutoff).await
+    }
+
+    async fn insert_weather_data(&self, weather: Weather) -> Result<(), sqlx::Error> {
+        let result = insert_weather(&self.pool, weather).await;
+        self.metrics.record_insert("weather", 1, &result);
+        result
     }
 }
 
@@ -126,4 +197,29 @@ async fn create_pool(system: &System) -> Result<PgPool, sqlx::Error> {
         .await?;
 
     Ok(pool)
-}
\ No newline at end of file
+}
+
+
+/// Aplica las migraciones embebidas que todavía no figuran en `_sqlx_migrations`.
+///
+/// `sqlx` valida además que toda versión ya aplicada exista en el binario con el mismo
+/// checksum. Si la base tiene una versión desconocida (fue migrada por un binario más
+/// nuevo) se retorna `MigrateError::VersionMissing`.
+async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::Error> {
+    info!("Info: aplicando migraciones de base de datos");
+
+    MIGRATOR.run(pool).await?;
+
+    let version = MIGRATOR.iter().map(|m| m.version).max().unwrap_or_default();
+    info!("Info: esquema de base de datos en versión {}", version);
+    Ok(())
+}
+
+
+/// Indica si un error de migración es permanente (no se soluciona reintentando).
+fn is_schema_incompatible(error: &MigrateError) -> bool {
+    matches!(error,
+        MigrateError::VersionMissing(_)
+        | MigrateError::VersionMismatch(_)
+        | MigrateError::Dirty(_))
+}
@@ -0,0 +1,47 @@
+//! Contrato de persistencia de las tareas.
+//!
+//! Las tareas no dependen de `Repository` sino de [`Storage`], compartido en
+//! `AppContext::repo` como `Arc<dyn Storage>`. En producción lo implementa el repositorio de
+//! Postgres; en los tests, `MemoryStorage`, que registra lo escrito sin base de datos.
+
+
+use std::fmt::Debug;
+use async_trait::async_trait;
+use crate::bucket::logic::{LateSample, ProcessedTelemetry};
+use crate::database::domain::WriteBatch;
+use crate::message::domain::Measurement;
+use crate::weather::domain::Weather;
+
+
+/// Operaciones de persistencia que usan las tareas.
+///
+/// Los errores son `sqlx::Error` también fuera de Postgres: `dba_task` distingue un rechazo
+/// permanente (`sqlx::Error::Database`) de una falla transitoria para decidir entre Nack y
+/// cola de desborde.
+#[async_trait]
+pub trait Storage: Debug + Send + Sync {
+
+    /// Verifica que el almacenamiento responda.
+    async fn ping(&self) -> Result<(), sqlx::Error>;
+
+    /// Inserta las filas del buffer de `dba_task` de forma atómica.
+    async fn insert_write_batch(&self, batch: WriteBatch) -> Result<(), sqlx::Error>;
+
+    /// Inserta el agregado de una ventana, fusionándolo con el existente si lo hubiera.
+    async fn insert_telemetry(&self, telemetry: ProcessedTelemetry) -> Result<(), sqlx::Error>;
+
+    /// Reemplaza el agregado de una ventana (reagregación desde el archivo crudo).
+    async fn replace_telemetry(&self, telemetry: ProcessedTelemetry) -> Result<(), sqlx::Error>;
+
+    async fn insert_late_sample(&self, sample: LateSample) -> Result<(), sqlx::Error>;
+
+    async fn insert_weather_data(&self, weather: Weather) -> Result<(), sqlx::Error>;
+
+    async fn insert_raw_measurements(&self, measurements: Vec<Measurement>) -> Result<(), sqlx::Error>;
+
+    /// Mediciones archivadas de la red con timestamp en `[from, to)`.
+    async fn raw_measurements(&self, network_id: &str, from: i64, to: i64) -> Result<Vec<Measurement>, sqlx::Error>;
+
+    /// Elimina las mediciones archivadas anteriores a `cutoff` y devuelve cuántas eran.
+    async fn purge_raw_measurements(&self, cutoff: i64) -> Result<u64, sqlx::Error>;
+}
@@ -4,49 +4,51 @@
 //! relacionados con la calidad del aire detectada por los sensores.
 
 
-use sqlx::{PgPool, Postgres, QueryBuilder};
+use sqlx::{PgConnection, Postgres};
+use sqlx::query_builder::Separated;
 use chrono::{DateTime};
+use crate::database::tables::bulk::{insert_rows, BulkRow, CopyBuffer};
 use crate::message::domain::AlertAir;
 
 
-/// Realiza una inserción masiva (batch) de alertas de aire usando `QueryBuilder`.
-///
-/// Utiliza `push_values` para construir una única sentencia SQL con múltiples filas,
-/// optimizando el rendimiento de red y base de datos.
-///
-/// # Argumentos
-/// * `pool`: Pool de conexiones a Postgres.
-/// * `data_vec`: Vector con las alertas a insertar.
-pub async fn insert_alert_air(pool: &PgPool,
-                              data_vec: Vec<AlertAir>
-) -> Result<(), sqlx::Error> {
-
-    if data_vec.is_empty() {
-        return Ok(());
+impl BulkRow for AlertAir {
+    const TABLE: &'static str = "alert_air";
+    const COLUMNS: &'static [&'static str] = &[
+        "sender_user_id", "destination_id", "timestamp",
+        "network_id", "co2_initial_ppm", "co2_actual_ppm",
+    ];
+    const CONFLICT: &'static str = "(sender_user_id, network_id, timestamp)";
+
+    fn push_binds(self, mut b: Separated<'_, '_, Postgres, &'static str>) {
+        b.push_bind(self.metadata.sender_user_id)
+            .push_bind(self.metadata.destination_id)
+            .push_bind(DateTime::from_timestamp(self.metadata.timestamp, 0).unwrap_or_default())
+            .push_bind(self.network)
+            .push_bind(self.co2_initial_ppm)
+            .push_bind(self.co2_actual_ppm);
     }
 
-    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
-        "INSERT INTO alert_air (
-            sender_user_id, destination_id, timestamp,
-            network_id, co2_initial_ppm, co2_actual_ppm
-        ) "
-    );
-
-    query_builder.push_values(data_vec, |mut b, data| {
-        b.push_bind(data.metadata.sender_user_id)
-            .push_bind(data.metadata.destination_id)
-            .push_bind(DateTime::from_timestamp(data.metadata.timestamp, 0).unwrap_or_default())
-            .push_bind(data.network)
-            .push_bind(data.co2_initial_ppm)
-            .push_bind(data.co2_actual_ppm);
-    });
-
-    let query = query_builder.build();
-    query.execute(pool).await?;
-
-    Ok(())
+    fn encode(&self, buffer: &mut CopyBuffer) {
+        buffer.text(&self.metadata.sender_user_id)
+            .text(&self.metadata.destination_id)
+            .timestamp(self.metadata.timestamp)
+            .text(&self.network)
+            .float4(self.co2_initial_ppm)
+            .float4(self.co2_actual_ppm);
+    }
 }
 
 
+/// Realiza una inserción masiva (batch) de alertas de aire.
+///
+/// # Argumentos
+/// * `conn`: Conexión a Postgres, dentro o fuera de una transacción.
+/// * `data_vec`: Vector con las alertas a insertar.
+/// * `copy_threshold`: Filas a partir de las cuales se usa `COPY` (`DB_COPY_THRESHOLD_ROWS`).
+pub async fn insert_alert_air(conn: &mut PgConnection,
+                              data_vec: Vec<AlertAir>,
+                              copy_threshold: usize
+) -> Result<(), sqlx::Error> {
 
-
+    insert_rows(conn, data_vec, copy_threshold).await
+}
@@ -2,40 +2,49 @@
 //!
 
 use chrono::DateTime;
-use sqlx::{PgPool, Postgres, QueryBuilder};
+use sqlx::{PgConnection, Postgres};
+use sqlx::query_builder::Separated;
+use crate::database::tables::bulk::{insert_rows, BulkRow, CopyBuffer};
 use crate::message::domain::{AlertTh};
 
 
+impl BulkRow for AlertTh {
+    const TABLE: &'static str = "alert_temp";
+    const COLUMNS: &'static [&'static str] = &[
+        "sender_user_id", "destination_id", "timestamp",
+        "network_id", "initial_temp", "actual_temp",
+    ];
+    const CONFLICT: &'static str = "(sender_user_id, network_id, timestamp)";
+
+    fn push_binds(self, mut b: Separated<'_, '_, Postgres, &'static str>) {
+        b.push_bind(self.metadata.sender_user_id)
+            .push_bind(self.metadata.destination_id)
+            .push_bind(DateTime::from_timestamp(self.metadata.timestamp, 0).unwrap_or_default())
+            .push_bind(self.network)
+            .push_bind(self.initial_temp)
+            .push_bind(self.actual_temp);
+    }
+
+    fn encode(&self, buffer: &mut CopyBuffer) {
+        buffer.text(&self.metadata.sender_user_id)
+            .text(&self.metadata.destination_id)
+            .timestamp(self.metadata.timestamp)
+            .text(&self.network)
+            .float4(self.initial_temp)
+            .float4(self.actual_temp);
+    }
+}
+
+
 /// Inserta un lote de alertas de temperatura de forma eficiente.
 ///
 /// # Argumentos
 /// * `data_vec`: Vector de alertas (`AlertTh`) acumuladas en memoria.
-pub async fn insert_alert_temp(pool: &PgPool,
-                               data_vec: Vec<AlertTh>
+/// * `copy_threshold`: Filas a partir de las cuales se usa `COPY` (`DB_COPY_THRESHOLD_ROWS`).
+pub async fn insert_alert_temp(conn: &mut PgConnection,
+                               data_vec: Vec<AlertTh>,
+                               copy_threshold: usize
 ) -> Result<(), sqlx::Error> {
 
-    if data_vec.is_empty() {
-        return Ok(());
-    }
-
-    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
-        "INSERT INTO alert_temp (
-            sender_user_id, destination_id, timestamp,
-            network_id, initial_temp, actual_temp
-        ) "
-    );
-
-    query_builder.push_values(data_vec, |mut b, data| {
-        b.push_bind(data.metadata.sender_user_id)
-            .push_bind(data.metadata.destination_id)
-            .push_bind(DateTime::from_timestamp(data.metadata.timestamp, 0).unwrap_or_default())
-            .push_bind(data.network)
-            .push_bind(data.initial_temp)
-            .push_bind(data.actual_temp);
-    });
-    
-    let query = query_builder.build();
-    query.execute(pool).await?;
-
-    Ok(())
+    insert_rows(conn, data_vec, copy_threshold).await
 }
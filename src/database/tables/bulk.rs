@@ -0,0 +1,243 @@
+//! Inserción masiva compartida por las tablas de eventos.
+//!
+//! Postgres admite como máximo 65535 parámetros por sentencia, por lo que un `INSERT`
+//! multi-fila de `monitor` (17 columnas) no puede superar las 3855 filas. Según el tamaño del
+//! lote se elige el camino ([`InsertPath`]):
+//!
+//! * **Values:** un único `INSERT ... VALUES` con `push_values`.
+//! * **Chunked:** varios `INSERT ... VALUES` dentro de una transacción, cada uno por debajo
+//!   del límite de parámetros.
+//! * **Copy:** a partir de `DB_COPY_THRESHOLD_ROWS` filas, `COPY ... FROM STDIN` en formato
+//!   binario hacia una tabla temporal y luego `INSERT ... SELECT` a la tabla final.
+//!
+//! Los tres caminos aplican el mismo `ON CONFLICT ... DO NOTHING`, por lo que la inserción
+//! sigue siendo idempotente.
+//!
+//! Las funciones reciben una conexión en lugar del pool: si la conexión ya está dentro de una
+//! transacción (ej. el vaciado del buffer de `dba_task`), los caminos `Chunked` y `Copy` abren
+//! un savepoint y el lote se confirma junto con el resto de la transacción.
+
+
+use chrono::DateTime;
+use sqlx::{Connection, PgConnection, Postgres, QueryBuilder};
+use sqlx::query_builder::Separated;
+use tracing::debug;
+
+
+/// Parámetros máximos por sentencia en el protocolo de Postgres.
+pub const MAX_BIND_PARAMS: usize = 65535;
+
+/// Encabezado del formato binario de `COPY`: firma, flags y largo de la extensión.
+const COPY_SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+
+/// Microsegundos entre la época Unix y la de Postgres (2000-01-01).
+const PG_EPOCH_OFFSET_MICROS: i64 = 946_684_800_000_000;
+
+/// Tamaño de cada mensaje `CopyData` enviado al servidor.
+const COPY_CHUNK_BYTES: usize = 1 << 20;
+
+
+/// Fila de una tabla que admite inserción masiva.
+pub trait BulkRow: Send + Sized {
+    const TABLE: &'static str;
+    /// Columnas escritas, en el orden de [`BulkRow::push_binds`] y [`BulkRow::encode`].
+    const COLUMNS: &'static [&'static str];
+    /// Clave natural de `ON CONFLICT`, ej. `(sender_user_id, network_id, timestamp)`.
+    const CONFLICT: &'static str;
+
+    /// Vincula los valores de la fila para `INSERT ... VALUES`.
+    fn push_binds(self, b: Separated<'_, '_, Postgres, &'static str>);
+
+    /// Escribe los valores de la fila en formato binario de `COPY`.
+    fn encode(&self, buffer: &mut CopyBuffer);
+}
+
+
+/// Camino de inserción de un lote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertPath {
+    Values,
+    Chunked,
+    Copy,
+}
+
+
+impl InsertPath {
+
+    /// Camino para `rows` filas de `columns` columnas.
+    pub fn for_batch(rows: usize, columns: usize, copy_threshold: usize) -> Self {
+        if rows >= copy_threshold {
+            InsertPath::Copy
+        } else if rows * columns > MAX_BIND_PARAMS {
+            InsertPath::Chunked
+        } else {
+            InsertPath::Values
+        }
+    }
+}
+
+
+/// Inserta el lote por el camino que corresponde a su tamaño.
+pub async fn insert_rows<T: BulkRow>(conn: &mut PgConnection,
+                                     rows: Vec<T>,
+                                     copy_threshold: usize
+) -> Result<(), sqlx::Error> {
+
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let path = InsertPath::for_batch(rows.len(), T::COLUMNS.len(), copy_threshold);
+    debug!("Debug: insertando {} filas en {} ({:?})", rows.len(), T::TABLE, path);
+
+    match path {
+        InsertPath::Values => insert_values(conn, rows).await,
+        InsertPath::Chunked => insert_chunked(conn, rows).await,
+        InsertPath::Copy => insert_copy(conn, rows).await,
+    }
+}
+
+
+/// Un único `INSERT ... VALUES`. El lote debe respetar [`MAX_BIND_PARAMS`].
+async fn insert_values<T: BulkRow>(conn: &mut PgConnection, rows: Vec<T>) -> Result<(), sqlx::Error> {
+    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        format!("INSERT INTO {} ({}) ", T::TABLE, T::COLUMNS.join(", "))
+    );
+
+    query_builder.push_values(rows, |b, row| row.push_binds(b));
+
+    // Un reenvío del Edge o una reproducción de la cola de desborde no duplica filas
+    query_builder.push(format!(" ON CONFLICT {} DO NOTHING", T::CONFLICT));
+
+    query_builder.build().execute(conn).await?;
+    Ok(())
+}
+
+
+/// Varios `INSERT ... VALUES` en una transacción: el lote se inserta completo o no se inserta.
+pub async fn insert_chunked<T: BulkRow>(conn: &mut PgConnection, mut rows: Vec<T>) -> Result<(), sqlx::Error> {
+    let chunk_rows = (MAX_BIND_PARAMS / T::COLUMNS.len()).max(1);
+
+    let mut tx = conn.begin().await?;
+    while !rows.is_empty() {
+        let rest = rows.split_off(chunk_rows.min(rows.len()));
+        insert_values(&mut tx, rows).await?;
+        rows = rest;
+    }
+    tx.commit().await
+}
+
+
+/// `COPY` binario a una tabla temporal con las columnas de la fila y `INSERT ... SELECT` a la
+/// tabla final. `COPY` no admite `ON CONFLICT`; la tabla temporal permite conservarlo.
+pub async fn insert_copy<T: BulkRow>(conn: &mut PgConnection, rows: Vec<T>) -> Result<(), sqlx::Error> {
+    let columns = T::COLUMNS.join(", ");
+    let staging = format!("{}_copy", T::TABLE);
+
+    let mut buffer = CopyBuffer::new();
+    for row in &rows {
+        buffer.row(T::COLUMNS.len());
+        row.encode(&mut buffer);
+    }
+    let data = buffer.finish();
+
+    let mut tx = conn.begin().await?;
+
+    sqlx::query(&format!(
+        "CREATE TEMP TABLE {staging} ON COMMIT DROP AS SELECT {columns} FROM {} WITH NO DATA",
+        T::TABLE,
+    ))
+        .execute(&mut *tx)
+        .await?;
+
+    let mut copy = tx.copy_in_raw(&format!("COPY {staging} ({columns}) FROM STDIN (FORMAT binary)")).await?;
+    for chunk in data.chunks(COPY_CHUNK_BYTES) {
+        if let Err(e) = copy.send(chunk).await {
+            copy.abort(e.to_string()).await?;
+            return Err(e);
+        }
+    }
+    copy.finish().await?;
+
+    sqlx::query(&format!(
+        "INSERT INTO {} ({columns}) SELECT {columns} FROM {staging} ON CONFLICT {} DO NOTHING",
+        T::TABLE,
+        T::CONFLICT,
+    ))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+
+/// Datos en el formato binario de `COPY`: encabezado, filas (cantidad de campos y cada campo
+/// con su largo en bytes, `-1` para `NULL`) y marca de fin.
+#[derive(Debug)]
+pub struct CopyBuffer {
+    data: Vec<u8>,
+}
+
+
+impl CopyBuffer {
+
+    fn new() -> Self {
+        let mut data = COPY_SIGNATURE.to_vec();
+        data.extend_from_slice(&0i32.to_be_bytes());
+        data.extend_from_slice(&0i32.to_be_bytes());
+        Self { data }
+    }
+
+    fn row(&mut self, fields: usize) {
+        self.data.extend_from_slice(&(fields as i16).to_be_bytes());
+    }
+
+    fn field(&mut self, bytes: &[u8]) -> &mut Self {
+        self.data.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    /// `TEXT`.
+    pub fn text(&mut self, value: &str) -> &mut Self {
+        self.field(value.as_bytes())
+    }
+
+    /// `INTEGER`.
+    pub fn int4(&mut self, value: i32) -> &mut Self {
+        self.field(&value.to_be_bytes())
+    }
+
+    /// `INTEGER` que admite `NULL`.
+    pub fn opt_int4(&mut self, value: Option<i32>) -> &mut Self {
+        match value {
+            Some(value) => self.int4(value),
+            None => {
+                self.data.extend_from_slice(&(-1i32).to_be_bytes());
+                self
+            }
+        }
+    }
+
+    /// `BIGINT`.
+    pub fn int8(&mut self, value: i64) -> &mut Self {
+        self.field(&value.to_be_bytes())
+    }
+
+    /// `REAL`.
+    pub fn float4(&mut self, value: f32) -> &mut Self {
+        self.field(&value.to_bits().to_be_bytes())
+    }
+
+    /// `TIMESTAMPTZ` desde un timestamp Unix en segundos, con la misma conversión que los
+    /// binds de `INSERT`.
+    pub fn timestamp(&mut self, unix_secs: i64) -> &mut Self {
+        let micros = DateTime::from_timestamp(unix_secs, 0).unwrap_or_default().timestamp_micros();
+        self.int8(micros - PG_EPOCH_OFFSET_MICROS)
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.data.extend_from_slice(&(-1i16).to_be_bytes());
+        self.data
+    }
+}
@@ -0,0 +1,50 @@
+//! Módulo de persistencia para Mediciones Tardías.
+//!
+//! Guarda, sin agregar, las muestras que llegaron después de cerrado su bucket.
+
+use chrono::DateTime;
+use sqlx::{PgPool};
+use crate::bucket::logic::LateSample;
+
+
+pub async fn insert_late_measurement(pool: &PgPool,
+                                     data: LateSample
+) -> Result<(), sqlx::Error> {
+
+    let measurement = data.measurement;
+
+    sqlx::query(
+        r#"
+        INSERT INTO late_measurement (received_at,
+                                      timestamp,
+                                      network_id,
+                                      bucket_timestamp,
+                                      window_secs,
+                                      sender_user_id,
+                                      sample,
+                                      pulse_counter,
+                                      pulse_max_duration,
+                                      temperature,
+                                      humidity,
+                                      co2_ppm)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        ON CONFLICT (sender_user_id, network_id, timestamp, sample) DO NOTHING
+        "#,
+    )
+        .bind(DateTime::from_timestamp(data.received_at, 0).unwrap_or_default())
+        .bind(DateTime::from_timestamp(measurement.metadata.timestamp, 0).unwrap_or_default())
+        .bind(measurement.network)
+        .bind(DateTime::from_timestamp(data.bucket_ts, 0).unwrap_or_default())
+        .bind(data.window_secs as i32)
+        .bind(measurement.metadata.sender_user_id)
+        .bind(measurement.sample as i64)
+        .bind(measurement.pulse_counter)
+        .bind(measurement.pulse_max_duration)
+        .bind(measurement.temperature)
+        .bind(measurement.humidity)
+        .bind(measurement.co2_ppm)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -2,37 +2,249 @@
 //!
 //! Este módulo maneja la tabla con mayor volumen de escritura del sistema,
 //! almacenando los reportes periódicos de los sensores.
+//!
+//! # Idempotencia
+//! Cada ventana `(network_id, timestamp, window_secs)` tiene una sola fila. Si ya existe
+//! (ej. un bucket reabierto tras un reinicio), el nuevo agregado se fusiona con ella mediante
+//! [`ProcessedTelemetry::merge`]. La fila guarda en `aggregate_ids` los agregados ya
+//! fusionados, de modo que un reintento de la misma inserción no la altera.
+//!
+//! Una ventana vuelta a agregar desde el archivo crudo reemplaza la fila en lugar de
+//! fusionarse ([`replace_measurement`]).
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Row};
+use sqlx::postgres::{PgArguments, PgRow};
+use sqlx::query::Query;
+use crate::bucket::logic::{AggregationStrategies, BucketStats, ProcessedTelemetry, RejectedSamples};
 
-use chrono::DateTime;
-use sqlx::{PgPool};
-use crate::bucket::logic::ProcessedTelemetry;
 
+/// Columnas escritas por [`bind_columns`], en el orden de sus parámetros.
+const COLUMNS: [&str; 41] = [
+    "timestamp", "network_id", "pulse_counter", "pulse_max_duration",
+    "temperature", "humidity", "co2_ppm", "window_secs",
+    "temperature_out_of_range", "temperature_outliers",
+    "humidity_out_of_range", "humidity_outliers",
+    "co2_out_of_range", "co2_outliers",
+    "temperature_strategy", "humidity_strategy", "co2_strategy",
+    "temperature_min", "temperature_max", "temperature_stddev",
+    "temperature_samples_raw", "temperature_samples_kept",
+    "temperature_first_at", "temperature_last_at",
+    "humidity_min", "humidity_max", "humidity_stddev",
+    "humidity_samples_raw", "humidity_samples_kept",
+    "humidity_first_at", "humidity_last_at",
+    "co2_min", "co2_max", "co2_stddev",
+    "co2_samples_raw", "co2_samples_kept",
+    "co2_first_at", "co2_last_at",
+    "senders", "destinations", "duplicate_samples",
+];
 
+
+/// Inserta el agregado o lo fusiona con la fila existente de la misma ventana.
 pub async fn insert_measurement(pool: &PgPool,
                                 data: ProcessedTelemetry
 ) -> Result<(), sqlx::Error> {
 
-    sqlx::query(
-        r#"
-        INSERT INTO measurement (timestamp,
-                                 network_id,
-                                 pulse_counter,
-                                 pulse_max_duration,
-                                 temperature,
-                                 humidity,
-                                 co2_ppm)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
-        "#,
-    )
-        .bind(DateTime::from_timestamp(data.timestamp, 0).unwrap_or_default())
-        .bind(data.network_id)
+    let mut tx = pool.begin().await?;
+
+    let insert = format!(
+        "INSERT INTO measurement ({}, aggregate_ids) VALUES ({}, ARRAY[${}])
+         ON CONFLICT (network_id, timestamp, window_secs) DO NOTHING",
+        COLUMNS.join(", "),
+        placeholders(),
+        COLUMNS.len() + 1,
+    );
+    let inserted = bind_columns(sqlx::query(&insert), &data)
+        .bind(&data.aggregate_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    if inserted == 0 {
+        let row = sqlx::query(
+            r#"
+            SELECT *
+            FROM measurement
+            WHERE network_id = $1 AND timestamp = $2 AND window_secs = $3
+            FOR UPDATE
+            "#,
+        )
+            .bind(&data.network_id)
+            .bind(to_datetime(data.timestamp))
+            .bind(data.window_secs as i32)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let id: i64 = row.try_get("id")?;
+        let aggregate_ids: Vec<String> = row.try_get("aggregate_ids")?;
+
+        if !aggregate_ids.contains(&data.aggregate_id) {
+            let mut merged = from_row(&row)?;
+            merged.merge(&data);
+
+            let update = format!(
+                "UPDATE measurement SET ({}) = ({}), aggregate_ids = array_append(aggregate_ids, ${})
+                 WHERE id = ${}",
+                COLUMNS.join(", "),
+                placeholders(),
+                COLUMNS.len() + 1,
+                COLUMNS.len() + 2,
+            );
+            bind_columns(sqlx::query(&update), &merged)
+                .bind(&data.aggregate_id)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+
+/// Inserta el agregado o reemplaza por completo la fila existente de la misma ventana.
+pub async fn replace_measurement(pool: &PgPool,
+                                 data: ProcessedTelemetry
+) -> Result<(), sqlx::Error> {
+
+    let upsert = format!(
+        "INSERT INTO measurement ({columns}, aggregate_ids) VALUES ({}, ARRAY[${}])
+         ON CONFLICT (network_id, timestamp, window_secs)
+         DO UPDATE SET ({columns}, aggregate_ids) = ({}, EXCLUDED.aggregate_ids)",
+        placeholders(),
+        COLUMNS.len() + 1,
+        COLUMNS.iter().map(|column| format!("EXCLUDED.{column}")).collect::<Vec<_>>().join(", "),
+        columns = COLUMNS.join(", "),
+    );
+    bind_columns(sqlx::query(&upsert), &data)
+        .bind(&data.aggregate_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+
+fn placeholders() -> String {
+    (1..=COLUMNS.len())
+        .map(|i| format!("${i}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+
+fn to_datetime(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_default()
+}
+
+
+/// Vincula los valores de [`COLUMNS`] como `$1..$41`.
+fn bind_columns<'q>(query: Query<'q, Postgres, PgArguments>,
+                    data: &'q ProcessedTelemetry
+) -> Query<'q, Postgres, PgArguments> {
+
+    let stats = &data.stats;
+    query
+        .bind(to_datetime(data.timestamp))
+        .bind(&data.network_id)
         .bind(data.pulse_counter_total)
         .bind(data.pulse_max_duration)
         .bind(data.temperature)
         .bind(data.humidity)
         .bind(data.co2_ppm)
-        .execute(pool)
-        .await?;
+        .bind(data.window_secs as i32)
+        .bind(data.rejected.temperature_out_of_range)
+        .bind(data.rejected.temperature_outliers)
+        .bind(data.rejected.humidity_out_of_range)
+        .bind(data.rejected.humidity_outliers)
+        .bind(data.rejected.co2_out_of_range)
+        .bind(data.rejected.co2_outliers)
+        .bind(&data.strategies.temperature_strategy)
+        .bind(&data.strategies.humidity_strategy)
+        .bind(&data.strategies.co2_strategy)
+        .bind(stats.temperature_min)
+        .bind(stats.temperature_max)
+        .bind(stats.temperature_stddev)
+        .bind(stats.temperature_samples_raw)
+        .bind(stats.temperature_samples_kept)
+        .bind(stats.temperature_first_at.map(to_datetime))
+        .bind(stats.temperature_last_at.map(to_datetime))
+        .bind(stats.humidity_min)
+        .bind(stats.humidity_max)
+        .bind(stats.humidity_stddev)
+        .bind(stats.humidity_samples_raw)
+        .bind(stats.humidity_samples_kept)
+        .bind(stats.humidity_first_at.map(to_datetime))
+        .bind(stats.humidity_last_at.map(to_datetime))
+        .bind(stats.co2_min)
+        .bind(stats.co2_max)
+        .bind(stats.co2_stddev)
+        .bind(stats.co2_samples_raw)
+        .bind(stats.co2_samples_kept)
+        .bind(stats.co2_first_at.map(to_datetime))
+        .bind(stats.co2_last_at.map(to_datetime))
+        .bind(&data.senders)
+        .bind(&data.destinations)
+        .bind(data.rejected.duplicate_samples)
+}
 
-    Ok(())
+
+/// Reconstruye el agregado guardado en una fila de `measurement`.
+fn from_row(row: &PgRow) -> Result<ProcessedTelemetry, sqlx::Error> {
+    let timestamp = |column: &str| -> Result<Option<i64>, sqlx::Error> {
+        Ok(row.try_get::<Option<DateTime<Utc>>, _>(column)?.map(|at| at.timestamp()))
+    };
+
+    Ok(ProcessedTelemetry {
+        network_id: row.try_get("network_id")?,
+        timestamp: row.try_get::<DateTime<Utc>, _>("timestamp")?.timestamp(),
+        temperature: row.try_get("temperature")?,
+        humidity: row.try_get("humidity")?,
+        co2_ppm: row.try_get("co2_ppm")?,
+        pulse_counter_total: row.try_get("pulse_counter")?,
+        pulse_max_duration: row.try_get("pulse_max_duration")?,
+        window_secs: row.try_get::<i32, _>("window_secs")? as i64,
+        rejected: RejectedSamples {
+            temperature_out_of_range: row.try_get("temperature_out_of_range")?,
+            temperature_outliers: row.try_get("temperature_outliers")?,
+            humidity_out_of_range: row.try_get("humidity_out_of_range")?,
+            humidity_outliers: row.try_get("humidity_outliers")?,
+            co2_out_of_range: row.try_get("co2_out_of_range")?,
+            co2_outliers: row.try_get("co2_outliers")?,
+            duplicate_samples: row.try_get("duplicate_samples")?,
+        },
+        stats: BucketStats {
+            temperature_min: row.try_get("temperature_min")?,
+            temperature_max: row.try_get("temperature_max")?,
+            temperature_stddev: row.try_get("temperature_stddev")?,
+            temperature_samples_raw: row.try_get("temperature_samples_raw")?,
+            temperature_samples_kept: row.try_get("temperature_samples_kept")?,
+            temperature_first_at: timestamp("temperature_first_at")?,
+            temperature_last_at: timestamp("temperature_last_at")?,
+            humidity_min: row.try_get("humidity_min")?,
+            humidity_max: row.try_get("humidity_max")?,
+            humidity_stddev: row.try_get("humidity_stddev")?,
+            humidity_samples_raw: row.try_get("humidity_samples_raw")?,
+            humidity_samples_kept: row.try_get("humidity_samples_kept")?,
+            humidity_first_at: timestamp("humidity_first_at")?,
+            humidity_last_at: timestamp("humidity_last_at")?,
+            co2_min: row.try_get("co2_min")?,
+            co2_max: row.try_get("co2_max")?,
+            co2_stddev: row.try_get("co2_stddev")?,
+            co2_samples_raw: row.try_get("co2_samples_raw")?,
+            co2_samples_kept: row.try_get("co2_samples_kept")?,
+            co2_first_at: timestamp("co2_first_at")?,
+            co2_last_at: timestamp("co2_last_at")?,
+        },
+        strategies: AggregationStrategies {
+            temperature_strategy: row.try_get("temperature_strategy")?,
+            humidity_strategy: row.try_get("humidity_strategy")?,
+            co2_strategy: row.try_get("co2_strategy")?,
+        },
+        senders: row.try_get("senders")?,
+        destinations: row.try_get("destinations")?,
+        aggregate_id: String::new(),
+        deliveries: Vec::new(),
+    })
 }
@@ -4,50 +4,67 @@
 //! para monitoreo de infraestructura.
 
 use chrono::DateTime;
-use sqlx::{PgPool, Postgres, QueryBuilder};
+use sqlx::{PgConnection, Postgres};
+use sqlx::query_builder::Separated;
+use crate::database::tables::bulk::{insert_rows, BulkRow, CopyBuffer};
 use crate::message::domain::{SystemMetrics};
 
 
+impl BulkRow for SystemMetrics {
+    const TABLE: &'static str = "metric";
+    const COLUMNS: &'static [&'static str] = &[
+        "sender_user_id", "destination_id", "timestamp",
+        "uptime_seconds", "cpu_usage_percent", "cpu_temp_celsius",
+        "ram_total_mb", "ram_used_mb", "sd_total_gb", "sd_used_gb", "sd_usage_percent",
+        "network_rx_bytes", "network_tx_bytes", "wifi_rssi", "wifi_signal_dbm",
+    ];
+    const CONFLICT: &'static str = "(sender_user_id, timestamp)";
+
+    fn push_binds(self, mut b: Separated<'_, '_, Postgres, &'static str>) {
+        b.push_bind(self.metadata.sender_user_id)
+            .push_bind(self.metadata.destination_id)
+            .push_bind(DateTime::from_timestamp(self.metadata.timestamp, 0).unwrap_or_default())
+            .push_bind(self.uptime_seconds as i64)
+            .push_bind(self.cpu_usage_percent)
+            .push_bind(self.cpu_temp_celsius)
+            .push_bind(self.ram_total_mb as i64)
+            .push_bind(self.ram_used_mb as i64)
+            .push_bind(self.sd_total_gb as i64)
+            .push_bind(self.sd_used_gb as i64)
+            .push_bind(self.sd_usage_percent)
+            .push_bind(self.network_rx_bytes as i64)
+            .push_bind(self.network_tx_bytes as i64)
+            .push_bind(self.wifi_rssi)
+            .push_bind(self.wifi_signal_dbm);
+    }
+
+    fn encode(&self, buffer: &mut CopyBuffer) {
+        buffer.text(&self.metadata.sender_user_id)
+            .text(&self.metadata.destination_id)
+            .timestamp(self.metadata.timestamp)
+            .int8(self.uptime_seconds as i64)
+            .float4(self.cpu_usage_percent)
+            .float4(self.cpu_temp_celsius)
+            .int8(self.ram_total_mb as i64)
+            .int8(self.ram_used_mb as i64)
+            .int8(self.sd_total_gb as i64)
+            .int8(self.sd_used_gb as i64)
+            .float4(self.sd_usage_percent)
+            .int8(self.network_rx_bytes as i64)
+            .int8(self.network_tx_bytes as i64)
+            .opt_int4(self.wifi_rssi)
+            .opt_int4(self.wifi_signal_dbm);
+    }
+}
+
+
 /// Inserta métricas del sistema manejando automáticamente los campos opcionales.
 ///
-/// Si los campos `Option` en Rust son `None`, `sqlx` insertará `NULL` en SQL.
-pub async fn insert_system_metrics(pool: &PgPool,
-                                   data_vec: Vec<SystemMetrics>
+/// Si los campos `Option` en Rust son `None`, se inserta `NULL` en SQL.
+pub async fn insert_system_metrics(conn: &mut PgConnection,
+                                   data_vec: Vec<SystemMetrics>,
+                                   copy_threshold: usize
 ) -> Result<(), sqlx::Error> {
 
-    if data_vec.is_empty() {
-        return Ok(());
-    }
-
-    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
-        "INSERT INTO metric (
-            sender_user_id, destination_id, timestamp,
-            uptime_seconds, cpu_usage_percent, cpu_temp_celsius,
-            ram_total_mb, ram_used_mb, sd_total_gb, sd_used_gb, sd_usage_percent,
-            network_rx_bytes, network_tx_bytes, wifi_rssi, wifi_signal_dbm
-        ) "
-    );
-
-    query_builder.push_values(data_vec, |mut b, data| {
-        b.push_bind(data.metadata.sender_user_id)
-            .push_bind(data.metadata.destination_id)
-            .push_bind(DateTime::from_timestamp(data.metadata.timestamp, 0).unwrap_or_default())
-            .push_bind(data.uptime_seconds as i64)
-            .push_bind(data.cpu_usage_percent)
-            .push_bind(data.cpu_temp_celsius)
-            .push_bind(data.ram_total_mb as i64)
-            .push_bind(data.ram_used_mb as i64)
-            .push_bind(data.sd_total_gb as i64)
-            .push_bind(data.sd_used_gb as i64)
-            .push_bind(data.sd_usage_percent)
-            .push_bind(data.network_rx_bytes as i64)
-            .push_bind(data.network_tx_bytes as i64)
-            .push_bind(data.wifi_rssi)
-            .push_bind(data.wifi_signal_dbm);
-    });
-
-    let query = query_builder.build();
-    query.execute(pool).await?;
-
-    Ok(())
-}
\ No newline at end of file
+    insert_rows(conn, data_vec, copy_threshold).await
+}
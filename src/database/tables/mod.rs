@@ -1,6 +1,9 @@
 pub mod alert_air;
 pub mod alert_temp;
+pub mod bulk;
+pub mod late_measurement;
 pub mod measurement;
 pub mod metrics;
 pub mod monitor;
+pub mod raw_measurement;
 pub mod weather;
\ No newline at end of file
@@ -4,51 +4,71 @@
 //! de las tareas FreeRTOS en el microcontrolador.
 
 use chrono::DateTime;
-use sqlx::{PgPool, Postgres, QueryBuilder};
+use sqlx::{PgConnection, Postgres};
+use sqlx::query_builder::Separated;
+use crate::database::tables::bulk::{insert_rows, BulkRow, CopyBuffer};
 use crate::message::domain::Monitor;
 
 
-/// Batch insert para datos de diagnóstico de firmware.
-pub async fn insert_monitor(pool: &PgPool,
-                            data_vec: Vec<Monitor>
-) -> Result<(), sqlx::Error> {
+impl BulkRow for Monitor {
+    const TABLE: &'static str = "monitor";
+    const COLUMNS: &'static [&'static str] = &[
+        "sender_user_id", "destination_id", "timestamp", "network_id",
+        "mem_free", "mem_free_hm", "mem_free_block", "mem_free_internal",
+        "stack_free_min_coll", "stack_free_min_pub", "stack_free_min_mic",
+        "stack_free_min_th", "stack_free_min_air", "stack_free_min_mon",
+        "wifi_ssid", "wifi_rssi", "active_time",
+    ];
+    const CONFLICT: &'static str = "(sender_user_id, network_id, timestamp)";
+
+    fn push_binds(self, mut b: Separated<'_, '_, Postgres, &'static str>) {
+        b.push_bind(self.metadata.sender_user_id)
+            .push_bind(self.metadata.destination_id)
+            .push_bind(DateTime::from_timestamp(self.metadata.timestamp, 0).unwrap_or_default())
+            .push_bind(self.network)
+            .push_bind(self.mem_free)
+            .push_bind(self.mem_free_hm)
+            .push_bind(self.mem_free_block)
+            .push_bind(self.mem_free_internal)
+            .push_bind(self.stack_free_min_coll)
+            .push_bind(self.stack_free_min_pub)
+            .push_bind(self.stack_free_min_mic)
+            .push_bind(self.stack_free_min_th)
+            .push_bind(self.stack_free_min_air)
+            .push_bind(self.stack_free_min_mon)
+            .push_bind(self.wifi_ssid)
+            .push_bind(self.wifi_rssi as i32)
+            .push_bind(self.active_time);
+    }
 
-    if data_vec.is_empty() {
-        return Ok(());
+    fn encode(&self, buffer: &mut CopyBuffer) {
+        buffer.text(&self.metadata.sender_user_id)
+            .text(&self.metadata.destination_id)
+            .timestamp(self.metadata.timestamp)
+            .text(&self.network)
+            .int8(self.mem_free)
+            .int8(self.mem_free_hm)
+            .int8(self.mem_free_block)
+            .int8(self.mem_free_internal)
+            .int8(self.stack_free_min_coll)
+            .int8(self.stack_free_min_pub)
+            .int8(self.stack_free_min_mic)
+            .int8(self.stack_free_min_th)
+            .int8(self.stack_free_min_air)
+            .int8(self.stack_free_min_mon)
+            .text(&self.wifi_ssid)
+            .int4(self.wifi_rssi as i32)
+            .int8(self.active_time);
     }
+}
+
+
+/// Batch insert para datos de diagnóstico de firmware. Los lotes grandes se insertan por
+/// partes o con `COPY` (ver [`crate::database::tables::bulk`]).
+pub async fn insert_monitor(conn: &mut PgConnection,
+                            data_vec: Vec<Monitor>,
+                            copy_threshold: usize
+) -> Result<(), sqlx::Error> {
 
-    let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
-        "INSERT INTO monitor (
-            sender_user_id, destination_id, timestamp, network_id,
-            mem_free, mem_free_hm, mem_free_block, mem_free_internal,
-            stack_free_min_coll, stack_free_min_pub, stack_free_min_mic,
-            stack_free_min_th, stack_free_min_air, stack_free_min_mon,
-            wifi_ssid, wifi_rssi, active_time
-        ) "
-    );
-
-    query_builder.push_values(data_vec, |mut b, data| {
-        b.push_bind(data.metadata.sender_user_id)
-            .push_bind(data.metadata.destination_id)
-            .push_bind(DateTime::from_timestamp(data.metadata.timestamp, 0).unwrap_or_default())
-            .push_bind(data.network)
-            .push_bind(data.mem_free)
-            .push_bind(data.mem_free_hm)
-            .push_bind(data.mem_free_block)
-            .push_bind(data.mem_free_internal)
-            .push_bind(data.stack_free_min_coll)
-            .push_bind(data.stack_free_min_pub)
-            .push_bind(data.stack_free_min_mic)
-            .push_bind(data.stack_free_min_th)
-            .push_bind(data.stack_free_min_air)
-            .push_bind(data.stack_free_min_mon)
-            .push_bind(data.wifi_ssid)
-            .push_bind(data.wifi_rssi as i32)
-            .push_bind(data.active_time);
-    });
-
-    let query = query_builder.build();
-    query.execute(pool).await?;
-
-    Ok(())
-}
\ No newline at end of file
+    insert_rows(conn, data_vec, copy_threshold).await
+}
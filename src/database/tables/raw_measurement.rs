@@ -0,0 +1,117 @@
+//! Módulo de persistencia para el Archivo Crudo de Mediciones.
+//!
+//! Guarda las mediciones tal como llegaron del Edge, antes de agregarlas, y permite leerlas
+//! por red y rango de tiempo para volver a agregarlas.
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Row};
+use sqlx::query_builder::Separated;
+use crate::database::tables::bulk::{insert_rows, BulkRow, CopyBuffer};
+use crate::message::domain::{Measurement, Metadata};
+
+
+impl BulkRow for Measurement {
+    const TABLE: &'static str = "raw_measurement";
+    const COLUMNS: &'static [&'static str] = &[
+        "timestamp", "network_id", "sender_user_id", "destination_id", "sample",
+        "pulse_counter", "pulse_max_duration", "temperature", "humidity", "co2_ppm",
+    ];
+    const CONFLICT: &'static str = "(sender_user_id, network_id, timestamp, sample)";
+
+    fn push_binds(self, mut b: Separated<'_, '_, Postgres, &'static str>) {
+        b.push_bind(DateTime::from_timestamp(self.metadata.timestamp, 0).unwrap_or_default())
+            .push_bind(self.network)
+            .push_bind(self.metadata.sender_user_id)
+            .push_bind(self.metadata.destination_id)
+            .push_bind(self.sample as i64)
+            .push_bind(self.pulse_counter)
+            .push_bind(self.pulse_max_duration)
+            .push_bind(self.temperature)
+            .push_bind(self.humidity)
+            .push_bind(self.co2_ppm);
+    }
+
+    fn encode(&self, buffer: &mut CopyBuffer) {
+        buffer.timestamp(self.metadata.timestamp)
+            .text(&self.network)
+            .text(&self.metadata.sender_user_id)
+            .text(&self.metadata.destination_id)
+            .int8(self.sample as i64)
+            .int8(self.pulse_counter)
+            .int8(self.pulse_max_duration)
+            .float4(self.temperature)
+            .float4(self.humidity)
+            .float4(self.co2_ppm);
+    }
+}
+
+
+/// Inserta un lote de mediciones crudas. Las ya archivadas se ignoran.
+pub async fn insert_raw_measurements(pool: &PgPool,
+                                     data_vec: Vec<Measurement>,
+                                     copy_threshold: usize
+) -> Result<(), sqlx::Error> {
+
+    let mut conn = pool.acquire().await?;
+    insert_rows(&mut conn, data_vec, copy_threshold).await
+}
+
+
+/// Mediciones archivadas de una red con timestamp en `[from, to)` (Unix, segundos).
+pub async fn select_raw_measurements(pool: &PgPool,
+                                     network_id: &str,
+                                     from: i64,
+                                     to: i64
+) -> Result<Vec<Measurement>, sqlx::Error> {
+
+    let rows = sqlx::query(
+        r#"
+        SELECT timestamp, network_id, sender_user_id, destination_id, sample,
+               pulse_counter, pulse_max_duration, temperature, humidity, co2_ppm
+        FROM raw_measurement
+        WHERE network_id = $1 AND timestamp >= $2 AND timestamp < $3
+        ORDER BY timestamp
+        "#,
+    )
+        .bind(network_id)
+        .bind(DateTime::from_timestamp(from, 0).unwrap_or_default())
+        .bind(DateTime::from_timestamp(to, 0).unwrap_or_default())
+        .fetch_all(pool)
+        .await?;
+
+    rows.iter()
+        .map(|row| Ok(Measurement {
+            metadata: Metadata {
+                sender_user_id: row.try_get("sender_user_id")?,
+                destination_id: row.try_get("destination_id")?,
+                timestamp: row.try_get::<DateTime<Utc>, _>("timestamp")?.timestamp(),
+                // Ya se confirmaron al recibirse
+                sequence_id: 0,
+            },
+            network: row.try_get("network_id")?,
+            pulse_counter: row.try_get("pulse_counter")?,
+            pulse_max_duration: row.try_get("pulse_max_duration")?,
+            temperature: row.try_get("temperature")?,
+            humidity: row.try_get("humidity")?,
+            co2_ppm: row.try_get("co2_ppm")?,
+            sample: row.try_get::<i64, _>("sample")? as u32,
+        }))
+        .collect()
+}
+
+
+/// Elimina las mediciones con timestamp anterior a `cutoff` (Unix, segundos).
+///
+/// # Retorno
+/// Cantidad de filas eliminadas.
+pub async fn delete_raw_measurements_before(pool: &PgPool,
+                                            cutoff: i64
+) -> Result<u64, sqlx::Error> {
+
+    let result = sqlx::query("DELETE FROM raw_measurement WHERE timestamp < $1")
+        .bind(DateTime::from_timestamp(cutoff, 0).unwrap_or_default())
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
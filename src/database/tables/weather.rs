@@ -7,6 +7,8 @@ pub async fn insert_weather(pool: &PgPool, data: Weather) -> Result<(), sqlx::Er
         r#"
         INSERT INTO weather (timestamp, temperature, humidity)
         VALUES ($1, $2, $3)
+        ON CONFLICT (timestamp) DO UPDATE SET temperature = EXCLUDED.temperature,
+                                              humidity = EXCLUDED.humidity
         "#,
     )
         .bind(data.timestamp)
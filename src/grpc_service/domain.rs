@@ -0,0 +1,1010 @@
+//! Dominio del cliente gRPC: estados, política de reintentos, estado de conexión, endpoints,
+//! enrutamiento de la subida, TLS y credenciales de autenticación.
+//!
+//! Separa de `logic.rs` las estructuras que describen la conexión para que puedan ser
+//! consultadas por otras partes del sistema sin depender de la tarea `grpc_task`.
+
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use chrono::{DateTime, Utc};
+use dashmap::{DashMap, DashSet};
+use serde::Serialize;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity, Server, ServerTlsConfig};
+use tonic::{Request, Status};
+use tracing::{debug, error, info, warn};
+use crate::grpc::{FromDataSaver, Metadata, ToDataSaver, from_data_saver, to_data_saver};
+use crate::system::domain::{ErrorType, System};
+use crate::system::domain::grpc_service_const::AUTH_MAX_CLOCK_SKEW_SECS;
+
+
+/// Estados posibles de la máquina de estados del cliente gRPC.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateClient {
+    /// Estado inicial: Intentando establecer conexión TCP/HTTP2.
+    Init,
+    /// Estado operativo: El stream bidireccional está activo y transfiriendo datos.
+    Work,
+    /// Estado de fallo: Ocurrió un error y se está esperando antes de reintentar (Backoff).
+    Error,
+    /// El Edge rechazó las credenciales (`Code::Unauthenticated`). Se reintenta con backoff
+    /// releyendo el token, pero se reporta por separado de los fallos de transporte.
+    Unauthenticated,
+}
+
+
+/// Backoff exponencial con *full jitter*.
+///
+/// El techo de espera se duplica en cada intento fallido (`initial * 2^intento`) hasta
+/// `max`, y la espera real se sortea uniformemente en `[0, techo]`. Así, varias instancias
+/// que pierden la conexión a la vez no reintentan sincronizadas contra el Edge.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max, attempt: 0 }
+    }
+
+    /// Cantidad de intentos fallidos consecutivos.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Calcula la próxima espera y avanza el contador de intentos.
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = self.initial
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let ceiling_ms = ceiling.as_millis() as u64;
+        Duration::from_millis(rand::random_range(0..=ceiling_ms))
+    }
+
+    /// Vuelve al primer intento (se llama tras un período estable en `Work`).
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+
+/// Estado observable de la conexión gRPC.
+///
+/// `grpc_task` lo actualiza en cada transición; el resto del sistema lo lee con
+/// [`ConnectionStatus::snapshot`] a través de [`SharedConnectionStatus`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionStatus {
+    /// Estado actual de la máquina de estados.
+    pub state: StateClient,
+    /// Intentos de conexión fallidos consecutivos. Es siempre una copia de
+    /// [`Backoff::attempt`], el contador que determina la espera entre reintentos.
+    pub attempt: u32,
+    /// Descripción del último error observado.
+    pub last_error: Option<String>,
+    /// Momento del último error observado.
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Momento en que se estableció el stream actual (solo en `Work`).
+    pub connected_since: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    connected_at: Option<Instant>,
+}
+
+
+/// Estado de conexión compartido entre `grpc_task` (escritor) y los consumidores.
+pub type SharedConnectionStatus = Arc<RwLock<ConnectionStatus>>;
+
+
+/// Estado de cada conexión gRPC, indexado por endpoint (o dirección de escucha en modo servidor).
+pub type ConnectionStatusMap = Arc<DashMap<String, SharedConnectionStatus>>;
+
+
+impl Default for ConnectionStatus {
+    fn default() -> Self {
+        Self {
+            state: StateClient::Init,
+            attempt: 0,
+            last_error: None,
+            last_error_at: None,
+            connected_since: None,
+            connected_at: None,
+        }
+    }
+}
+
+
+impl ConnectionStatus {
+
+    /// Registra el inicio de un intento de conexión.
+    pub fn set_init(&mut self, attempt: u32) {
+        self.state = StateClient::Init;
+        self.attempt = attempt;
+    }
+
+    /// Registra que el stream bidireccional quedó establecido.
+    ///
+    /// El contador de intentos no vuelve a cero al conectar: lo hace cuando el backoff se
+    /// reinicia, tras un período estable en `Work`.
+    pub fn set_work(&mut self, attempt: u32) {
+        self.state = StateClient::Work;
+        self.attempt = attempt;
+        self.connected_since = Some(Utc::now());
+        self.connected_at = Some(Instant::now());
+    }
+
+    /// Registra el intento de reconexión cuya espera está en curso.
+    pub fn set_attempt(&mut self, attempt: u32) {
+        self.attempt = attempt;
+    }
+
+    /// Registra un fallo y abandona el estado `Work`.
+    pub fn set_error(&mut self, error: String) {
+        self.set_failure(StateClient::Error, error);
+    }
+
+    /// Registra que el Edge rechazó las credenciales.
+    pub fn set_unauthenticated(&mut self, error: String) {
+        self.set_failure(StateClient::Unauthenticated, error);
+    }
+
+    fn set_failure(&mut self, state: StateClient, error: String) {
+        self.state = state;
+        self.last_error = Some(error);
+        self.last_error_at = Some(Utc::now());
+        self.connected_since = None;
+        self.connected_at = None;
+    }
+
+    /// Tiempo que lleva establecido el stream actual, o `None` si no está en `Work`.
+    pub fn time_connected(&self) -> Option<Duration> {
+        self.connected_at.map(|at| at.elapsed())
+    }
+
+    /// Aplica una modificación bajo el lock de escritura.
+    pub fn update(status: &SharedConnectionStatus, f: impl FnOnce(&mut ConnectionStatus)) {
+        match status.write() {
+            Ok(mut guard) => f(&mut guard),
+            Err(poisoned) => f(&mut poisoned.into_inner()),
+        }
+    }
+
+    /// Crea el estado de una conexión y lo publica en el mapa bajo `label`.
+    pub fn register(map: &ConnectionStatusMap, label: String) -> SharedConnectionStatus {
+        let status = Arc::new(RwLock::new(ConnectionStatus::default()));
+        map.insert(label, status.clone());
+        status
+    }
+
+    /// Copia consistente del estado actual.
+    pub fn snapshot(status: &SharedConnectionStatus) -> ConnectionStatus {
+        match status.read() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+
+/// Endpoint de un Edge al que se conecta el cliente gRPC (`host:puerto`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrpcEndpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+
+impl GrpcEndpoint {
+
+    /// Interpreta una lista separada por comas (`GRPC_ENDPOINTS`), ej. `edge-a:50052,edge-b:50052`.
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, String> {
+        let endpoints = list.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                let (host, port) = item.rsplit_once(':')
+                    .ok_or_else(|| format!("endpoint sin puerto: {item}"))?;
+                let port = port.parse()
+                    .map_err(|_| format!("puerto inválido en {item}"))?;
+                if host.is_empty() {
+                    return Err(format!("endpoint sin host: {item}"));
+                }
+                Ok(GrpcEndpoint { host: host.to_string(), port })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if endpoints.is_empty() {
+            return Err("la lista de endpoints está vacía".to_string());
+        }
+        Ok(endpoints)
+    }
+}
+
+
+impl fmt::Display for GrpcEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+
+/// Identificador interno de una sesión (un endpoint en modo cliente, un stream en modo servidor).
+pub type SessionId = u64;
+
+/// Extremo de envío hacia el carril de subida de un Edge.
+pub type SessionSender = mpsc::Sender<FromDataSaver>;
+
+
+/// Sesiones hacia los Edge y rutas `sender_user_id -> sesión`.
+///
+/// Los mensajes de subida de `message_upload` salen por un único canal; el registro los
+/// reparte entre las sesiones. Las confirmaciones Ack/Nack deben volver por la sesión del
+/// emisor original, por lo que el registro aprende qué `sender_user_id` llega por cada
+/// sesión a medida que entran los mensajes.
+///
+/// En modo servidor cada sesión lleva la identidad con la que se autenticó el Edge (con su
+/// propio secreto, ver [`ClientSecrets`]), y solo aprende el emisor que coincide con ella: un
+/// Edge no puede reclamar el `sender_user_id` de otro y quedarse con sus confirmaciones.
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    next_id: AtomicU64,
+    sessions: DashMap<SessionId, SessionSender>,
+    identities: DashMap<SessionId, String>,
+    routes: DashMap<String, SessionId>,
+    /// Emisores ya rechazados por sesión, para advertirlo una sola vez.
+    refused: DashSet<(SessionId, String)>,
+}
+
+
+impl SessionRegistry {
+
+    /// Registra una nueva sesión y devuelve su identificador.
+    ///
+    /// `identity` es la identidad autenticada del Edge (modo servidor con `GRPC_AUTH_MODE`),
+    /// o `None` si la sesión puede aprender cualquier emisor.
+    pub fn open(&self, tx: SessionSender, identity: Option<String>) -> SessionId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.sessions.insert(id, tx);
+        if let Some(identity) = identity {
+            self.identities.insert(id, identity);
+        }
+        info!("Info: sesión gRPC {} abierta ({} activas)", id, self.sessions.len());
+        id
+    }
+
+    /// Elimina la sesión y todas las rutas que apuntaban a ella.
+    pub fn close(&self, id: SessionId) {
+        self.sessions.remove(&id);
+        self.identities.remove(&id);
+        self.routes.retain(|_, session| *session != id);
+        self.refused.retain(|(session, _)| *session != id);
+        info!("Info: sesión gRPC {} cerrada ({} activas)", id, self.sessions.len());
+    }
+
+    /// Asocia a la sesión los emisores presentes en el mensaje entrante.
+    ///
+    /// Si un mismo emisor aparece en otra sesión (ej. el Edge se reconectó), la ruta
+    /// pasa a la sesión más reciente. Si la sesión tiene identidad, los emisores distintos
+    /// de ella no se aprenden: sus datos se procesan, pero no reciben confirmaciones.
+    pub fn learn(&self, id: SessionId, msg: &ToDataSaver) {
+        let identity = self.identities.get(&id).map(|identity| identity.clone());
+        for sender in sender_ids(msg) {
+            if identity.as_deref().is_some_and(|identity| identity != sender) {
+                if self.refused.insert((id, sender.to_string())) {
+                    warn!("Warning: la sesión {} autenticada como {:?} envía datos de {}; no recibirá sus confirmaciones",
+                        id, identity, sender);
+                }
+                continue;
+            }
+            if self.routes.get(sender).is_some_and(|session| *session == id) {
+                continue;
+            }
+            debug!("Debug: emisor {} asociado a la sesión {}", sender, id);
+            self.routes.insert(sender.to_string(), id);
+        }
+    }
+
+    /// Entrega un mensaje de subida a la sesión que corresponda.
+    ///
+    /// * **Ack/Nack:** a la sesión del emisor indicado en `metadata.destination_id`.
+    /// * **Heartbeat:** a todas las sesiones.
+    ///
+    /// Usa `try_send`: un Edge lento o caído no debe frenar al resto. Un Ack perdido se recupera
+    /// con el reenvío del Edge (entrega al menos una vez).
+    pub fn dispatch(&self, msg: FromDataSaver) {
+        match &msg.payload {
+            Some(from_data_saver::Payload::Heartbeat(_)) => {
+                for session in self.sessions.iter() {
+                    if session.value().try_send(msg.clone()).is_err() {
+                        warn!("Warning: no se pudo enviar el heartbeat a la sesión {}", session.key());
+                    }
+                }
+            }
+            Some(from_data_saver::Payload::Ack(ack)) => self.route(destination(&ack.metadata), msg.clone()),
+            Some(from_data_saver::Payload::Nack(nack)) => self.route(destination(&nack.metadata), msg.clone()),
+            None => {}
+        }
+    }
+
+    fn route(&self, destination: &str, msg: FromDataSaver) {
+        let Some(id) = self.routes.get(destination).map(|session| *session) else {
+            warn!("Warning: no hay sesión activa para {}; se descarta la confirmación", destination);
+            return;
+        };
+        let Some(tx) = self.sessions.get(&id).map(|tx| tx.clone()) else {
+            return;
+        };
+        if tx.try_send(msg).is_err() {
+            warn!("Warning: no se pudo enviar la confirmación a la sesión {}", id);
+        }
+    }
+}
+
+
+fn destination(metadata: &Option<Metadata>) -> &str {
+    metadata.as_ref().map(|m| m.destination_id.as_str()).unwrap_or_default()
+}
+
+
+/// Emisores (`sender_user_id`) de los datos contenidos en un mensaje del Edge.
+fn sender_ids(msg: &ToDataSaver) -> Vec<&str> {
+    use to_data_saver::Payload;
+
+    let metadata: Vec<&Option<Metadata>> = match &msg.payload {
+        Some(Payload::Measurement(m)) => vec![&m.metadata],
+        Some(Payload::Monitor(m)) => vec![&m.metadata],
+        Some(Payload::Metric(m)) => vec![&m.metadata],
+        Some(Payload::AlertAir(m)) => vec![&m.metadata],
+        Some(Payload::AlertTh(m)) => vec![&m.metadata],
+        Some(Payload::MeasurementBatch(b)) => b.measurements.iter().map(|m| &m.metadata).collect(),
+        Some(Payload::MonitorBatch(b)) => b.monitors.iter().map(|m| &m.metadata).collect(),
+        Some(Payload::AlertAirBatch(b)) => b.alerts.iter().map(|m| &m.metadata).collect(),
+        Some(Payload::AlertThBatch(b)) => b.alerts.iter().map(|m| &m.metadata).collect(),
+        None => Vec::new(),
+    };
+
+    let mut senders: Vec<&str> = metadata.into_iter()
+        .filter_map(|m| m.as_ref().map(|m| m.sender_user_id.as_str()))
+        .filter(|sender| !sender.is_empty())
+        .collect();
+    senders.sort_unstable();
+    senders.dedup();
+    senders
+}
+
+
+/// Construye la configuración TLS del canal gRPC saliente a partir de `System`.
+///
+/// # Retorno
+/// * `Ok(None)` si TLS está deshabilitado (`GRPC_TLS=false`).
+/// * `Ok(Some(config))` con la CA (o las raíces del sistema), la identidad del cliente
+///   para mTLS y el dominio SNI, ya validados.
+/// * `Err(ErrorType::Tls)` si algún archivo falta o no contiene PEM válido. La validación
+///   se hace al arrancar para fallar de inmediato y no en cada intento de reconexión.
+pub fn load_tls_config(system: &System) -> Result<Option<ClientTlsConfig>, ErrorType> {
+    if !system.grpc_tls_enabled {
+        if system.grpc_tls_ca_cert.is_some() || system.grpc_tls_client_cert.is_some() {
+            warn!("Warning: hay certificados TLS configurados pero GRPC_TLS=false; se ignoran");
+        }
+        return Ok(None);
+    }
+
+    info!("Info: cargando configuración TLS de gRPC");
+
+    let mut config = match &system.grpc_tls_ca_cert {
+        Some(path) => ClientTlsConfig::new()
+            .ca_certificate(Certificate::from_pem(read_pem(path, "CERTIFICATE")?)),
+        None => ClientTlsConfig::new().with_enabled_roots(),
+    };
+
+    config = match (&system.grpc_tls_client_cert, &system.grpc_tls_client_key) {
+        (Some(cert), Some(key)) => {
+            config.identity(Identity::from_pem(read_pem(cert, "CERTIFICATE")?, read_pem(key, "PRIVATE KEY")?))
+        }
+        (None, None) => config,
+        _ => return Err(ErrorType::Tls(
+            "GRPC_TLS_CLIENT_CERT y GRPC_TLS_CLIENT_KEY deben configurarse juntos".to_string()
+        )),
+    };
+
+    if let Some(domain) = &system.grpc_tls_domain {
+        config = config.domain_name(domain.clone());
+    }
+
+    // `tls_config` parsea certificados y clave, y arma el conector rustls
+    let url = match system.grpc_endpoints.first() {
+        Some(endpoint) => format!("https://{endpoint}"),
+        None => format!("https://{}:{}", system.grpc_host, system.grpc_port),
+    };
+    Channel::from_shared(url)
+        .map_err(|e| ErrorType::Tls(format!("URL inválida: {e}")))?
+        .tls_config(config.clone())
+        .map_err(|e| ErrorType::Tls(format!("configuración inválida: {e}")))?;
+
+    Ok(Some(config))
+}
+
+
+/// Construye la configuración TLS del servidor de ingesta (`GRPC_MODE=server`).
+///
+/// Usa los mismos archivos que el modo cliente: `GRPC_TLS_CLIENT_CERT` y `GRPC_TLS_CLIENT_KEY`
+/// son el certificado y la clave propios del servicio, y `GRPC_TLS_CA_CERT`, si se define, la
+/// CA que firma los certificados de los Edge. Con CA la conexión exige mTLS.
+///
+/// # Retorno
+/// * `Ok(None)` si `GRPC_TLS=false`.
+/// * `Err(ErrorType::Tls)` si falta el certificado o la clave, o si no son PEM válidos.
+pub fn load_server_tls_config(system: &System) -> Result<Option<ServerTlsConfig>, ErrorType> {
+    if !system.grpc_tls_enabled {
+        return Ok(None);
+    }
+
+    info!("Info: cargando configuración TLS del servidor de ingesta");
+
+    let identity = match (&system.grpc_tls_client_cert, &system.grpc_tls_client_key) {
+        (Some(cert), Some(key)) => Identity::from_pem(read_pem(cert, "CERTIFICATE")?, read_pem(key, "PRIVATE KEY")?),
+        _ => return Err(ErrorType::Tls(
+            "el servidor de ingesta requiere GRPC_TLS_CLIENT_CERT y GRPC_TLS_CLIENT_KEY".to_string()
+        )),
+    };
+
+    let mut config = ServerTlsConfig::new().identity(identity);
+    if let Some(path) = &system.grpc_tls_ca_cert {
+        config = config.client_ca_root(Certificate::from_pem(read_pem(path, "CERTIFICATE")?));
+    }
+
+    // `tls_config` parsea certificado y clave, y arma el aceptador rustls
+    Server::builder()
+        .tls_config(config.clone())
+        .map_err(|e| ErrorType::Tls(format!("configuración inválida: {e}")))?;
+
+    Ok(Some(config))
+}
+
+
+/// Lee un archivo PEM y verifica que contenga al menos un bloque del tipo esperado.
+///
+/// `tonic` acepta silenciosamente un archivo sin bloques PEM, lo que dejaría al cliente
+/// sin CA de confianza; por eso se valida aquí.
+fn read_pem(path: &str, kind: &str) -> Result<Vec<u8>, ErrorType> {
+    let pem = fs::read(path)
+        .map_err(|e| ErrorType::Tls(format!("no se pudo leer {path}: {e}")))?;
+
+    let text = String::from_utf8_lossy(&pem);
+    let has_block = text.lines()
+        .any(|line| line.starts_with("-----BEGIN ") && line.contains(kind));
+    if !has_block {
+        return Err(ErrorType::Tls(format!("{path} no contiene un bloque PEM {kind}")));
+    }
+    Ok(pem)
+}
+
+
+/// Esquema de autenticación de las peticiones gRPC (`GRPC_AUTH_MODE`): salientes en modo
+/// cliente, entrantes en el servidor de ingesta.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthMode {
+    /// Sin credenciales.
+    None,
+    /// Cabeceras `authorization: Bearer <token>` y `x-client-id`.
+    Bearer,
+    /// Cabeceras `x-client-id`, `x-timestamp` y `x-signature`, donde la firma es
+    /// `hex(HMAC-SHA256(secreto, "<client_id>:<timestamp>"))`.
+    Hmac,
+}
+
+
+impl AuthMode {
+    pub fn from_config(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "none" => Ok(AuthMode::None),
+            "bearer" => Ok(AuthMode::Bearer),
+            "hmac" => Ok(AuthMode::Hmac),
+            other => Err(format!("modo de autenticación desconocido: {other}")),
+        }
+    }
+}
+
+
+/// Rol del servicio en la conexión gRPC con los Edge (`GRPC_MODE`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GrpcMode {
+    /// El Data Saver se conecta a cada Edge de `GRPC_ENDPOINTS` (o a `GRPC_HOST:GRPC_PORT` si está
+    /// vacía) con un `ConnectStream` por endpoint.
+    Client,
+    /// El Data Saver escucha en `GRPC_HOST:GRPC_PORT` y los Edge empujan sus datos (`PushStream`).
+    Server,
+}
+
+
+impl GrpcMode {
+    pub fn from_config(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "client" => Ok(GrpcMode::Client),
+            "server" => Ok(GrpcMode::Server),
+            other => Err(format!("modo gRPC desconocido: {other}")),
+        }
+    }
+}
+
+
+/// Token vigente y fecha de modificación del archivo del que se leyó.
+#[derive(Debug)]
+struct TokenState {
+    secret: String,
+    modified: Option<SystemTime>,
+}
+
+
+/// Credenciales que se adjuntan a cada petición hacia el Edge.
+///
+/// Si el secreto proviene de `GRPC_AUTH_TOKEN_FILE`, antes de cada petición se compara la
+/// fecha de modificación del archivo y se relee si cambió, de modo que una rotación del token
+/// se aplica en la siguiente reconexión sin reiniciar el servicio.
+#[derive(Debug)]
+pub struct AuthCredentials {
+    mode: AuthMode,
+    client_id: String,
+    token_file: Option<PathBuf>,
+    state: Mutex<TokenState>,
+}
+
+
+impl AuthCredentials {
+
+    /// Construye las credenciales desde `System`.
+    ///
+    /// # Retorno
+    /// * `Ok(None)` si `GRPC_AUTH_MODE=none`.
+    /// * `Err(ErrorType::Auth)` si el modo requiere un secreto y no hay uno válido.
+    pub fn from_system(system: &System) -> Result<Option<Arc<Self>>, ErrorType> {
+        if system.grpc_auth_mode == AuthMode::None {
+            return Ok(None);
+        }
+        if !system.grpc_tls_enabled {
+            warn!("Warning: autenticación gRPC habilitada sin TLS; las credenciales viajan en claro");
+        }
+
+        let token_file = system.grpc_auth_token_file.as_ref().map(PathBuf::from);
+        let state = match (&token_file, &system.grpc_auth_token) {
+            (Some(path), _) => read_token_file(path)?,
+            (None, Some(token)) => TokenState { secret: token.trim().to_string(), modified: None },
+            (None, None) => return Err(ErrorType::Auth(
+                "se requiere GRPC_AUTH_TOKEN o GRPC_AUTH_TOKEN_FILE".to_string()
+            )),
+        };
+        if state.secret.is_empty() {
+            return Err(ErrorType::Auth("el token de autenticación está vacío".to_string()));
+        }
+
+        info!("Info: autenticación gRPC habilitada en modo {:?}", system.grpc_auth_mode);
+        Ok(Some(Arc::new(Self {
+            mode: system.grpc_auth_mode,
+            client_id: system.grpc_auth_client_id.clone(),
+            token_file,
+            state: Mutex::new(state),
+        })))
+    }
+
+    /// Relee el archivo del token si cambió desde la última lectura.
+    ///
+    /// Si la relectura falla o el archivo quedó vacío (ej. rotación a medio escribir),
+    /// se conserva el token anterior.
+    fn refresh(&self, state: &mut TokenState) {
+        let Some(path) = &self.token_file else {
+            return;
+        };
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == state.modified {
+            return;
+        }
+        match read_token_file(path) {
+            Ok(new_state) if !new_state.secret.is_empty() => {
+                info!("Info: token de autenticación gRPC recargado desde {}", path.display());
+                *state = new_state;
+            }
+            Ok(_) => warn!("Warning: {} está vacío; se conserva el token anterior", path.display()),
+            Err(e) => warn!("Warning: no se pudo recargar el token; se conserva el anterior. {}", e),
+        }
+    }
+
+    /// Agrega las cabeceras de autenticación a la petición.
+    fn apply<T>(&self, request: &mut Request<T>) -> Result<(), String> {
+        let mut state = match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        self.refresh(&mut state);
+
+        let headers = request.metadata_mut();
+        match self.mode {
+            AuthMode::None => {}
+            AuthMode::Bearer => {
+                headers.insert("authorization", header_value(&format!("Bearer {}", state.secret))?);
+                headers.insert("x-client-id", header_value(&self.client_id)?);
+            }
+            AuthMode::Hmac => {
+                let timestamp = Utc::now().timestamp().to_string();
+                let mut mac = Hmac::<Sha256>::new_from_slice(state.secret.as_bytes())
+                    .map_err(|e| format!("clave HMAC inválida: {e}"))?;
+                mac.update(format!("{}:{}", self.client_id, timestamp).as_bytes());
+                let signature = hex::encode(mac.finalize().into_bytes());
+
+                headers.insert("x-client-id", header_value(&self.client_id)?);
+                headers.insert("x-timestamp", header_value(&timestamp)?);
+                headers.insert("x-signature", header_value(&signature)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Estado de [`ClientSecrets`]: secreto de cada Edge y fecha de modificación del archivo.
+#[derive(Debug)]
+struct SecretsState {
+    secrets: HashMap<String, String>,
+    modified: Option<SystemTime>,
+}
+
+
+/// Secretos con los que el servidor de ingesta verifica cada `PushStream`, uno por Edge.
+///
+/// `GRPC_AUTH_CLIENTS_FILE` es un objeto JSON `{"client_id": "secreto"}`. Cada Edge presenta
+/// (o firma con) su propio secreto, así que conocerlo no permite autenticarse con el
+/// `x-client-id` de otro. El archivo se relee cuando cambia su fecha de modificación.
+#[derive(Debug)]
+pub struct ClientSecrets {
+    mode: AuthMode,
+    path: PathBuf,
+    state: Mutex<SecretsState>,
+}
+
+
+impl ClientSecrets {
+
+    /// Construye los secretos desde `System`.
+    ///
+    /// # Retorno
+    /// * `Ok(None)` si `GRPC_AUTH_MODE=none`.
+    /// * `Err(ErrorType::Auth)` si falta `GRPC_AUTH_CLIENTS_FILE` o no es válido.
+    pub fn from_system(system: &System) -> Result<Option<Arc<Self>>, ErrorType> {
+        if system.grpc_auth_mode == AuthMode::None {
+            return Ok(None);
+        }
+        if !system.grpc_tls_enabled {
+            warn!("Warning: autenticación gRPC habilitada sin TLS; las credenciales viajan en claro");
+        }
+
+        let path = system.grpc_auth_clients_file.as_ref()
+            .map(PathBuf::from)
+            .ok_or_else(|| ErrorType::Auth("se requiere GRPC_AUTH_CLIENTS_FILE en modo server".to_string()))?;
+        let state = read_clients_file(&path)?;
+
+        info!("Info: autenticación gRPC habilitada en modo {:?} para {} Edge", system.grpc_auth_mode, state.secrets.len());
+        Ok(Some(Arc::new(Self {
+            mode: system.grpc_auth_mode,
+            path,
+            state: Mutex::new(state),
+        })))
+    }
+
+    /// Relee el archivo de secretos si cambió desde la última lectura. Si la relectura falla
+    /// se conservan los secretos anteriores.
+    fn refresh(&self, state: &mut SecretsState) {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == state.modified {
+            return;
+        }
+        match read_clients_file(&self.path) {
+            Ok(new_state) => {
+                info!("Info: secretos de los Edge recargados desde {}", self.path.display());
+                *state = new_state;
+            }
+            Err(e) => warn!("Warning: no se pudieron recargar los secretos; se conservan los anteriores. {}", e),
+        }
+    }
+
+    /// Verifica las credenciales de una petición entrante con el secreto de su `x-client-id`
+    /// y el mismo esquema que usa el modo cliente.
+    ///
+    /// # Retorno
+    /// La identidad autenticada: el `x-client-id` de la petición, que en modo `hmac` forma
+    /// parte de la firma. En modo `bearer` el Edge también debe enviarlo.
+    fn verify<T>(&self, request: &Request<T>) -> Result<String, String> {
+        let mut state = match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        self.refresh(&mut state);
+
+        let headers = request.metadata();
+        let header = |name: &str| headers.get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| format!("falta la cabecera {name}"));
+
+        let client_id = header("x-client-id")?;
+        let secret = state.secrets.get(client_id)
+            .ok_or_else(|| format!("x-client-id desconocido: {client_id}"))?;
+        match self.mode {
+            AuthMode::None => {}
+            AuthMode::Bearer => {
+                let token = header("authorization")?
+                    .strip_prefix("Bearer ")
+                    .ok_or("la cabecera authorization no es Bearer")?;
+                if !constant_time_eq(token.as_bytes(), secret.as_bytes()) {
+                    return Err("token inválido".to_string());
+                }
+            }
+            AuthMode::Hmac => {
+                let timestamp = header("x-timestamp")?;
+                let issued: i64 = timestamp.parse().map_err(|_| "x-timestamp inválido".to_string())?;
+                if (Utc::now().timestamp() - issued).abs() > AUTH_MAX_CLOCK_SKEW_SECS {
+                    return Err(format!("x-timestamp fuera de la tolerancia de {AUTH_MAX_CLOCK_SKEW_SECS} s"));
+                }
+                let signature = hex::decode(header("x-signature")?)
+                    .map_err(|_| "x-signature no es hexadecimal".to_string())?;
+
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                    .map_err(|e| format!("clave HMAC inválida: {e}"))?;
+                mac.update(format!("{client_id}:{timestamp}").as_bytes());
+                mac.verify_slice(&signature).map_err(|_| "firma inválida".to_string())?;
+            }
+        }
+        Ok(client_id.to_string())
+    }
+}
+
+
+/// Compara en tiempo constante para no filtrar el token por la duración de la comparación.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+
+/// Identidad autenticada de una petición entrante, que [`AuthVerifier`] deja en sus extensiones.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedClient(pub String);
+
+
+/// Interceptor de `tonic` del servidor de ingesta: verifica las credenciales de cada
+/// `PushStream` y lo rechaza con `Status::unauthenticated` si no son válidas.
+///
+/// Sin secretos configurados (`GRPC_AUTH_MODE=none`) deja pasar la petición sin identidad.
+#[derive(Clone, Debug, Default)]
+pub struct AuthVerifier {
+    secrets: Option<Arc<ClientSecrets>>,
+}
+
+
+impl AuthVerifier {
+    pub fn new(secrets: Option<Arc<ClientSecrets>>) -> Self {
+        Self { secrets }
+    }
+}
+
+
+impl Interceptor for AuthVerifier {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(secrets) = &self.secrets {
+            let client = secrets.verify(&request).map_err(|e| {
+                warn!("Warning: PushStream rechazado desde {:?}. {}", request.remote_addr(), e);
+                Status::unauthenticated(e)
+            })?;
+            request.extensions_mut().insert(AuthenticatedClient(client));
+        }
+        Ok(request)
+    }
+}
+
+
+/// Interceptor de `tonic` que adjunta las credenciales a cada petición saliente.
+///
+/// Sin credenciales (`None`) deja pasar la petición intacta, lo que permite usar siempre
+/// el mismo tipo de cliente.
+#[derive(Clone, Debug, Default)]
+pub struct AuthInterceptor {
+    credentials: Option<Arc<AuthCredentials>>,
+}
+
+
+impl AuthInterceptor {
+    pub fn new(credentials: Option<Arc<AuthCredentials>>) -> Self {
+        Self { credentials }
+    }
+}
+
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(credentials) = &self.credentials {
+            credentials.apply(&mut request).map_err(|e| {
+                error!("Error: no se pudieron adjuntar las credenciales gRPC. {}", e);
+                Status::internal(e)
+            })?;
+        }
+        Ok(request)
+    }
+}
+
+
+fn read_token_file(path: &PathBuf) -> Result<TokenState, ErrorType> {
+    let secret = fs::read_to_string(path)
+        .map_err(|e| ErrorType::Auth(format!("no se pudo leer {}: {e}", path.display())))?;
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    Ok(TokenState { secret: secret.trim().to_string(), modified })
+}
+
+
+fn read_clients_file(path: &PathBuf) -> Result<SecretsState, ErrorType> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| ErrorType::Auth(format!("no se pudo leer {}: {e}", path.display())))?;
+    let secrets = parse_client_secrets(&content)
+        .map_err(|e| ErrorType::Auth(format!("{} inválido: {e}", path.display())))?;
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+    Ok(SecretsState { secrets, modified })
+}
+
+
+/// Interpreta el JSON de `GRPC_AUTH_CLIENTS_FILE`. Exige al menos un Edge y ningún secreto vacío.
+fn parse_client_secrets(content: &str) -> Result<HashMap<String, String>, String> {
+    let secrets: HashMap<String, String> = serde_json::from_str(content)
+        .map_err(|e| format!("se esperaba un objeto {{\"client_id\": \"secreto\"}}: {e}"))?;
+    let secrets: HashMap<String, String> = secrets.into_iter()
+        .map(|(client_id, secret)| (client_id.trim().to_string(), secret.trim().to_string()))
+        .collect();
+    if secrets.is_empty() {
+        return Err("no define ningún Edge".to_string());
+    }
+    if let Some((client_id, _)) = secrets.iter().find(|(client_id, secret)| client_id.is_empty() || secret.is_empty()) {
+        return Err(format!("el Edge '{client_id}' tiene un identificador o secreto vacío"));
+    }
+    Ok(secrets)
+}
+
+
+fn header_value(value: &str) -> Result<MetadataValue<tonic::metadata::Ascii>, String> {
+    value.parse()
+        .map_err(|_| "credencial con caracteres no válidos para una cabecera".to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grpc::Measurement as GrpcMeasurement;
+
+    fn credentials(mode: AuthMode, client_id: &str, secret: &str) -> AuthCredentials {
+        AuthCredentials {
+            mode,
+            client_id: client_id.to_string(),
+            token_file: None,
+            state: Mutex::new(TokenState { secret: secret.to_string(), modified: None }),
+        }
+    }
+
+    fn server(mode: AuthMode) -> ClientSecrets {
+        let secrets = HashMap::from([
+            ("edge-1".to_string(), "secreto-1".to_string()),
+            ("edge-2".to_string(), "secreto-2".to_string()),
+        ]);
+        ClientSecrets {
+            mode,
+            path: PathBuf::new(),
+            state: Mutex::new(SecretsState { secrets, modified: None }),
+        }
+    }
+
+    fn signed(credentials: &AuthCredentials) -> Request<()> {
+        let mut request = Request::new(());
+        credentials.apply(&mut request).expect("no se pudieron adjuntar las credenciales");
+        request
+    }
+
+    fn from_sender(sender: &str) -> ToDataSaver {
+        ToDataSaver {
+            payload: Some(to_data_saver::Payload::Measurement(GrpcMeasurement {
+                metadata: Some(Metadata { sender_user_id: sender.to_string(), ..Default::default() }),
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn accepts_the_credentials_the_client_sends() {
+        for mode in [AuthMode::Bearer, AuthMode::Hmac] {
+            let credentials = credentials(mode, "edge-1", "secreto-1");
+            assert_eq!(server(mode).verify(&signed(&credentials)), Ok("edge-1".to_string()));
+        }
+    }
+
+    #[test]
+    fn rejects_a_wrong_secret_or_a_forged_client_id() {
+        for mode in [AuthMode::Bearer, AuthMode::Hmac] {
+            let server = server(mode);
+            assert!(server.verify(&signed(&credentials(mode, "edge-1", "otro"))).is_err());
+            assert!(server.verify(&signed(&credentials(mode, "edge-3", "secreto-1"))).is_err());
+
+            // Un Edge no puede presentarse como otro con su propio secreto
+            assert!(server.verify(&signed(&credentials(mode, "edge-2", "secreto-1"))).is_err());
+
+            let mut request = signed(&credentials(mode, "edge-1", "secreto-1"));
+            request.metadata_mut().remove("x-client-id");
+            assert!(server.verify(&request).is_err());
+        }
+
+        let mut request = signed(&credentials(AuthMode::Hmac, "edge-1", "secreto-1"));
+        request.metadata_mut().insert("x-client-id", "edge-2".parse().unwrap());
+        assert!(server(AuthMode::Hmac).verify(&request).is_err());
+    }
+
+    #[test]
+    fn rejects_stale_hmac_timestamps() {
+        let timestamp = (Utc::now().timestamp() - AUTH_MAX_CLOCK_SKEW_SECS - 60).to_string();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secreto-1").unwrap();
+        mac.update(format!("edge-1:{timestamp}").as_bytes());
+
+        let mut request = Request::new(());
+        request.metadata_mut().insert("x-client-id", "edge-1".parse().unwrap());
+        request.metadata_mut().insert("x-timestamp", timestamp.parse().unwrap());
+        request.metadata_mut().insert("x-signature", hex::encode(mac.finalize().into_bytes()).parse().unwrap());
+        assert!(server(AuthMode::Hmac).verify(&request).is_err());
+    }
+
+    #[test]
+    fn parses_one_secret_per_client() {
+        let secrets = parse_client_secrets(r#"{"edge-1": " secreto-1 ", "edge-2": "secreto-2"}"#).unwrap();
+        assert_eq!(secrets.get("edge-1").map(String::as_str), Some("secreto-1"));
+        assert_eq!(secrets.len(), 2);
+
+        for content in ["{}", r#"{"edge-1": ""}"#, r#"{"": "secreto"}"#, r#"["secreto"]"#, "secreto"] {
+            assert!(parse_client_secrets(content).is_err(), "{content} debería rechazarse");
+        }
+    }
+
+    #[test]
+    fn sessions_only_learn_their_authenticated_sender() {
+        let registry = SessionRegistry::default();
+        let (tx_a, _rx_a) = mpsc::channel(1);
+        let (tx_b, _rx_b) = mpsc::channel(1);
+        let a = registry.open(tx_a, Some("edge-a".to_string()));
+        let b = registry.open(tx_b, Some("edge-b".to_string()));
+
+        registry.learn(a, &from_sender("edge-a"));
+        registry.learn(b, &from_sender("edge-a"));
+        registry.learn(b, &from_sender("edge-c"));
+
+        assert_eq!(registry.routes.get("edge-a").map(|id| *id), Some(a));
+        assert!(registry.routes.get("edge-c").is_none());
+
+        registry.close(b);
+        assert!(registry.refused.is_empty());
+    }
+
+    #[test]
+    fn sessions_without_identity_learn_any_sender() {
+        let registry = SessionRegistry::default();
+        let (tx, _rx) = mpsc::channel(1);
+        let session = registry.open(tx, None);
+
+        registry.learn(session, &from_sender("edge-a"));
+        assert_eq!(registry.routes.get("edge-a").map(|id| *id), Some(session));
+    }
+}
@@ -5,87 +5,133 @@
 //! (Conexión inicial, Streaming de datos, Reconexión ante fallos).
 //!
 //! # Características
-//! * **Autorecuperación:** Reintenta la conexión automáticamente tras fallos.
+//! * **Multi-Edge:** Una tarea `grpc_task` por cada endpoint de `GRPC_ENDPOINTS`, con su propia
+//!   máquina de estados y backoff. Un Edge caído no frena a los demás.
+//! * **Autorecuperación:** Reintenta la conexión con backoff exponencial y *full jitter*.
+//! * **Observabilidad:** Publica estado, intentos y último error por endpoint en `AppContext::grpc_status`.
 //! * **Bidireccional:** Soporta envío y recepción simultánea (Full Duplex).
 //! * **Optimizado:** Utiliza compresión Gzip y Keep-Alive HTTP/2.
+//! * **Seguro:** Soporta TLS y mTLS (`GRPC_TLS`), validados al arrancar, y adjunta
+//!   credenciales (Bearer o HMAC) mediante un interceptor.
 
 
-use tonic::transport::{Channel};
+use tonic::transport::{Channel, ClientTlsConfig};
 use tonic::codec::CompressionEncoding;
-use tonic::Request;
+use tonic::{Code, Request, Status};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 use tracing::{error, info, instrument, warn};
+use std::sync::Arc;
 use std::time::Duration;
 use crate::context::domain::AppContext;
 use crate::grpc::{ToDataSaver, FromDataSaver};
 use crate::grpc::data_service_client::DataServiceClient;
-use crate::system::domain::{InternalEvent, ErrorType, System};
+use crate::grpc_service::domain::{AuthInterceptor, Backoff, ConnectionStatus, GrpcEndpoint, SessionId, SessionRegistry, SharedConnectionStatus, StateClient};
+use crate::supervisor::domain::shared;
+use crate::supervisor::logic::supervise;
+use crate::system::domain::{InternalEvent, ErrorType};
 use crate::system::domain::grpc_service_const::{KEEP_ALIVE_INTERVAL_SECS, KEEP_ALIVE_TIMEOUT_SECS, TIMEOUT_SECS};
 
 
-/// Estados posibles de la máquina de estados del cliente gRPC.
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum StateClient {
-    /// Estado inicial: Intentando establecer conexión TCP/HTTP2.
-    Init,
-    /// Estado operativo: El stream bidireccional está activo y transfiriendo datos.
-    Work,
-    /// Estado de fallo: Ocurrió un error y se está esperando antes de reintentar (Backoff).
-    Error,
-}
-
-
 /// Crea y configura el canal de transporte gRPC (Channel).
 ///
 /// Aplica configuraciones críticas de red como Timeouts y Keep-Alive para evitar
 /// que intermediarios (Firewalls, Load Balancers) cierren la conexión silenciosamente.
 ///
 /// # Argumentos
-/// * `system`: Configuración del sistema que contiene host y puerto.
+/// * `endpoint`: Host y puerto del Edge.
+/// * `tls`: Configuración TLS ya validada; si es `None` el canal es `http://` en claro.
 ///
 /// # Retorno
 /// Retorna un `Channel` listo para ser usado por el cliente `IotServiceClient`.
-async fn create_channel(system: &System) -> Result<Channel, ErrorType> {
+async fn create_channel(endpoint: &GrpcEndpoint, tls: Option<&ClientTlsConfig>) -> Result<Channel, ErrorType> {
 
     info!("Info: creando canal gRPC");
-    let url = format!("http://{}:{}", system.grpc_host, system.grpc_port);
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let url = format!("{}://{}", scheme, endpoint);
 
-    let endpoint = Channel::from_shared(url)
-        .map_err(|_| ErrorType::Endpoint)?
+    let mut endpoint = Channel::from_shared(url.clone())
+        .map_err(|e| ErrorType::Endpoint(format!("URL inválida {url}: {e}")))?
         .connect_timeout(Duration::from_secs(TIMEOUT_SECS))
         .keep_alive_timeout(Duration::from_secs(KEEP_ALIVE_TIMEOUT_SECS))
         .http2_keep_alive_interval(Duration::from_secs(KEEP_ALIVE_INTERVAL_SECS))
         .keep_alive_while_idle(true);
 
+    if let Some(tls) = tls {
+        endpoint = endpoint.tls_config(tls.clone())
+            .map_err(|e| ErrorType::Tls(e.to_string()))?;
+    }
+
     endpoint.connect().await.map_err(|e| {
         error!("Error: no se pudo conectar gRPC: {}", e);
-        ErrorType::Endpoint
+        ErrorType::Endpoint(format!("no se pudo conectar a {url}: {e}"))
     })
 }
 
 
-/// Tarea principal (Actor) que gestiona el ciclo de vida de la comunicación gRPC.
+/// Tarea principal (Actor) que gestiona el ciclo de vida de la comunicación gRPC con un Edge.
 ///
 /// Implementa un bucle infinito controlado por una máquina de estados:
 /// 1. **Init:** Crea el cliente y establece el `ConnectStream`.
 /// 2. **Work:** Usa `tokio::select!` para multiplexar envío y recepción.
-/// 3. **Error:** Limpia recursos y espera 5 segundos antes de volver a Init.
+/// 3. **Error:** Limpia recursos y espera según el backoff antes de volver a Init.
+/// 4. **Unauthenticated:** Igual que Error, pero se reporta aparte: el Edge rechazó las
+///    credenciales. El token se relee (si proviene de archivo) en el siguiente intento.
+///
+/// # Backoff
+/// La espera crece exponencialmente desde `grpc_backoff_initial_ms` hasta `grpc_backoff_max_secs`
+/// con *full jitter*. Si el stream estuvo en `Work` al menos `grpc_backoff_reset_secs`,
+/// la secuencia vuelve a empezar desde el primer intento.
 ///
 /// # Flujo de Datos
-/// * **Upstream (Subida):** Recibe `DataSaverUpload` de `rx_from_server` y lo envía al router.
-/// * **Downstream (Bajada):** Recibe `DataSaverDownload` del router y lo envía a `tx_to_msg`.
+/// * **Upstream (Subida):** Recibe de `rx_from_server` los `FromDataSaver` que el registro de
+///   sesiones asignó a este endpoint y los envía al Edge.
+/// * **Downstream (Bajada):** Recibe `ToDataSaver` del Edge, registra sus emisores en la sesión
+///   y lo envía a `tx_to_msg` etiquetado con el endpoint de origen.
 #[instrument(
     name = "grpc_task",
-    skip(tx_to_msg, rx_from_server, app_context)
+    skip(tx_to_msg, rx_from_server, session, registry, status, app_context),
+    fields(endpoint = %endpoint)
 )]
 pub async fn grpc_task(tx_to_msg: mpsc::Sender<InternalEvent>,
-                       mut rx_from_server: mpsc::Receiver<FromDataSaver>,
+                       rx_from_server: &mut mpsc::Receiver<FromDataSaver>,
+                       endpoint: GrpcEndpoint,
+                       session: SessionId,
+                       registry: Arc<SessionRegistry>,
+                       status: SharedConnectionStatus,
                        app_context: AppContext) {
 
     info!("Info: grpc task creada");
 
+    let shutdown = app_context.shutdown.clone();
+
+    // Al apagar se cancela la máquina de estados: se deja de recibir del Edge y se suelta
+    // `tx_to_msg` para que el resto del pipeline se vacíe. Lo que el Edge envió y no llegó
+    // a confirmarse lo reenviará en la próxima conexión.
+    tokio::select! {
+        _ = client_loop(tx_to_msg, rx_from_server, endpoint, session, registry, status, app_context) => {},
+        _ = shutdown.wait() => info!("Info: apagado en curso, grpc task deja de recibir datos"),
+    }
+}
+
+
+/// Máquina de estados de `grpc_task`. Solo termina al ser cancelada.
+async fn client_loop(tx_to_msg: mpsc::Sender<InternalEvent>,
+                     rx_from_server: &mut mpsc::Receiver<FromDataSaver>,
+                     endpoint: GrpcEndpoint,
+                     session: SessionId,
+                     registry: Arc<SessionRegistry>,
+                     status: SharedConnectionStatus,
+                     app_context: AppContext) {
+
+    let system = &app_context.system;
+    let status = &status;
+    let label = endpoint.to_string();
+    let mut backoff = Backoff::new(Duration::from_millis(system.grpc_backoff_initial_ms),
+                                   Duration::from_secs(system.grpc_backoff_max_secs));
+    let stable_after = Duration::from_secs(system.grpc_backoff_reset_secs);
+
     let mut state = StateClient::Init;
     let mut tx_session: Option<mpsc::Sender<FromDataSaver>> = None;
     let mut inbound_stream: Option<tonic::Streaming<ToDataSaver>> = None;
@@ -93,10 +139,13 @@ pub async fn grpc_task(tx_to_msg: mpsc::Sender<InternalEvent>,
     loop {
         match state {
             StateClient::Init => {
-                match create_channel(&app_context.system).await {
+                ConnectionStatus::update(status, |s| s.set_init(backoff.attempt()));
+
+                match create_channel(&endpoint, app_context.grpc_tls.as_ref()).await {
                     Ok(channel) => {
                         info!("Info: canal gRPC creado correctamente");
-                        let mut grpc_client = DataServiceClient::new(channel)
+                        let interceptor = AuthInterceptor::new(app_context.grpc_auth.clone());
+                        let mut grpc_client = DataServiceClient::with_interceptor(channel, interceptor)
                             .send_compressed(CompressionEncoding::Gzip)
                             .accept_compressed(CompressionEncoding::Gzip);
 
@@ -108,16 +157,19 @@ pub async fn grpc_task(tx_to_msg: mpsc::Sender<InternalEvent>,
                                 info!("Info: gRPC Conectado. Stream Bidireccional iniciado");
                                 tx_session = Some(tx_sess);
                                 inbound_stream = Some(response.into_inner());
+                                ConnectionStatus::update(status, |s| s.set_work(backoff.attempt()));
                                 state = StateClient::Work;
                             }
                             Err(e) => {
                                 error!("Error: no se pudo conectar al canal gRPC. {}", e);
-                                state = StateClient::Error;
+                                state = failure_state(&e);
+                                record_failure(status, state, format!("ConnectStream rechazado: {e}"));
                             }
                         }
                     }
                     Err(e) => {
-                        error!("Error: canal gRPC no creado. {:?}", e);
+                        error!("Error: canal gRPC no creado. {}", e);
+                        ConnectionStatus::update(status, |s| s.set_error(e.to_string()));
                         state = StateClient::Error;
                     }
                 }
@@ -137,42 +189,60 @@ pub async fn grpc_task(tx_to_msg: mpsc::Sender<InternalEvent>,
                         Ok(())
                     };
 
+                    // El downstream siempre termina en fallo: devuelve el próximo estado y el motivo
                     let downstream = async {
                         while let Some(server_msg) = stream.next().await {
                             match server_msg {
                                 Ok(download_msg) => {
-                                    if tx_to_msg_clone.send(InternalEvent::IncomingMessage(download_msg)).await.is_err() {
-                                        return Err("Error: fallo al enviar el mensaje al dominio interno".to_string());
+                                    registry.learn(session, &download_msg);
+                                    let event = InternalEvent::IncomingMessage { endpoint: label.clone(), message: download_msg };
+                                    if tx_to_msg_clone.send(event).await.is_err() {
+                                        return (StateClient::Error, "Error: fallo al enviar el mensaje al dominio interno".to_string());
                                     }
                                 }
-                                Err(e) => return Err(format!("Error en el stream gRPC: {}", e)),
+                                Err(e) => return (failure_state(&e), format!("Error en el stream gRPC: {}", e)),
                             }
                         }
-                        Err::<(), String>("Error: el servidor cerró el stream de bajada".to_string())
+                        (StateClient::Error, "Error: el servidor cerró el stream de bajada".to_string())
                     };
 
-                    tokio::select! {
+                    let (next_state, reason) = tokio::select! {
                         res_up = upstream => {
                             warn!("Warning: upstream abortado. Motivo: {:?}", res_up);
-                            state = StateClient::Error;
+                            (StateClient::Error, format!("upstream abortado: {:?}", res_up))
                         }
-                        res_down = downstream => {
-                            warn!("Warning: downstream abortado. Motivo: {:?}", res_down);
-                            state = StateClient::Error;
+                        (next_state, reason) = downstream => {
+                            warn!("Warning: downstream abortado. Motivo: {}", reason);
+                            (next_state, format!("downstream abortado: {}", reason))
                         }
+                    };
+
+                    let connected_for = ConnectionStatus::snapshot(status).time_connected();
+                    if connected_for.is_some_and(|d| d >= stable_after) {
+                        backoff.reset();
                     }
+                    record_failure(status, next_state, reason);
+                    state = next_state;
                 } else {
                     warn!("Warning: estado Work sin stream válido, reiniciando...");
                     state = StateClient::Init;
                 }
             }
 
-            StateClient::Error => {
-                info!("Info: StateClient Error, limpiando recursos y haciendo backpressure");
+            StateClient::Error | StateClient::Unauthenticated => {
                 tx_session = None;
                 inbound_stream = None;
 
-                tokio::time::sleep(Duration::from_secs(5)).await;
+                let delay = backoff.next_delay();
+                ConnectionStatus::update(status, |s| s.set_attempt(backoff.attempt()));
+                if state == StateClient::Unauthenticated {
+                    error!("Error: el Edge rechazó las credenciales gRPC. Reintento {} en {:?}", backoff.attempt(), delay);
+                } else {
+                    info!("Info: StateClient Error, limpiando recursos. Reintento {} en {:?}", backoff.attempt(), delay);
+                }
+
+                tokio::time::sleep(delay).await;
+                app_context.metrics.grpc_reconnects.with_label_values(&[&label]).inc();
                 state = StateClient::Init;
             }
         }
@@ -180,20 +250,105 @@ pub async fn grpc_task(tx_to_msg: mpsc::Sender<InternalEvent>,
 }
 
 
-/// Inicializa y lanza la tarea gRPC en segundo plano.
+/// Determina el estado de fallo según el código gRPC: las credenciales rechazadas se
+/// distinguen de los fallos de transporte.
+fn failure_state(status: &Status) -> StateClient {
+    if status.code() == Code::Unauthenticated {
+        StateClient::Unauthenticated
+    } else {
+        StateClient::Error
+    }
+}
+
+
+/// Publica el fallo en el estado de conexión compartido.
+fn record_failure(status: &SharedConnectionStatus, state: StateClient, reason: String) {
+    ConnectionStatus::update(status, |s| {
+        if state == StateClient::Unauthenticated {
+            s.set_unauthenticated(reason);
+        } else {
+            s.set_error(reason);
+        }
+    });
+}
+
+
+/// Reparte los mensajes de subida (Heartbeat, Ack, Nack) entre las sesiones abiertas.
+///
+/// La usan tanto el modo cliente (una sesión por endpoint) como el servidor de ingesta
+/// (una sesión por `PushStream`).
+#[instrument(
+    name = "upload_dispatch_task",
+    skip(rx_from_msg, registry)
+)]
+pub async fn upload_dispatch_task(rx_from_msg: &mut mpsc::Receiver<FromDataSaver>,
+                                  registry: Arc<SessionRegistry>) {
+
+    info!("Info: upload dispatch task creada");
+
+    while let Some(msg) = rx_from_msg.recv().await {
+        registry.dispatch(msg);
+    }
+    info!("Info: upload dispatch task finalizada");
+}
+
+
+/// Inicializa y lanza una tarea gRPC por cada endpoint configurado, más el dispatcher
+/// que reparte entre ellas los mensajes de subida.
 ///
 /// # Argumentos
-/// * `tx_to_msg`: Canal para enviar los mensajes recibidos del servidor hacia la lógica de negocio.
-/// * `rx_from_msg`: Canal para recibir los mensajes que deben ser enviados al servidor.
+/// * `tx_to_msg`: Canal para enviar los mensajes recibidos de los Edge hacia la lógica de negocio.
+/// * `rx_from_msg`: Canal para recibir los mensajes que deben ser enviados a los Edge.
 /// * `app_context`: Contexto global de la aplicación.
 pub fn start_grpc(tx_to_msg: mpsc::Sender<InternalEvent>,
                   rx_from_msg: mpsc::Receiver<FromDataSaver>,
                   app_context: AppContext) {
 
-    info!("Info: iniciando tarea grpc");
-    tokio::spawn(async move {
-        grpc_task(tx_to_msg,
-                  rx_from_msg,
-                  app_context).await;
+    info!("Info: iniciando tareas grpc para {} endpoint(s)", app_context.system.grpc_endpoints.len());
+    let registry = Arc::new(SessionRegistry::default());
+
+    for endpoint in app_context.system.grpc_endpoints.clone() {
+        let (tx_session, rx_session) = mpsc::channel::<FromDataSaver>(100);
+        let session = registry.open(tx_session, None);
+        let status = ConnectionStatus::register(&app_context.grpc_status, endpoint.to_string());
+        let rx_session = shared(rx_session);
+        let tx_to_msg = tx_to_msg.clone();
+        let registry = registry.clone();
+        let ctx = app_context.clone();
+
+        supervise(format!("grpc_task[{endpoint}]"), true, &app_context, move || {
+            let tx_to_msg = tx_to_msg.clone();
+            let rx_session = rx_session.clone();
+            let endpoint = endpoint.clone();
+            let registry = registry.clone();
+            let status = status.clone();
+            let ctx = ctx.clone();
+            async move {
+                grpc_task(tx_to_msg,
+                          &mut *rx_session.lock().await,
+                          endpoint,
+                          session,
+                          registry,
+                          status,
+                          ctx).await;
+            }
+        });
+    }
+
+    start_upload_dispatch(rx_from_msg, registry, &app_context);
+}
+
+
+/// Lanza `upload_dispatch_task` bajo el supervisor.
+pub fn start_upload_dispatch(rx_from_msg: mpsc::Receiver<FromDataSaver>,
+                             registry: Arc<SessionRegistry>,
+                             app_context: &AppContext) {
+    let rx_from_msg = shared(rx_from_msg);
+    supervise("upload_dispatch_task", true, app_context, move || {
+        let rx_from_msg = rx_from_msg.clone();
+        let registry = registry.clone();
+        async move {
+            upload_dispatch_task(&mut *rx_from_msg.lock().await, registry).await;
+        }
     });
-}
\ No newline at end of file
+}
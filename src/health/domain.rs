@@ -0,0 +1,69 @@
+//! Reportes de salud que devuelven `/healthz` y `/readyz`.
+
+
+use std::collections::BTreeMap;
+use serde::Serialize;
+use crate::grpc_service::domain::ConnectionStatus;
+use crate::supervisor::domain::TaskHealth;
+
+
+/// Resultado global de un endpoint de salud.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Ok,
+    Fail,
+}
+
+
+impl HealthStatus {
+    pub fn from_ok(ok: bool) -> Self {
+        if ok { HealthStatus::Ok } else { HealthStatus::Fail }
+    }
+}
+
+
+/// Resultado de una verificación individual.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+
+impl Check {
+    pub fn ok() -> Self {
+        Self { ok: true, detail: None }
+    }
+
+    pub fn fail(detail: impl Into<String>) -> Self {
+        Self { ok: false, detail: Some(detail.into()) }
+    }
+}
+
+
+/// Respuesta de `/healthz`: el proceso y sus actores están vivos.
+#[derive(Debug, Clone, Serialize)]
+pub struct Liveness {
+    pub status: HealthStatus,
+    pub shutting_down: bool,
+    /// Salud de cada tarea supervisada.
+    pub tasks: BTreeMap<String, TaskHealth>,
+}
+
+
+/// Respuesta de `/readyz`: el servicio puede recibir y persistir datos.
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub status: HealthStatus,
+    pub shutting_down: bool,
+    /// La base de datos responde `SELECT 1`.
+    pub database: Check,
+    /// Todas las conexiones gRPC están en `Work`.
+    pub grpc: Check,
+    /// Estado de cada conexión gRPC (endpoint o dirección de escucha).
+    pub grpc_connections: BTreeMap<String, ConnectionStatus>,
+    /// El sweeper hizo su último tick hace menos de `SWEEPER_STALE_SECS` (o tres intervalos).
+    pub sweeper: Check,
+}
@@ -0,0 +1,111 @@
+//! Endpoints de salud para el orquestador (Docker, Kubernetes).
+//!
+//! * `GET /healthz` (liveness): falla si alguna tarea crítica dejó de ejecutarse fuera
+//!   del apagado. Una tarea no crítica caída se informa pero no hace fallar el chequeo.
+//! * `GET /readyz` (readiness): falla si la base de datos no responde, si alguna conexión
+//!   gRPC no está en `Work`, si el sweeper no hizo tick recientemente o si el servicio se
+//!   está apagando.
+//!
+//! Ambos responden `200` o `503` con el detalle en JSON.
+
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use crate::bucket::logic::SWEEPER_TASK;
+use crate::context::domain::AppContext;
+use crate::grpc_service::domain::{ConnectionStatus, StateClient};
+use crate::health::domain::{Check, HealthStatus, Liveness, Readiness};
+use crate::system::domain::health_const::{DB_PING_TIMEOUT_SECS, SWEEPER_STALE_SECS};
+
+
+/// Handler de `GET /healthz`.
+pub async fn healthz_handler(State(app_context): State<AppContext>) -> Response {
+    let shutting_down = app_context.shutdown.is_triggered();
+    let tasks = app_context.tasks.snapshot();
+
+    let critical_down = tasks.iter()
+        .any(|(_, health)| health.critical && !health.is_alive());
+    let ok = shutting_down || !critical_down;
+
+    let report = Liveness {
+        status: HealthStatus::from_ok(ok),
+        shutting_down,
+        tasks: tasks.into_iter().collect(),
+    };
+    respond(ok, report)
+}
+
+
+/// Handler de `GET /readyz`.
+pub async fn readyz_handler(State(app_context): State<AppContext>) -> Response {
+    let shutting_down = app_context.shutdown.is_triggered();
+
+    let database = check_database(&app_context).await;
+
+    let grpc_connections: BTreeMap<String, ConnectionStatus> = app_context.grpc_status.iter()
+        .map(|entry| (entry.key().clone(), ConnectionStatus::snapshot(entry.value())))
+        .collect();
+    let not_working: Vec<&str> = grpc_connections.iter()
+        .filter(|(_, status)| status.state != StateClient::Work)
+        .map(|(label, _)| label.as_str())
+        .collect();
+    let grpc = if not_working.is_empty() {
+        Check::ok()
+    } else {
+        Check::fail(format!("conexiones fuera de Work: {}", not_working.join(", ")))
+    };
+
+    let sweeper = check_sweeper(&app_context);
+
+    let ok = !shutting_down && database.ok && grpc.ok && sweeper.ok;
+    let report = Readiness {
+        status: HealthStatus::from_ok(ok),
+        shutting_down,
+        database,
+        grpc,
+        grpc_connections,
+        sweeper,
+    };
+    respond(ok, report)
+}
+
+
+/// Ejecuta `SELECT 1` con un plazo acotado para no colgar el chequeo.
+async fn check_database(app_context: &AppContext) -> Check {
+    match tokio::time::timeout(Duration::from_secs(DB_PING_TIMEOUT_SECS), app_context.repo.ping()).await {
+        Ok(Ok(())) => Check::ok(),
+        Ok(Err(e)) => Check::fail(e.to_string()),
+        Err(_) => Check::fail(format!("SELECT 1 no respondió en {DB_PING_TIMEOUT_SECS}s")),
+    }
+}
+
+
+/// Verifica la antigüedad del último tick del sweeper.
+fn check_sweeper(app_context: &AppContext) -> Check {
+    let Some(health) = app_context.tasks.get(SWEEPER_TASK) else {
+        return Check::fail("sweeper no registrado");
+    };
+    let stale_after = SWEEPER_STALE_SECS.max(3 * app_context.system.bucket.tick_secs() as i64);
+    match health.last_beat_at {
+        Some(at) => {
+            let age = (Utc::now() - at).num_seconds();
+            if age <= stale_after {
+                Check::ok()
+            } else {
+                Check::fail(format!("último tick hace {age}s"))
+            }
+        }
+        None => Check::fail("el sweeper todavía no hizo tick"),
+    }
+}
+
+
+fn respond<T: serde::Serialize>(ok: bool, report: T) -> Response {
+    let code = if ok { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (code, Json(report)).into_response()
+}
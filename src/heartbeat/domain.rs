@@ -12,6 +12,9 @@ use tokio::sync::mpsc;
 use tokio::time::{sleep, Duration};
 use tracing::{error, info, instrument};
 use tracing::log::debug;
+use crate::context::domain::AppContext;
+use crate::supervisor::domain::shared;
+use crate::supervisor::logic::supervise;
 
 
 /// Eventos de control para la coordinación entre el Heartbeat y su Temporizador.
@@ -45,7 +48,7 @@ pub enum Event {
     skip(tx_to_heartbeat, rx_from_heartbeat)
 )]
 pub async fn watchdog_timer_for_heartbeat(tx_to_heartbeat: mpsc::Sender<Event>,
-                                          mut rx_from_heartbeat: mpsc::Receiver<Event>) {
+                                          rx_from_heartbeat: &mut mpsc::Receiver<Event>) {
     info!("Info: watchdog timer creada");
 
     loop {
@@ -72,14 +75,21 @@ pub async fn watchdog_timer_for_heartbeat(tx_to_heartbeat: mpsc::Sender<Event>,
 /// # Argumentos
 /// * `tx_to_heartbeat`: Canal de transmisión hacia la tarea principal.
 /// * `rx_from_heartbeat`: Canal de recepción desde la tarea principal.
+/// * `app_context`: Contexto de la aplicación (supervisor).
 pub fn start_watchdog(tx_to_heartbeat: mpsc::Sender<Event>,
-                      rx_from_heartbeat: mpsc::Receiver<Event>) {
+                      rx_from_heartbeat: mpsc::Receiver<Event>,
+                      app_context: AppContext) {
 
     info!("Info: iniciando tarea watchdog timer");
-    tokio::spawn(async move {
-        watchdog_timer_for_heartbeat(
-            tx_to_heartbeat,
-            rx_from_heartbeat
-        ).await;
+    let rx_from_heartbeat = shared(rx_from_heartbeat);
+    supervise("watchdog_task", false, &app_context, move || {
+        let tx_to_heartbeat = tx_to_heartbeat.clone();
+        let rx_from_heartbeat = rx_from_heartbeat.clone();
+        async move {
+            watchdog_timer_for_heartbeat(
+                tx_to_heartbeat,
+                &mut *rx_from_heartbeat.lock().await
+            ).await;
+        }
     });
 }
\ No newline at end of file
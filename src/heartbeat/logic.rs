@@ -16,6 +16,8 @@ use tracing::{debug, error, info, instrument};
 use chrono::Utc;
 use crate::context::domain::AppContext;
 use crate::message::domain::{Heartbeat, Message, Metadata};
+use crate::supervisor::domain::shared;
+use crate::supervisor::logic::supervise;
 use super::domain::Event;
 
 
@@ -43,7 +45,7 @@ use super::domain::Event;
 )]
 pub async fn run_heartbeat(tx_event: mpsc::Sender<Event>,
                            tx_msg: mpsc::Sender<Message>,
-                           mut rx_from_watchdog: mpsc::Receiver<Event>,
+                           rx_from_watchdog: &mut mpsc::Receiver<Event>,
                            app_context: AppContext) {
 
     info!("Info: heartbeat task creada");
@@ -54,26 +56,24 @@ pub async fn run_heartbeat(tx_event: mpsc::Sender<Event>,
 
     while let Some(event) = rx_from_watchdog.recv().await {
         debug!("Debug: evento entrante del watchdog");
-        match event {
-            Event::Timeout => {
-                let timestamp = Utc::now().timestamp();
-                let metadata = Metadata {
-                    sender_user_id: "data_saver".to_string(),
-                    destination_id: "all".to_string(),
-                    timestamp
-                };
-                let heartbeat = Heartbeat {
-                    metadata,
-                    beat: true
-                };
-                if tx_msg.send(Message::Heartbeat(heartbeat)).await.is_err() {
-                    error!("Error: no se pudo enviar el mensaje de heartbeat");
-                }
-                if tx_event.send(Event::InitTimer(Duration::from_secs(app_context.system.heartbeat_interval_secs))).await.is_err() {
-                    error!("Error: no se pudo enviar el evento a heartbeat");
-                }
+        if let Event::Timeout = event {
+            let timestamp = Utc::now().timestamp();
+            let metadata = Metadata {
+                sender_user_id: "data_saver".to_string(),
+                destination_id: "all".to_string(),
+                timestamp,
+                sequence_id: 0,
+            };
+            let heartbeat = Heartbeat {
+                metadata,
+                beat: true
+            };
+            if tx_msg.send(Message::Heartbeat(heartbeat)).await.is_err() {
+                error!("Error: no se pudo enviar el mensaje de heartbeat");
+            }
+            if tx_event.send(Event::InitTimer(Duration::from_secs(app_context.system.heartbeat_interval_secs))).await.is_err() {
+                error!("Error: no se pudo enviar el evento a heartbeat");
             }
-            _ => {}
         }
     }
     info!("Info: heartbeat task finalizada");
@@ -95,12 +95,19 @@ pub fn start_heartbeat(to_watchdog: mpsc::Sender<Event>,
                        ctx: AppContext) {
 
     info!("Info: iniciando tarea heartbeat");
-    tokio::spawn(async move {
-        run_heartbeat(
-            to_watchdog,
-            to_upload_message,
-            from_watchdog,
-            ctx,
-        ).await;
+    let from_watchdog = shared(from_watchdog);
+    supervise("heartbeat_task", false, &ctx.clone(), move || {
+        let to_watchdog = to_watchdog.clone();
+        let to_upload_message = to_upload_message.clone();
+        let from_watchdog = from_watchdog.clone();
+        let ctx = ctx.clone();
+        async move {
+            run_heartbeat(
+                to_watchdog,
+                to_upload_message,
+                &mut *from_watchdog.lock().await,
+                ctx,
+            ).await;
+        }
     });
 }
\ No newline at end of file
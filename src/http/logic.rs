@@ -0,0 +1,94 @@
+//! Servidor HTTP de observabilidad.
+//!
+//! Atiende `GET /metrics`, `GET /healthz`, `GET /readyz` y `POST /archive/reaggregate` en
+//! `HTTP_HOST:HTTP_PORT`, separado del puerto gRPC. No observa la señal de apagado: sigue respondiendo mientras se
+//! vacía el pipeline (con `/readyz` en `503`) y termina con el proceso.
+//!
+//! Las rutas de administración (`/archive/*`) exigen `authorization: Bearer <HTTP_ADMIN_TOKEN>`
+//! y no se exponen si el token no está configurado.
+
+
+use std::time::Duration;
+use axum::Router;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::http::header::AUTHORIZATION;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use tokio::net::TcpListener;
+use tracing::{error, info, instrument, warn};
+use crate::archive::logic::reaggregate_handler;
+use crate::context::domain::AppContext;
+use crate::grpc_service::domain::constant_time_eq;
+use crate::health::logic::{healthz_handler, readyz_handler};
+use crate::metrics::logic::metrics_handler;
+use crate::supervisor::logic::supervise;
+
+
+/// Espera entre intentos de abrir el puerto HTTP.
+const BIND_RETRY_SECS: u64 = 5;
+
+
+/// Tarea del servidor HTTP. Reintenta abrir el puerto hasta lograrlo.
+#[instrument(
+    name = "http_server_task",
+    skip(app_context)
+)]
+pub async fn http_server_task(app_context: AppContext) {
+
+    let addr = format!("{}:{}", app_context.system.http_host, app_context.system.http_port);
+
+    let listener = loop {
+        match TcpListener::bind(&addr).await {
+            Ok(listener) => break listener,
+            Err(e) => {
+                error!("Error: no se pudo abrir el puerto HTTP {}. Reintentando en {}s. {}", addr, BIND_RETRY_SECS, e);
+                tokio::time::sleep(Duration::from_secs(BIND_RETRY_SECS)).await;
+            }
+        }
+    };
+
+    let mut router = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler));
+
+    if app_context.system.http_admin_token.is_some() {
+        router = router.route("/archive/reaggregate", post(reaggregate_handler)
+            .route_layer(middleware::from_fn_with_state(app_context.clone(), require_admin_token)));
+    } else {
+        info!("Info: rutas de administración deshabilitadas (sin HTTP_ADMIN_TOKEN)");
+    }
+    let router = router.with_state(app_context);
+
+    info!("Info: servidor HTTP escuchando en {}", addr);
+    if let Err(e) = axum::serve(listener, router).await {
+        error!("Error: el servidor HTTP terminó con error. {}", e);
+    }
+}
+
+
+/// Rechaza con `401` las peticiones sin `authorization: Bearer <HTTP_ADMIN_TOKEN>`.
+async fn require_admin_token(State(app_context): State<AppContext>, request: Request, next: Next) -> Response {
+    let expected = app_context.system.http_admin_token.as_deref().unwrap_or_default();
+    let provided = request.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    if expected.is_empty() || !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        warn!("Warning: petición de administración rechazada en {}", request.uri().path());
+        return (StatusCode::UNAUTHORIZED, "token de administración inválido").into_response();
+    }
+    next.run(request).await
+}
+
+
+/// Lanza el servidor HTTP bajo el supervisor. No es crítico: sin él el pipeline sigue funcionando.
+pub fn start_http_server(app_context: AppContext) {
+    info!("Info: iniciando servidor HTTP");
+    supervise("http_server_task", false, &app_context.clone(), move || {
+        http_server_task(app_context.clone())
+    });
+}
@@ -0,0 +1 @@
+pub mod logic;
\ No newline at end of file
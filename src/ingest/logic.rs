@@ -0,0 +1,239 @@
+//! Servidor de ingesta gRPC (`GRPC_MODE=server`).
+//!
+//! En lugar de conectarse a los Edge, el Data Saver escucha en `GRPC_HOST:GRPC_PORT`
+//! y expone `IngestService::PushStream`, al que se conectan simultáneamente todos los Edge.
+//! Los mensajes recibidos entran al mismo pipeline que en modo cliente
+//! (`InternalEvent::IncomingMessage` hacia `message_download`).
+//!
+//! # Tareas
+//! * **Servidor:** Acepta conexiones y abre una sesión por cada `PushStream`.
+//! * **Dispatcher:** `upload_dispatch_task`, compartida con el modo cliente, consume los
+//!   `FromDataSaver` de `message_upload` y los reparte entre las sesiones a través del
+//!   [`SessionRegistry`].
+//!
+//! # Seguridad
+//! `GRPC_TLS` y `GRPC_AUTH_MODE` se aplican también aquí: el servidor presenta el certificado
+//! configurado (mTLS si hay CA) y [`AuthVerifier`] rechaza con `UNAUTHENTICATED` los
+//! `PushStream` sin credenciales válidas. Cada Edge se autentica con su propio secreto de
+//! `GRPC_AUTH_CLIENTS_FILE`, y su sesión solo aprende las rutas de confirmación del
+//! `sender_user_id` igual a su identidad autenticada.
+
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::codec::CompressionEncoding;
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{debug, error, info, instrument, warn};
+use crate::context::domain::AppContext;
+use crate::grpc::{FromDataSaver, ToDataSaver};
+use crate::grpc::ingest_service_server::{IngestService, IngestServiceServer};
+use crate::grpc_service::domain::{AuthVerifier, AuthenticatedClient, Backoff, ConnectionStatus, SessionRegistry, SharedConnectionStatus};
+use crate::grpc_service::logic::start_upload_dispatch;
+use crate::shutdown::domain::ShutdownSignal;
+use crate::supervisor::logic::supervise;
+use crate::system::domain::{ErrorType, InternalEvent, System};
+use crate::system::domain::grpc_service_const::{KEEP_ALIVE_INTERVAL_SECS, KEEP_ALIVE_TIMEOUT_SECS};
+
+
+/// Implementación de `IngestService`: cada `PushStream` es una sesión independiente.
+struct IngestServer {
+    tx_to_msg: mpsc::Sender<InternalEvent>,
+    registry: Arc<SessionRegistry>,
+    shutdown: ShutdownSignal,
+}
+
+
+#[tonic::async_trait]
+impl IngestService for IngestServer {
+    type PushStreamStream = Pin<Box<dyn Stream<Item = Result<FromDataSaver, Status>> + Send + 'static>>;
+
+    async fn push_stream(&self,
+                         request: Request<Streaming<ToDataSaver>>) -> Result<Response<Self::PushStreamStream>, Status> {
+
+        let peer = request.remote_addr();
+        let identity = request.extensions().get::<AuthenticatedClient>().map(|client| client.0.clone());
+        // La IP sin puerto: el puerto efímero cambiaría la etiqueta en cada reconexión
+        let endpoint = identity.clone()
+            .or_else(|| peer.map(|peer| peer.ip().to_string()))
+            .unwrap_or_else(|| "unknown".to_string());
+        let mut inbound = request.into_inner();
+        let (tx_session, rx_session) = mpsc::channel::<FromDataSaver>(100);
+        info!("Info: Edge {:?} conectado desde {:?}", identity, peer);
+        let session = self.registry.open(tx_session, identity);
+        debug!("Debug: sesión {} abierta para {:?}", session, peer);
+
+        let tx_to_msg = self.tx_to_msg.clone();
+        let registry = self.registry.clone();
+        let shutdown = self.shutdown.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let msg = tokio::select! {
+                    msg = inbound.next() => msg,
+                    _ = shutdown.wait() => break,
+                };
+                let Some(msg) = msg else {
+                    break;
+                };
+                match msg {
+                    Ok(msg) => {
+                        registry.learn(session, &msg);
+                        let event = InternalEvent::IncomingMessage { endpoint: endpoint.clone(), message: msg };
+                        if tx_to_msg.send(event).await.is_err() {
+                            error!("Error: fallo al enviar el mensaje al dominio interno");
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Warning: stream de la sesión {} abortado. {}", session, e);
+                        break;
+                    }
+                }
+            }
+            debug!("Debug: fin del stream de la sesión {}", session);
+            registry.close(session);
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx_session).map(Ok))))
+    }
+}
+
+
+/// Resuelve `GRPC_HOST:GRPC_PORT` a la dirección de escucha.
+fn listen_addr(system: &System) -> Result<SocketAddr, ErrorType> {
+    (system.grpc_host.as_str(), system.grpc_port)
+        .to_socket_addrs()
+        .map_err(|e| ErrorType::Endpoint(format!("dirección de escucha inválida: {e}")))?
+        .next()
+        .ok_or_else(|| ErrorType::Endpoint(format!("{} no resuelve a ninguna dirección", system.grpc_host)))
+}
+
+
+/// Tarea principal del servidor de ingesta.
+///
+/// Abre el puerto y atiende conexiones hasta que el servidor falla; en ese caso publica
+/// el error en `AppContext::grpc_status` y vuelve a intentar con backoff. Mientras el
+/// puerto está abierto el estado es `Work`, haya o no Edges conectados. Termina al apagar.
+#[instrument(
+    name = "ingest_server_task",
+    skip(tx_to_msg, registry, status, app_context)
+)]
+pub async fn ingest_server_task(tx_to_msg: mpsc::Sender<InternalEvent>,
+                                registry: Arc<SessionRegistry>,
+                                status: SharedConnectionStatus,
+                                app_context: AppContext) {
+
+    info!("Info: ingest server task creada");
+
+    let system = &app_context.system;
+    let status = &status;
+    let mut backoff = Backoff::new(Duration::from_millis(system.grpc_backoff_initial_ms),
+                                   Duration::from_secs(system.grpc_backoff_max_secs));
+
+    loop {
+        ConnectionStatus::update(status, |s| s.set_init(backoff.attempt()));
+
+        let incoming = listen_addr(system).and_then(|addr| {
+            TcpIncoming::new(addr, true, Some(Duration::from_secs(KEEP_ALIVE_INTERVAL_SECS)))
+                .map(|incoming| (addr, incoming))
+                .map_err(|e| ErrorType::Endpoint(format!("no se pudo escuchar en {addr}: {e}")))
+        });
+
+        let reason = match incoming {
+            Ok((addr, incoming)) => {
+                info!("Info: servidor de ingesta escuchando en {}", addr);
+                backoff.reset();
+                ConnectionStatus::update(status, |s| s.set_work(backoff.attempt()));
+
+                let service = IngestServiceServer::new(IngestServer {
+                        tx_to_msg: tx_to_msg.clone(),
+                        registry: registry.clone(),
+                        shutdown: app_context.shutdown.clone(),
+                    })
+                    .send_compressed(CompressionEncoding::Gzip)
+                    .accept_compressed(CompressionEncoding::Gzip);
+                let service = InterceptedService::new(service, AuthVerifier::new(app_context.grpc_clients.clone()));
+
+                // Al apagar se deja de aceptar conexiones; las sesiones abiertas terminan solas
+                // porque también observan la señal.
+
+                let builder = match &app_context.grpc_server_tls {
+                    Some(tls) => Server::builder().tls_config(tls.clone()),
+                    None => Ok(Server::builder()),
+                };
+
+                let result = match builder {
+                    Ok(builder) => builder
+                        .http2_keepalive_interval(Some(Duration::from_secs(KEEP_ALIVE_INTERVAL_SECS)))
+                        .http2_keepalive_timeout(Some(Duration::from_secs(KEEP_ALIVE_TIMEOUT_SECS)))
+                        .add_service(service)
+                        .serve_with_incoming_shutdown(incoming, app_context.shutdown.wait())
+                        .await,
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok(()) if app_context.shutdown.is_triggered() => {
+                        info!("Info: apagado en curso, servidor de ingesta detenido");
+                        return;
+                    }
+                    Ok(()) => "el servidor de ingesta finalizó".to_string(),
+                    Err(e) => format!("el servidor de ingesta falló: {e}"),
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+
+        error!("Error: {}", reason);
+        ConnectionStatus::update(status, |s| s.set_error(reason));
+
+        let delay = backoff.next_delay();
+        ConnectionStatus::update(status, |s| s.set_attempt(backoff.attempt()));
+        info!("Info: reintento {} del servidor de ingesta en {:?}", backoff.attempt(), delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {},
+            _ = app_context.shutdown.wait() => return,
+        }
+    }
+}
+
+
+/// Inicializa y lanza el servidor de ingesta y su dispatcher en segundo plano.
+///
+/// Recibe los mismos canales que `start_grpc`, por lo que ambos modos son intercambiables.
+///
+/// # Argumentos
+/// * `tx_to_msg`: Canal para enviar los mensajes recibidos de los Edge hacia la lógica de negocio.
+/// * `rx_from_msg`: Canal para recibir los mensajes que deben ser enviados a los Edge.
+/// * `app_context`: Contexto global de la aplicación.
+pub fn start_ingest_server(tx_to_msg: mpsc::Sender<InternalEvent>,
+                           rx_from_msg: mpsc::Receiver<FromDataSaver>,
+                           app_context: AppContext) {
+
+    info!("Info: iniciando servidor de ingesta");
+    if app_context.grpc_clients.is_none() {
+        warn!("Warning: servidor de ingesta sin GRPC_AUTH_MODE; cualquier Edge puede enviar datos y reclamar cualquier sender_user_id");
+    }
+
+    let registry = Arc::new(SessionRegistry::default());
+    let label = format!("{}:{}", app_context.system.grpc_host, app_context.system.grpc_port);
+    let status = ConnectionStatus::register(&app_context.grpc_status, label);
+
+    start_upload_dispatch(rx_from_msg, registry.clone(), &app_context);
+
+    let ctx = app_context.clone();
+    supervise("ingest_server_task", true, &app_context, move || {
+        ingest_server_task(tx_to_msg.clone(),
+                           registry.clone(),
+                           status.clone(),
+                           ctx.clone())
+    });
+}
@@ -1,11 +1,16 @@
+use crate::archive::logic::start_archive;
 use crate::bucket::logic::{start_bucket, start_sweeper};
 use crate::channels::domain::Channels;
 use crate::context::domain::AppContext;
 use crate::database::logic::{start_dba};
+use crate::grpc_service::domain::GrpcMode;
 use crate::grpc_service::logic::{start_grpc};
 use crate::heartbeat::domain::{start_watchdog};
 use crate::heartbeat::logic::{start_heartbeat};
+use crate::http::logic::start_http_server;
+use crate::ingest::logic::start_ingest_server;
 use crate::message::logic::{start_message_download, start_message_upload};
+use crate::shutdown::logic::graceful_shutdown;
 use crate::system::domain::{init_tracing};
 use crate::weather::logic::start_weather_worker;
 
@@ -19,6 +24,17 @@ mod channels;
 mod weather;
 mod alert_issuer;
 mod bucket;
+mod archive;
+mod spill;
+mod ingest;
+mod shutdown;
+mod supervisor;
+mod metrics;
+mod http;
+mod health;
+
+#[cfg(test)]
+mod tests;
 
 pub mod grpc {
     tonic::include_proto!("grpc");
@@ -33,38 +49,62 @@ async fn main() {
 
     init_tracing(&app_context.system);
 
+    channels.register_metrics(&app_context.metrics);
+    start_http_server(app_context.clone());
+
     start_heartbeat(channels.heartbeat_to_watchdog,
                     channels.heartbeat_to_upload_message,
                     channels.heartbeat_from_watchdog,
                     app_context.clone());
 
     start_watchdog(channels.watchdog_to_heartbeat,
-                   channels.watchdog_from_heartbeat);
+                   channels.watchdog_from_heartbeat,
+                   app_context.clone());
 
     start_message_upload(channels.upload_message_to_grpc,
-                         channels.upload_message_from_heartbeat);
+                         channels.upload_message_from_heartbeat,
+                         channels.upload_message_from_dba,
+                         app_context.clone());
 
     start_message_download(channels.download_message_to_dba, 
                            channels.download_message_to_bucket,
                            channels.download_message_from_grpc,
                            app_context.clone());
 
-    start_dba(channels.dba_from_download_message,
-              channels.dba_from_sweeper,
-              channels.dba_from_weather,
-              app_context.clone());
+    let dba = start_dba(channels.dba_from_download_message,
+                        channels.dba_from_sweeper,
+                        channels.dba_from_bucket,
+                        channels.dba_from_weather,
+                        channels.dba_to_upload_message,
+                        app_context.clone());
 
-    start_grpc(channels.grpc_to_download_message,
-               channels.grpc_from_upload_message,
-               app_context.clone());
+    match app_context.system.grpc_mode {
+        GrpcMode::Client => start_grpc(channels.grpc_to_download_message,
+                                       channels.grpc_from_upload_message,
+                                       app_context.clone()),
+        GrpcMode::Server => start_ingest_server(channels.grpc_to_download_message,
+                                                channels.grpc_from_upload_message,
+                                                app_context.clone()),
+    }
     
+    // Sin archivo crudo el canal se suelta y `bucket_task` no archiva nada
+    let (archive, tx_archive) = if app_context.system.raw_archive.is_enabled() {
+        (Some(start_archive(channels.archive_from_bucket, app_context.clone())), Some(channels.bucket_to_archive))
+    } else {
+        (None, None)
+    };
+
     start_bucket(channels.bucket_from_download_message, 
+                 channels.bucket_to_dba,
+                 channels.sweeper_to_dba.clone(),
+                 tx_archive,
                  app_context.clone());
     
-    start_sweeper(channels.sweeper_to_dba, 
-                  app_context.clone());
+    let sweeper = start_sweeper(channels.sweeper_to_dba, 
+                                app_context.clone());
     
-    start_weather_worker(channels.weather_to_dba);
+    start_weather_worker(channels.weather_to_dba,
+                         app_context.clone());
 
-    tokio::signal::ctrl_c().await.unwrap();
+    graceful_shutdown(sweeper, dba, archive, app_context).await;
 }
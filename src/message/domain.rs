@@ -17,6 +17,29 @@ pub struct Metadata {
     pub sender_user_id: String,
     pub destination_id: String,
     pub timestamp: i64,
+    /// Identificador asignado por el emisor para confirmar la persistencia (`0` = sin confirmación).
+    #[serde(default)]
+    #[sqlx(skip)]
+    pub sequence_id: u64,
+}
+
+
+/// Identifica un mensaje del Edge que debe confirmarse (Ack/Nack) una vez procesado.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct Delivery {
+    pub sender_user_id: String,
+    pub sequence_id: u64,
+}
+
+
+impl Metadata {
+    /// Devuelve la entrega a confirmar, o `None` si el emisor no asignó `sequence_id`.
+    pub fn delivery(&self) -> Option<Delivery> {
+        (self.sequence_id != 0).then(|| Delivery {
+            sender_user_id: self.sender_user_id.clone(),
+            sequence_id: self.sequence_id,
+        })
+    }
 }
 
 
@@ -91,6 +114,25 @@ pub struct Heartbeat {
 }
 
 
+/// Confirmación de persistencia (Ack) enviada al Edge.
+///
+/// `metadata.destination_id` es el `sender_user_id` del emisor original.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Ack {
+    pub metadata: Metadata,
+    pub sequence_ids: Vec<u64>,
+}
+
+
+/// Rechazo (Nack): los mensajes indicados fueron descartados y no se persistirán.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Nack {
+    pub metadata: Metadata,
+    pub sequence_ids: Vec<u64>,
+    pub reason: String,
+}
+
+
 /// Datos de telemetría y salud del Edge.
 ///
 /// Incluye información sobre memoria, cpu, sd y conectividad para diagnóstico.
@@ -113,8 +155,10 @@ pub struct SystemMetrics {
 
 
 /// Wrapper que encapsula los tipos de mensajes posibles
+///
+/// Se serializa con la etiqueta de la variante (`{"Monitor": {...}}`): llega a disco dentro de
+/// la cola de desborde y la variante no debe deducirse de la forma de los campos.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-#[serde(untagged)]
 pub enum Message {
     Monitor(Monitor),
     AlertAir(AlertAir),
@@ -124,4 +168,23 @@ pub enum Message {
     MonitorBatch(Vec<Monitor>),
     AlertAirBatch(Vec<AlertAir>),
     AlertTemBatch(Vec<AlertTh>),
+    Ack(Ack),
+    Nack(Nack),
+}
+
+
+impl Message {
+    /// Entregas del Edge contenidas en el mensaje que deben confirmarse tras persistirlo.
+    pub fn deliveries(&self) -> Vec<Delivery> {
+        match self {
+            Message::Monitor(m) => m.metadata.delivery().into_iter().collect(),
+            Message::AlertAir(m) => m.metadata.delivery().into_iter().collect(),
+            Message::AlertTem(m) => m.metadata.delivery().into_iter().collect(),
+            Message::Metrics(m) => m.metadata.delivery().into_iter().collect(),
+            Message::MonitorBatch(b) => b.iter().filter_map(|m| m.metadata.delivery()).collect(),
+            Message::AlertAirBatch(b) => b.iter().filter_map(|m| m.metadata.delivery()).collect(),
+            Message::AlertTemBatch(b) => b.iter().filter_map(|m| m.metadata.delivery()).collect(),
+            Message::Heartbeat(_) | Message::Ack(_) | Message::Nack(_) => Vec::new(),
+        }
+    }
 }
\ No newline at end of file
@@ -18,59 +18,79 @@ use crate::context::domain::AppContext;
 use crate::message::domain::{Measurement as MeasurementMessage, Monitor as MonitorMessage,
                              AlertAir as AlertAirMessage, AlertTh as AlertThMessage,
                              SystemMetrics as MetricsMessage, Message, Metadata as MetadataMessage};
-use crate::grpc::{FromDataSaver, Heartbeat, Metadata, from_data_saver};
+use crate::grpc::{FromDataSaver, Heartbeat, Ack, Nack, Metadata, from_data_saver};
 use crate::grpc::to_data_saver::Payload;
+use crate::metrics::domain::payload_label;
+use crate::supervisor::domain::shared;
+use crate::supervisor::logic::supervise;
 use crate::system::domain::InternalEvent;
 
 
 /// Tarea de subida: Transforma mensajes de dominio en mensajes de transporte gRPC.
 ///
-/// Actualmente se encarga principalmente de enviar los **Heartbeats** generados por el sistema
-/// hacia el servidor central (In-Store Service) para mantener la conexión viva.
+/// Se encarga de enviar los **Heartbeats** generados por el sistema hacia el servidor
+/// central (In-Store Service) para mantener la conexión viva, y las confirmaciones
+/// **Ack/Nack** que emite `dba_task` una vez persistidos (o descartados) los datos del Edge.
 ///
 /// # Flujo de Datos
-/// 1. Recibe un `Message::Heartbeat` del canal interno.
-/// 2. Construye la estructura anidada requerida por el `.proto` (`DataSaverUpload` -> `Payload` -> `Heartbeat`).
+/// 1. Recibe un `Message::Heartbeat` del generador de heartbeats o un `Message::Ack` /
+///    `Message::Nack` de `dba_task`, cada uno por su propio canal.
+/// 2. Construye la estructura anidada requerida por el `.proto` (`FromDataSaver` -> `Payload` -> mensaje).
 /// 3. Envía el mensaje resultante al canal de salida hacia la tarea gRPC.
 ///
 /// # Argumentos
 /// * `tx`: Canal de envío hacia la tarea de red (`grpc_service`).
-/// * `rx`: Canal de recepción desde el generador de heartbeats.
+/// * `rx_heartbeat`: Canal de recepción desde el generador de heartbeats.
+/// * `rx_ack`: Canal de recepción de las confirmaciones de `dba_task`.
 #[instrument(
     name = "message_upload_task",
-    skip(tx, rx)
+    skip(tx, rx_heartbeat, rx_ack)
 )]
 pub async fn message_upload(tx: mpsc::Sender<FromDataSaver>,
-                            mut rx: mpsc::Receiver<Message>) {
+                            rx_heartbeat: &mut mpsc::Receiver<Message>,
+                            rx_ack: &mut mpsc::Receiver<Message>) {
 
     info!("Info: message_upload_task creada");
 
-    while let Some(msg) = rx.recv().await {
-        debug!("Debug: ingreso un mensaje de heartbeat para enviar a gRPC");
-        match msg {
-            Message::Heartbeat(heartbeat) => {
-
-                let grpc_metadata = Metadata {
-                    sender_user_id: heartbeat.metadata.sender_user_id,
-                    destination_id: heartbeat.metadata.destination_id,
-                    timestamp: heartbeat.metadata.timestamp,
-                };
+    loop {
+        let msg = tokio::select! {
+            Some(msg) = rx_heartbeat.recv() => msg,
+            Some(msg) = rx_ack.recv() => msg,
+            else => break,
+        };
 
-                let grpc_heartbeat = Heartbeat {
-                    metadata: Some(grpc_metadata),
+        let to_edge_payload = match msg {
+            Message::Heartbeat(heartbeat) => {
+                debug!("Debug: ingreso un mensaje de heartbeat para enviar a gRPC");
+                from_data_saver::Payload::Heartbeat(Heartbeat {
+                    metadata: Some(to_proto_metadata(heartbeat.metadata)),
                     beat: heartbeat.beat,
-                };
+                })
+            },
+            Message::Ack(ack) => {
+                debug!("Debug: ingreso un Ack para enviar a gRPC");
+                from_data_saver::Payload::Ack(Ack {
+                    metadata: Some(to_proto_metadata(ack.metadata)),
+                    sequence_ids: ack.sequence_ids,
+                })
+            },
+            Message::Nack(nack) => {
+                debug!("Debug: ingreso un Nack para enviar a gRPC");
+                from_data_saver::Payload::Nack(Nack {
+                    metadata: Some(to_proto_metadata(nack.metadata)),
+                    sequence_ids: nack.sequence_ids,
+                    reason: nack.reason,
+                })
+            },
+            _ => continue,
+        };
 
-                let to_edge_payload = from_data_saver::Payload::Heartbeat(grpc_heartbeat);
-                let to_edge_msg = FromDataSaver {
-                    payload: Some(to_edge_payload),
-                };
+        let to_edge_msg = FromDataSaver {
+            payload: Some(to_edge_payload),
+        };
 
-                if tx.send(to_edge_msg).await.is_err() {
-                    error!("Error: no se pudo enviar mensaje Heartbeat a la tarea gRPC");
-                }
-            },
-            _ => {}
+        if tx.send(to_edge_msg).await.is_err() {
+            error!("Error: no se pudo enviar mensaje a la tarea gRPC");
         }
     }
     info!("Info: message_upload_task finalizada");
@@ -90,14 +110,15 @@ pub async fn message_upload(tx: mpsc::Sender<FromDataSaver>,
 ///
 /// # Argumentos
 /// * `tx`: Canal de envío hacia la capa de persistencia (Database/Batcher).
-/// * `rx`: Canal de recepción de eventos desde la tarea gRPC (`InternalEvent`).
+/// * `rx`: Canal de recepción de eventos desde las tareas gRPC (`InternalEvent`), etiquetados
+///   con el endpoint de origen, que se usa en los logs y en `messages_received_total`.
 #[instrument(
     name = "message_download_task",
     skip(tx, rx)
 )]
 pub async fn message_download(tx: mpsc::Sender<Message>,
                               tx_to_bucket: mpsc::Sender<BucketData>,
-                              mut rx: mpsc::Receiver<InternalEvent>,
+                              rx: &mut mpsc::Receiver<InternalEvent>,
                               app_context: AppContext) {
 
     info!("Info: message_download_task creada");
@@ -105,8 +126,10 @@ pub async fn message_download(tx: mpsc::Sender<Message>,
     while let Some(msg) = rx.recv().await {
         debug!("Debug: ingreso un mensaje de datos desde el servicio gRPC");
         match msg {
-            InternalEvent::IncomingMessage(msg) => {
-                if let Some(payload) = msg.payload {
+            InternalEvent::IncomingMessage { endpoint, message } => {
+                debug!("Debug: mensaje recibido desde {}", endpoint);
+                if let Some(payload) = message.payload {
+                    app_context.metrics.messages_received.with_label_values(&[&endpoint, payload_label(&payload)]).inc();
                     match payload { 
                         Payload::Measurement(measurement) => {
                             debug!("Debug: el mensaje entrante es de tipo Measurement");
@@ -272,10 +295,8 @@ pub async fn message_download(tx: mpsc::Sender<Message>,
                                 })
                                 .collect();
                             
-                            if !domain_measurements.is_empty() {
-                                if tx_to_bucket.send(BucketData::VecMeasurement(domain_measurements)).await.is_err() {
-                                    error!("Error: no se pudo enviar MeasurementBatch a dba_task");
-                                }
+                            if !domain_measurements.is_empty() && tx_to_bucket.send(BucketData::VecMeasurement(domain_measurements)).await.is_err() {
+                                error!("Error: no se pudo enviar MeasurementBatch a dba_task");
                             }
                         },
                         Payload::MonitorBatch(batch) => {
@@ -304,10 +325,8 @@ pub async fn message_download(tx: mpsc::Sender<Message>,
                                 })
                                 .collect();
 
-                            if !domain_monitors.is_empty() {
-                                if tx.send(Message::MonitorBatch(domain_monitors)).await.is_err() {
-                                    error!("Error: no se pudo enviar MonitorBatch a dba_task");
-                                }
+                            if !domain_monitors.is_empty() && tx.send(Message::MonitorBatch(domain_monitors)).await.is_err() {
+                                error!("Error: no se pudo enviar MonitorBatch a dba_task");
                             }
                         },
                         Payload::AlertAirBatch(batch) => {
@@ -325,10 +344,8 @@ pub async fn message_download(tx: mpsc::Sender<Message>,
                                 })
                                 .collect();
 
-                            if !domain_alerts.is_empty() {
-                                if tx.send(Message::AlertAirBatch(domain_alerts)).await.is_err() {
-                                    error!("Error: no se pudo enviar AlertAirBatch a dba_task");
-                                }
+                            if !domain_alerts.is_empty() && tx.send(Message::AlertAirBatch(domain_alerts)).await.is_err() {
+                                error!("Error: no se pudo enviar AlertAirBatch a dba_task");
                             }
 
                             let notifier = app_context.telegram_notifier.clone();
@@ -356,10 +373,8 @@ pub async fn message_download(tx: mpsc::Sender<Message>,
                                 })
                                 .collect();
 
-                            if !domain_alerts.is_empty() {
-                                if tx.send(Message::AlertTemBatch(domain_alerts)).await.is_err() {
-                                    error!("Error: no se pudo enviar AlertThBatch a dba_task");
-                                }
+                            if !domain_alerts.is_empty() && tx.send(Message::AlertTemBatch(domain_alerts)).await.is_err() {
+                                error!("Error: no se pudo enviar AlertThBatch a dba_task");
                             }
 
                             let notifier = app_context.telegram_notifier.clone();
@@ -408,13 +423,25 @@ pub fn format_unix_to_argentina(unix_seconds: i64) -> String {
 /// # Argumentos
 /// * `tx_to_grpc`: Canal hacia la capa de transporte.
 /// * `rx_from_heartbeat`: Canal desde el generador de eventos de dominio.
+/// * `rx_from_dba`: Canal de confirmaciones Ack/Nack desde `dba_task`.
+/// * `app_context`: Contexto de la aplicación (supervisor).
 pub fn start_message_upload(tx_to_grpc: mpsc::Sender<FromDataSaver>,
-                            rx_from_heartbeat: mpsc::Receiver<Message>) {
+                            rx_from_heartbeat: mpsc::Receiver<Message>,
+                            rx_from_dba: mpsc::Receiver<Message>,
+                            app_context: AppContext) {
     info!("Info: iniciando tarea message_upload");
-    tokio::spawn(async move {
-        message_upload(tx_to_grpc,
-                       rx_from_heartbeat
-        ).await;
+    let rx_from_heartbeat = shared(rx_from_heartbeat);
+    let rx_from_dba = shared(rx_from_dba);
+    supervise("message_upload_task", true, &app_context, move || {
+        let tx_to_grpc = tx_to_grpc.clone();
+        let rx_from_heartbeat = rx_from_heartbeat.clone();
+        let rx_from_dba = rx_from_dba.clone();
+        async move {
+            message_upload(tx_to_grpc,
+                           &mut *rx_from_heartbeat.lock().await,
+                           &mut *rx_from_dba.lock().await
+            ).await;
+        }
     });
 }
 
@@ -429,12 +456,19 @@ pub fn start_message_download(tx_to_dba: mpsc::Sender<Message>,
                               rx_from_grpc: mpsc::Receiver<InternalEvent>,
                               app_context: AppContext) {
     info!("Info: iniciando tarea message_download");
-    tokio::spawn(async move {
-        message_download(tx_to_dba,
-                         tx_to_bucket,
-                         rx_from_grpc,
-                         app_context
-        ).await;
+    let rx_from_grpc = shared(rx_from_grpc);
+    supervise("message_download_task", true, &app_context.clone(), move || {
+        let tx_to_dba = tx_to_dba.clone();
+        let tx_to_bucket = tx_to_bucket.clone();
+        let rx_from_grpc = rx_from_grpc.clone();
+        let app_context = app_context.clone();
+        async move {
+            message_download(tx_to_dba,
+                             tx_to_bucket,
+                             &mut *rx_from_grpc.lock().await,
+                             app_context
+            ).await;
+        }
     });
 }
 
@@ -447,10 +481,22 @@ fn extract_metadata(proto_meta: Option<Metadata>) -> Option<MetadataMessage> {
             sender_user_id: meta.sender_user_id,
             destination_id: meta.destination_id,
             timestamp: meta.timestamp,
+            sequence_id: meta.sequence_id,
         }),
         None => {
             warn!("Warning: mensaje descartado, llegó sin metadatos obligatorios");
             None
         }
     }
-}
\ No newline at end of file
+}
+
+
+/// Helper privado para convertir metadatos de dominio al formato Protobuf.
+fn to_proto_metadata(meta: MetadataMessage) -> Metadata {
+    Metadata {
+        sender_user_id: meta.sender_user_id,
+        destination_id: meta.destination_id,
+        timestamp: meta.timestamp,
+        sequence_id: meta.sequence_id,
+    }
+}
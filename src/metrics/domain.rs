@@ -0,0 +1,253 @@
+//! Métricas Prometheus del pipeline completo.
+//!
+//! `Metrics` se comparte a través del `AppContext` (y del `Repository`). Los contadores se
+//! incrementan en el punto donde ocurre cada evento; los gauges que describen el estado
+//! (profundidad de canales, buckets abiertos, cola de desborde) se calculan al momento de
+//! cada scrape en `metrics::logic`.
+
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+use prometheus::{exponential_buckets, Encoder, Gauge, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use tokio::sync::mpsc;
+use crate::grpc::to_data_saver::Payload;
+
+
+/// Lectura de la ocupación de un canal.
+type DepthProbe = Box<dyn Fn() -> Option<usize> + Send + Sync>;
+
+
+/// Registro de métricas del servicio.
+pub struct Metrics {
+    registry: Registry,
+    /// Mensajes recibidos de los Edge, por endpoint de origen y variante de `Payload`.
+    pub messages_received: IntCounterVec,
+    /// Mensajes encolados en cada canal de `Channels`.
+    pub channel_depth: IntGaugeVec,
+    /// Buckets abiertos en `bucket_map`.
+    pub open_buckets: IntGauge,
+    /// Muestras acumuladas en los buckets abiertos.
+    pub bucket_samples: IntGauge,
+    /// Tope de muestras en los buckets abiertos (`BUCKET_MAX_SAMPLES`).
+    pub bucket_samples_limit: IntGauge,
+    /// Muestras rechazadas por `BUCKET_MAX_*`, por motivo (`future`, `past`, `capacity`,
+    /// `network_buckets`).
+    pub bucket_rejected_samples: IntCounterVec,
+    /// Buckets cerrados antes de tiempo para respetar `BUCKET_MAX_*`, por motivo.
+    pub bucket_forced_closes: IntCounterVec,
+    /// Muestras que llegaron con su bucket ya cerrado, por política aplicada (`table`, `drop`).
+    pub late_samples: IntCounterVec,
+    /// Filas insertadas, por tabla.
+    pub db_rows_inserted: IntCounterVec,
+    /// Filas cuya inserción falló, por tabla. Cada reintento cuenta por separado.
+    pub db_rows_failed: IntCounterVec,
+    /// Momento (Unix, segundos) de la última inserción exitosa, por tabla.
+    pub db_last_insert: IntGaugeVec,
+    /// Reintentos realizados por `execute_with_retry`.
+    pub db_retries: IntCounter,
+    /// Vaciados del buffer de `dba_task`, por motivo (`size`, `age`).
+    pub db_flushes: IntCounterVec,
+    /// Segundos desde el inicio de un vaciado hasta su confirmación, reintentos incluidos.
+    pub db_flush_duration: Histogram,
+    /// Filas por vaciado confirmado.
+    pub db_flush_rows: Histogram,
+    /// Filas por segundo del último vaciado confirmado.
+    pub db_flush_rows_per_second: Gauge,
+    /// Reconexiones gRPC, por endpoint.
+    pub grpc_reconnects: IntCounterVec,
+    /// Alertas de Telegram enviadas, por resultado (`success`, `http_error`, `network_error`).
+    pub telegram_alerts: IntCounterVec,
+    /// Registros pendientes en la cola de desborde.
+    pub spill_pending: IntGauge,
+    /// Mediciones descartadas sin archivar, por motivo (`channel_full`, `pending_limit`).
+    pub archive_dropped: IntCounterVec,
+    /// Sondas de ocupación registradas con [`Metrics::register_channel`].
+    channel_probes: Mutex<Vec<(&'static str, DepthProbe)>>,
+}
+
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+
+impl Metrics {
+
+    /// Crea y registra todas las métricas.
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("data_saver".to_string()), None)?;
+
+        let messages_received = IntCounterVec::new(
+            Opts::new("messages_received_total", "Mensajes recibidos de los Edge por endpoint y tipo de payload"),
+            &["endpoint", "payload"])?;
+        let channel_depth = IntGaugeVec::new(
+            Opts::new("channel_depth", "Mensajes encolados en cada canal interno"),
+            &["channel"])?;
+        let open_buckets = IntGauge::new("open_buckets", "Buckets abiertos a la espera de su cierre")?;
+        let bucket_samples = IntGauge::new("bucket_samples", "Muestras acumuladas en los buckets abiertos")?;
+        let bucket_samples_limit = IntGauge::new("bucket_samples_limit", "Tope de muestras en los buckets abiertos")?;
+        let bucket_rejected_samples = IntCounterVec::new(
+            Opts::new("bucket_rejected_samples_total", "Muestras rechazadas por los límites de buckets por motivo"),
+            &["reason"])?;
+        let bucket_forced_closes = IntCounterVec::new(
+            Opts::new("bucket_forced_closes_total", "Buckets cerrados antes de tiempo por los límites de buckets por motivo"),
+            &["reason"])?;
+        let late_samples = IntCounterVec::new(
+            Opts::new("late_samples_total", "Muestras recibidas después del cierre de su bucket por política"),
+            &["policy"])?;
+        let db_rows_inserted = IntCounterVec::new(
+            Opts::new("db_rows_inserted_total", "Filas insertadas en la base de datos por tabla"),
+            &["table"])?;
+        let db_rows_failed = IntCounterVec::new(
+            Opts::new("db_rows_failed_total", "Filas cuya inserción falló por tabla"),
+            &["table"])?;
+        let db_last_insert = IntGaugeVec::new(
+            Opts::new("db_last_insert_timestamp_seconds", "Momento de la última inserción exitosa por tabla"),
+            &["table"])?;
+        let db_retries = IntCounter::new("db_retries_total", "Reintentos de inserción en la base de datos")?;
+        let db_flushes = IntCounterVec::new(
+            Opts::new("db_flushes_total", "Vaciados del buffer de escritura por motivo"),
+            &["trigger"])?;
+        let db_flush_duration = Histogram::with_opts(
+            HistogramOpts::new("db_flush_duration_seconds", "Duración de los vaciados confirmados del buffer de escritura")
+                .buckets(exponential_buckets(0.001, 2.0, 15)?))?;
+        let db_flush_rows = Histogram::with_opts(
+            HistogramOpts::new("db_flush_rows", "Filas por vaciado confirmado del buffer de escritura")
+                .buckets(exponential_buckets(1.0, 2.0, 14)?))?;
+        let db_flush_rows_per_second = Gauge::new(
+            "db_flush_rows_per_second", "Filas por segundo del último vaciado confirmado")?;
+        let grpc_reconnects = IntCounterVec::new(
+            Opts::new("grpc_reconnects_total", "Reconexiones del cliente gRPC por endpoint"),
+            &["endpoint"])?;
+        let telegram_alerts = IntCounterVec::new(
+            Opts::new("telegram_alerts_total", "Alertas de Telegram enviadas por resultado"),
+            &["outcome"])?;
+        let spill_pending = IntGauge::new("spill_pending", "Registros pendientes en la cola de desborde")?;
+        let archive_dropped = IntCounterVec::new(
+            Opts::new("archive_dropped_total", "Mediciones descartadas sin archivar por motivo"),
+            &["reason"])?;
+
+        registry.register(Box::new(messages_received.clone()))?;
+        registry.register(Box::new(channel_depth.clone()))?;
+        registry.register(Box::new(open_buckets.clone()))?;
+        registry.register(Box::new(bucket_samples.clone()))?;
+        registry.register(Box::new(bucket_samples_limit.clone()))?;
+        registry.register(Box::new(bucket_rejected_samples.clone()))?;
+        registry.register(Box::new(bucket_forced_closes.clone()))?;
+        registry.register(Box::new(late_samples.clone()))?;
+        registry.register(Box::new(db_rows_inserted.clone()))?;
+        registry.register(Box::new(db_rows_failed.clone()))?;
+        registry.register(Box::new(db_last_insert.clone()))?;
+        registry.register(Box::new(db_retries.clone()))?;
+        registry.register(Box::new(db_flushes.clone()))?;
+        registry.register(Box::new(db_flush_duration.clone()))?;
+        registry.register(Box::new(db_flush_rows.clone()))?;
+        registry.register(Box::new(db_flush_rows_per_second.clone()))?;
+        registry.register(Box::new(grpc_reconnects.clone()))?;
+        registry.register(Box::new(telegram_alerts.clone()))?;
+        registry.register(Box::new(spill_pending.clone()))?;
+        registry.register(Box::new(archive_dropped.clone()))?;
+
+        Ok(Self {
+            registry,
+            messages_received,
+            channel_depth,
+            open_buckets,
+            bucket_samples,
+            bucket_samples_limit,
+            bucket_rejected_samples,
+            bucket_forced_closes,
+            late_samples,
+            db_rows_inserted,
+            db_rows_failed,
+            db_last_insert,
+            db_retries,
+            db_flushes,
+            db_flush_duration,
+            db_flush_rows,
+            db_flush_rows_per_second,
+            grpc_reconnects,
+            telegram_alerts,
+            spill_pending,
+            archive_dropped,
+            channel_probes: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Registra un canal para publicar su profundidad.
+    ///
+    /// Se guarda un `WeakSender`: la sonda no mantiene el canal abierto, por lo que no
+    /// interfiere con el cierre en cascada durante el apagado.
+    pub fn register_channel<T: Send + 'static>(&self, name: &'static str, tx: &mpsc::Sender<T>) {
+        let weak = tx.downgrade();
+        let probe: DepthProbe = Box::new(move || {
+            weak.upgrade().map(|tx| tx.max_capacity() - tx.capacity())
+        });
+        self.channel_probes.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push((name, probe));
+    }
+
+    /// Actualiza `channel_depth` con la ocupación actual de cada canal registrado.
+    /// Un canal ya cerrado se publica con profundidad cero.
+    pub fn refresh_channels(&self) {
+        let probes = self.channel_probes.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for (name, probe) in probes.iter() {
+            let depth = probe().unwrap_or(0);
+            self.channel_depth.with_label_values(&[name]).set(depth as i64);
+        }
+    }
+
+    /// Registra el resultado de insertar `rows` filas en `table`.
+    pub fn record_insert<E>(&self, table: &str, rows: usize, result: &Result<(), E>) {
+        match result {
+            Ok(_) => {
+                self.db_rows_inserted.with_label_values(&[table]).inc_by(rows as u64);
+                self.db_last_insert.with_label_values(&[table]).set(chrono::Utc::now().timestamp());
+            }
+            Err(_) => self.db_rows_failed.with_label_values(&[table]).inc_by(rows as u64),
+        }
+    }
+
+    /// Registra un vaciado del buffer de escritura. Solo los confirmados (`persisted`)
+    /// actualizan la duración, el tamaño y el ritmo.
+    pub fn record_flush(&self, trigger: &str, rows: usize, elapsed: Duration, persisted: bool) {
+        self.db_flushes.with_label_values(&[trigger]).inc();
+        if !persisted {
+            return;
+        }
+        let secs = elapsed.as_secs_f64();
+        self.db_flush_duration.observe(secs);
+        self.db_flush_rows.observe(rows as f64);
+        if secs > 0.0 {
+            self.db_flush_rows_per_second.set(rows as f64 / secs);
+        }
+    }
+
+    /// Serializa todas las métricas en el formato de texto de Prometheus.
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+    }
+}
+
+
+/// Etiqueta de la métrica `messages_received_total` para cada variante de `Payload`.
+pub fn payload_label(payload: &Payload) -> &'static str {
+    match payload {
+        Payload::Measurement(_) => "measurement",
+        Payload::Monitor(_) => "monitor",
+        Payload::AlertAir(_) => "alert_air",
+        Payload::AlertTh(_) => "alert_th",
+        Payload::Metric(_) => "metric",
+        Payload::MeasurementBatch(_) => "measurement_batch",
+        Payload::MonitorBatch(_) => "monitor_batch",
+        Payload::AlertAirBatch(_) => "alert_air_batch",
+        Payload::AlertThBatch(_) => "alert_th_batch",
+    }
+}
@@ -0,0 +1,43 @@
+//! Exposición de las métricas en `GET /metrics`.
+
+
+use std::sync::atomic::Ordering;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use tracing::error;
+use crate::context::domain::AppContext;
+
+
+/// Tipo de contenido del formato de texto de Prometheus.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+
+/// Handler de `GET /metrics`.
+///
+/// Antes de serializar actualiza los gauges que se derivan del estado compartido:
+/// profundidad de canales, buckets abiertos y cola de desborde.
+pub async fn metrics_handler(State(app_context): State<AppContext>) -> Response {
+    refresh_gauges(&app_context);
+
+    match app_context.metrics.encode() {
+        Ok(body) => ([(header::CONTENT_TYPE, CONTENT_TYPE)], body).into_response(),
+        Err(e) => {
+            error!("Error: no se pudieron serializar las métricas. {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+
+fn refresh_gauges(app_context: &AppContext) {
+    let metrics = &app_context.metrics;
+
+    metrics.refresh_channels();
+
+    metrics.open_buckets.set(app_context.bucket_map.len() as i64);
+    metrics.bucket_samples.set(app_context.bucket_samples.load(Ordering::Relaxed) as i64);
+    metrics.bucket_samples_limit.set(app_context.system.bucket_limits.max_samples as i64);
+
+    metrics.spill_pending.set(app_context.spill_stats.pending.load(Ordering::Relaxed) as i64);
+}
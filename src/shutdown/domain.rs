@@ -0,0 +1,82 @@
+//! Señal de apagado ordenado (Graceful Shutdown).
+//!
+//! `ShutdownSignal` se comparte a través del `AppContext`. Cuando llega SIGINT o SIGTERM
+//! se dispara una sola vez y las tareas que la observan dejan de aceptar trabajo nuevo.
+//! El vaciado del pipeline no depende de la señal: al terminar las tareas de entrada se
+//! cierran sus canales y cada actor finaliza en cascada cuando su receptor queda vacío.
+
+
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::info;
+
+
+/// Señal de apagado compartida entre todas las tareas.
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        Self { tx: Arc::new(watch::Sender::new(false)) }
+    }
+}
+
+
+impl ShutdownSignal {
+
+    /// Dispara el apagado. Las llamadas posteriores no tienen efecto.
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    /// Indica si el apagado ya fue disparado.
+    pub fn is_triggered(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Espera a que se dispare el apagado. Retorna de inmediato si ya ocurrió.
+    pub async fn wait(&self) {
+        let mut rx = self.tx.subscribe();
+        // `wait_for` solo falla si el emisor se destruye, y este vive mientras exista la señal
+        let _ = rx.wait_for(|triggered| *triggered).await;
+    }
+}
+
+
+/// Espera SIGINT (Ctrl+C) o SIGTERM (`docker stop`, Kubernetes).
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => info!("Info: SIGINT recibido"),
+                    _ = sigterm.recv() => info!("Info: SIGTERM recibido"),
+                }
+                return;
+            }
+            Err(e) => tracing::warn!("Warning: no se pudo escuchar SIGTERM, solo se atiende SIGINT. {}", e),
+        }
+    }
+
+    if tokio::signal::ctrl_c().await.is_ok() {
+        info!("Info: SIGINT recibido");
+    }
+}
+
+
+/// Resultado del vaciado de `dba_task` durante el apagado.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DrainReport {
+    /// Operaciones insertadas en la base de datos después de la señal de apagado.
+    pub persisted: u64,
+    /// Operaciones escritas en la cola de desborde (se reproducen en el próximo arranque).
+    pub spilled: u64,
+    /// Operaciones descartadas (con Nack).
+    pub dropped: u64,
+}
@@ -0,0 +1,92 @@
+//! Coordinación del apagado ordenado.
+//!
+//! # Secuencia
+//! 1. Llega SIGINT o SIGTERM y se dispara `AppContext::shutdown`.
+//! 2. Las tareas gRPC (cliente o servidor de ingesta) y el worker de clima dejan de recibir
+//!    datos y sueltan sus canales.
+//! 3. `message_download` y `bucket_task` vacían sus colas y terminan.
+//! 4. El sweeper cierra y agrega **todos** los buckets abiertos, sin esperar su vencimiento, y
+//!    `archive_task` inserta las mediciones crudas pendientes.
+//! 5. `dba_task` inserta lo pendiente (sin reintentos largos: lo que falla va a la cola de
+//!    desborde) y termina cuando sus canales de entrada se cierran.
+//!
+//! Todo debe completarse dentro de `SHUTDOWN_DEADLINE_SECS`; si no, se informa lo abandonado.
+
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, info, instrument, warn};
+use crate::context::domain::AppContext;
+use crate::shutdown::domain::{wait_for_signal, DrainReport};
+use crate::supervisor::domain::TaskState;
+
+
+/// Espera la señal de apagado y vacía el pipeline dentro del plazo configurado.
+///
+/// # Argumentos
+/// * `sweeper`: Tarea del sweeper; devuelve la cantidad de buckets cerrados al apagar.
+/// * `dba`: Tarea DBA; devuelve el resultado de su vaciado.
+/// * `archive`: Tarea del archivo crudo, si está habilitado; devuelve las mediciones archivadas.
+/// * `app_context`: Contexto global (señal de apagado, `bucket_map` y estadísticas).
+#[instrument(
+    name = "shutdown",
+    skip(sweeper, dba, archive, app_context)
+)]
+pub async fn graceful_shutdown(sweeper: JoinHandle<Option<usize>>,
+                               dba: JoinHandle<Option<DrainReport>>,
+                               archive: Option<JoinHandle<Option<usize>>>,
+                               app_context: AppContext) {
+
+    wait_for_signal().await;
+
+    let deadline = Duration::from_secs(app_context.system.shutdown_deadline_secs);
+    info!("Info: iniciando apagado ordenado (plazo {:?})", deadline);
+    app_context.shutdown.trigger();
+
+    let drain = async {
+        let flushed = sweeper.await;
+        let drained = dba.await;
+        if let Some(archive) = archive
+            && let Err(e) = archive.await {
+            error!("Error: archive_task terminó con error durante el apagado. {}", e);
+        }
+        (flushed, drained)
+    };
+
+    match tokio::time::timeout(deadline, drain).await {
+        Ok((flushed, drained)) => {
+            // `None` indica que el supervisor dejó la tarea `Failed`; el motivo ya se registró
+            let flushed = flushed.unwrap_or_else(|e| {
+                error!("Error: el sweeper terminó con error durante el apagado. {}", e);
+                None
+            }).unwrap_or_default();
+            let drained = drained.unwrap_or_else(|e| {
+                error!("Error: dba_task terminó con error durante el apagado. {}", e);
+                None
+            }).unwrap_or_default();
+            info!("Info: apagado completo. Buckets cerrados: {}, operaciones persistidas: {}, \
+                   enviadas a la cola de desborde: {}, descartadas: {}",
+                flushed, drained.persisted, drained.spilled, drained.dropped);
+        }
+        Err(_) => {
+            let open_buckets = app_context.bucket_map.len();
+            let open_measurements: usize = app_context.bucket_map.iter().map(|entry| entry.value().len()).sum();
+            warn!("Warning: plazo de apagado agotado. Abandonados: {} buckets abiertos ({} mediciones) \
+                   y los mensajes aún en tránsito hacia la base de datos",
+                open_buckets, open_measurements);
+        }
+    }
+
+    for (name, health) in app_context.tasks.snapshot() {
+        if health.state == TaskState::Failed {
+            warn!("Warning: la tarea {} terminó en estado fallido ({} reinicios). {}",
+                name, health.restarts, health.last_error.unwrap_or_default());
+        }
+    }
+
+    let pending = app_context.spill_stats.pending.load(Ordering::Relaxed);
+    if pending > 0 {
+        info!("Info: {} registros quedan en la cola de desborde y se reproducirán al arrancar", pending);
+    }
+}
@@ -0,0 +1,514 @@
+//! Cola de desborde durable en disco (Spill Queue).
+//!
+//! Cuando la base de datos no está disponible durante más tiempo del que cubren los
+//! reintentos de `dba_task`, las operaciones pendientes se escriben en un log local de
+//! solo-anexado (append-only) en lugar de descartarse. Al recuperarse el repositorio,
+//! se reproducen (replay) en el mismo orden en que fueron escritas.
+//!
+//! # Formato en disco
+//! El log se divide en **segmentos** (`segment-<id>.log`) dentro de `SPILL_DIR`.
+//! Cada línea es un registro serializado en JSON. Solo el segmento de mayor `id`
+//! (el activo) recibe escrituras; el resto están sellados y se reproducen del más
+//! antiguo al más nuevo.
+//!
+//! # Límites
+//! * `max_segment_bytes`: al superarlo se sella el segmento activo y se abre uno nuevo.
+//! * `max_total_bytes`: al superarlo se rechaza el registro y se contabiliza como descartado.
+
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_SUFFIX: &str = ".log";
+
+
+/// Política de sincronización a disco (`fsync`) de las escrituras.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsyncPolicy {
+    /// `fsync` después de cada registro. Máxima durabilidad, menor rendimiento.
+    Always,
+    /// `fsync` como máximo una vez por intervalo. Ante un corte de energía se pierde
+    /// a lo sumo lo escrito durante el último intervalo.
+    Interval(Duration),
+    /// Nunca se fuerza el `fsync`; el sistema operativo decide cuándo persistir.
+    Never,
+}
+
+
+impl FsyncPolicy {
+
+    /// Construye la política a partir de su nombre (`always`, `interval` o `never`).
+    ///
+    /// # Argumentos
+    /// * `name`: Valor de `SPILL_FSYNC`.
+    /// * `interval`: Intervalo usado solo por la política `interval`.
+    pub fn from_config(name: &str, interval: Duration) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "always" => Ok(FsyncPolicy::Always),
+            "interval" => Ok(FsyncPolicy::Interval(interval)),
+            "never" => Ok(FsyncPolicy::Never),
+            other => Err(format!("política de fsync desconocida: {other}")),
+        }
+    }
+}
+
+
+/// Contadores operativos de la cola de desborde.
+///
+/// Se comparte vía `Arc` a través del `AppContext` para poder consultarlos desde
+/// otras partes del sistema sin acceder a la cola (que pertenece a `dba_task`).
+#[derive(Debug, Default)]
+pub struct SpillStats {
+    /// Registros escritos en disco.
+    pub spilled: AtomicU64,
+    /// Registros reproducidos con éxito contra la base de datos.
+    pub replayed: AtomicU64,
+    /// Registros perdidos (cola llena, error de escritura o línea corrupta).
+    pub dropped: AtomicU64,
+    /// Registros actualmente pendientes en disco.
+    pub pending: AtomicU64,
+}
+
+
+/// Errores de la cola de desborde.
+#[derive(Debug)]
+pub enum SpillError {
+    /// Error de entrada/salida del sistema de archivos.
+    Io(io::Error),
+    /// Error al serializar un registro.
+    Serialize(serde_json::Error),
+    /// Se alcanzó `max_total_bytes`; el registro no fue escrito.
+    Full,
+}
+
+
+impl fmt::Display for SpillError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpillError::Io(e) => write!(f, "error de E/S en la cola de desborde: {e}"),
+            SpillError::Serialize(e) => write!(f, "error al serializar registro: {e}"),
+            SpillError::Full => write!(f, "cola de desborde llena"),
+        }
+    }
+}
+
+
+impl From<io::Error> for SpillError {
+    fn from(e: io::Error) -> Self {
+        SpillError::Io(e)
+    }
+}
+
+
+/// Segmento abierto para escritura.
+struct ActiveSegment {
+    id: u64,
+    file: File,
+    bytes: u64,
+}
+
+
+/// Log de segmentos append-only para registros de tipo `T`.
+///
+/// No es `Clone` ni compartible: pertenece a una única tarea (actor), que es la
+/// responsable de escribir y reproducir los registros.
+pub struct SpillQueue<T> {
+    dir: PathBuf,
+    max_segment_bytes: u64,
+    max_total_bytes: u64,
+    fsync: FsyncPolicy,
+    active: Option<ActiveSegment>,
+    /// Segmentos sellados, del más antiguo al más nuevo.
+    sealed: VecDeque<u64>,
+    next_id: u64,
+    total_bytes: u64,
+    last_sync: Instant,
+    stats: Arc<SpillStats>,
+    _record: PhantomData<T>,
+}
+
+
+impl<T: Serialize + DeserializeOwned> SpillQueue<T> {
+
+    /// Abre (o crea) la cola en `dir`.
+    ///
+    /// Los segmentos que existan de una ejecución anterior se consideran sellados y
+    /// quedan pendientes de reproducción. Nunca se anexa a un segmento previo.
+    pub fn open(dir: impl AsRef<Path>,
+                max_segment_bytes: u64,
+                max_total_bytes: u64,
+                fsync: FsyncPolicy,
+                stats: Arc<SpillStats>) -> Result<Self, SpillError> {
+
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let name = entry?.file_name();
+            if let Some(id) = parse_segment_id(&name.to_string_lossy()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+
+        let mut total_bytes = 0;
+        let mut pending = 0;
+        for id in &ids {
+            let path = segment_path(&dir, *id);
+            total_bytes += fs::metadata(&path)?.len();
+            pending += BufReader::new(File::open(&path)?).split(b'\n').count() as u64;
+        }
+
+        stats.pending.store(pending, Ordering::Relaxed);
+        if pending > 0 {
+            info!("Info: cola de desborde con {} registros pendientes en {} segmentos", pending, ids.len());
+        }
+
+        Ok(Self {
+            next_id: ids.last().map(|id| id + 1).unwrap_or(1),
+            sealed: ids.into(),
+            dir,
+            max_segment_bytes,
+            max_total_bytes,
+            fsync,
+            active: None,
+            total_bytes,
+            last_sync: Instant::now(),
+            stats,
+            _record: PhantomData,
+        })
+    }
+
+    /// Indica si hay registros esperando ser reproducidos.
+    pub fn has_pending(&self) -> bool {
+        self.stats.pending.load(Ordering::Relaxed) > 0
+    }
+
+    /// Anexa un registro al segmento activo, rotándolo si supera `max_segment_bytes`.
+    ///
+    /// Si la cola alcanzó `max_total_bytes` el registro se descarta y se retorna
+    /// `SpillError::Full`. Todo registro que no pueda escribirse se contabiliza como descartado.
+    pub fn append(&mut self, record: &T) -> Result<(), SpillError> {
+        let result = self.write_record(record);
+        if let Err(e) = &result {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            // Una escritura parcial dejaría una línea truncada: el siguiente registro
+            // se escribe en un segmento nuevo para no quedar pegado a ella.
+            if matches!(e, SpillError::Io(_)) {
+                self.seal_active();
+            }
+        }
+        result
+    }
+
+    fn write_record(&mut self, record: &T) -> Result<(), SpillError> {
+        let mut line = serde_json::to_vec(record).map_err(SpillError::Serialize)?;
+        line.push(b'\n');
+        let len = line.len() as u64;
+
+        if self.total_bytes + len > self.max_total_bytes {
+            return Err(SpillError::Full);
+        }
+
+        let rotate = match &self.active {
+            Some(active) => active.bytes > 0 && active.bytes + len > self.max_segment_bytes,
+            None => true,
+        };
+        if rotate {
+            self.seal_active();
+            self.open_active()?;
+        }
+
+        let Some(active) = self.active.as_mut() else {
+            return Err(SpillError::Io(io::Error::other("segmento activo no disponible")));
+        };
+
+        active.file.write_all(&line)?;
+        active.bytes += len;
+        self.total_bytes += len;
+
+        let sync = match self.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Interval(every) => self.last_sync.elapsed() >= every,
+            FsyncPolicy::Never => false,
+        };
+        if sync {
+            active.file.sync_data()?;
+            self.last_sync = Instant::now();
+        }
+
+        self.stats.spilled.fetch_add(1, Ordering::Relaxed);
+        self.stats.pending.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Lee todos los registros del segmento más antiguo.
+    ///
+    /// Si no hay segmentos sellados, sella el activo para poder reproducirlo.
+    /// Las líneas que no se puedan deserializar (incluidas las de UTF-8 inválido que deja una
+    /// escritura truncada) se descartan y se contabilizan.
+    /// El segmento no se elimina hasta llamar a [`SpillQueue::complete_oldest`].
+    pub fn read_oldest(&mut self) -> Result<Option<Vec<T>>, SpillError> {
+        if self.sealed.is_empty() {
+            self.seal_active();
+        }
+        let Some(id) = self.sealed.front().copied() else {
+            return Ok(None);
+        };
+
+        let reader = BufReader::new(File::open(segment_path(&self.dir, id))?);
+        let mut records = Vec::new();
+        // Se lee por bytes: una línea truncada no debe impedir reproducir el resto del segmento
+        for (number, line) in reader.split(b'\n').enumerate() {
+            let line = line?;
+            match serde_json::from_slice::<T>(&line) {
+                Ok(record) => records.push(record),
+                Err(e) => {
+                    error!("Error: línea {} corrupta en segmento {} de la cola de desborde. Descartada. {}", number + 1, id, e);
+                    self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    self.stats.pending.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+        }
+        Ok(Some(records))
+    }
+
+    /// Confirma el avance sobre el segmento más antiguo.
+    ///
+    /// `replayed` es la cantidad de registros persistidos y `rejected` la de registros
+    /// descartados por ser rechazados de forma permanente; ambos se cuentan desde el inicio
+    /// del segmento. `remaining` son los que quedaron sin procesar. Si no queda ninguno el
+    /// segmento se elimina; si no, se reescribe de forma atómica (archivo temporal + `rename`).
+    pub fn complete_oldest(&mut self, replayed: usize, rejected: usize, remaining: &[T]) -> Result<(), SpillError> {
+        let Some(id) = self.sealed.front().copied() else {
+            return Ok(());
+        };
+        let path = segment_path(&self.dir, id);
+        let old_len = fs::metadata(&path)?.len();
+
+        let new_len = if remaining.is_empty() {
+            fs::remove_file(&path)?;
+            self.sealed.pop_front();
+            0
+        } else {
+            let tmp = path.with_extension("tmp");
+            let mut file = File::create(&tmp)?;
+            let mut written = 0;
+            for record in remaining {
+                let mut line = serde_json::to_vec(record).map_err(SpillError::Serialize)?;
+                line.push(b'\n');
+                file.write_all(&line)?;
+                written += line.len() as u64;
+            }
+            file.sync_data()?;
+            fs::rename(&tmp, &path)?;
+            written
+        };
+
+        self.total_bytes = self.total_bytes.saturating_sub(old_len) + new_len;
+        self.stats.replayed.fetch_add(replayed as u64, Ordering::Relaxed);
+        self.stats.dropped.fetch_add(rejected as u64, Ordering::Relaxed);
+        self.stats.pending.fetch_sub((replayed + rejected) as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Cierra el segmento activo (si tiene datos) y lo agrega a la lista de sellados.
+    fn seal_active(&mut self) {
+        if let Some(active) = self.active.take() {
+            if let Err(e) = active.file.sync_data() {
+                warn!("Warning: no se pudo sincronizar el segmento {} al sellarlo. {}", active.id, e);
+            }
+            if active.bytes > 0 {
+                self.sealed.push_back(active.id);
+            } else if let Err(e) = fs::remove_file(segment_path(&self.dir, active.id)) {
+                warn!("Warning: no se pudo eliminar el segmento vacío {}. {}", active.id, e);
+            }
+        }
+    }
+
+    /// Crea un nuevo segmento activo con el siguiente `id`.
+    fn open_active(&mut self) -> Result<(), SpillError> {
+        let id = self.next_id;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(&self.dir, id))?;
+        self.next_id += 1;
+        self.active = Some(ActiveSegment { id, file, bytes: 0 });
+        Ok(())
+    }
+}
+
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{SEGMENT_PREFIX}{id:020}{SEGMENT_SUFFIX}"))
+}
+
+
+fn parse_segment_id(name: &str) -> Option<u64> {
+    name.strip_prefix(SEGMENT_PREFIX)?
+        .strip_suffix(SEGMENT_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Directorio vacío propio del test, eliminado al terminar.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("spill_queue_{}_{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn open(dir: &TestDir, max_segment_bytes: u64, max_total_bytes: u64) -> (SpillQueue<String>, Arc<SpillStats>) {
+        let stats = Arc::new(SpillStats::default());
+        let queue = SpillQueue::open(&dir.0, max_segment_bytes, max_total_bytes, FsyncPolicy::Never, stats.clone())
+            .expect("no se pudo abrir la cola");
+        (queue, stats)
+    }
+
+    fn segments(dir: &TestDir) -> usize {
+        fs::read_dir(&dir.0).unwrap()
+            .filter(|entry| parse_segment_id(&entry.as_ref().unwrap().file_name().to_string_lossy()).is_some())
+            .count()
+    }
+
+    fn load(stats: &SpillStats) -> (u64, u64, u64, u64) {
+        (stats.spilled.load(Ordering::Relaxed),
+         stats.replayed.load(Ordering::Relaxed),
+         stats.dropped.load(Ordering::Relaxed),
+         stats.pending.load(Ordering::Relaxed))
+    }
+
+    fn record(i: usize) -> String {
+        format!("record-{i}")
+    }
+
+    #[test]
+    fn rotates_segments_when_the_active_one_is_full() {
+        let dir = TestDir::new("rotation");
+        // Cada registro ocupa 11 bytes ("\"record-0\"\n"): entran dos por segmento
+        let (mut queue, _) = open(&dir, 25, 1_000);
+        for i in 0..5 {
+            queue.append(&record(i)).unwrap();
+        }
+
+        assert_eq!(segments(&dir), 3);
+        assert_eq!(queue.read_oldest().unwrap(), Some(vec![record(0), record(1)]));
+        queue.complete_oldest(2, 0, &[]).unwrap();
+        assert_eq!(queue.read_oldest().unwrap(), Some(vec![record(2), record(3)]));
+        queue.complete_oldest(2, 0, &[]).unwrap();
+        assert_eq!(queue.read_oldest().unwrap(), Some(vec![record(4)]));
+        queue.complete_oldest(1, 0, &[]).unwrap();
+
+        assert!(!queue.has_pending());
+        assert_eq!(queue.read_oldest().unwrap(), None);
+        assert_eq!(segments(&dir), 0);
+    }
+
+    #[test]
+    fn rejects_records_beyond_max_total_bytes() {
+        let dir = TestDir::new("total_cap");
+        let (mut queue, stats) = open(&dir, 1_000, 25);
+        queue.append(&record(0)).unwrap();
+        queue.append(&record(1)).unwrap();
+
+        assert!(matches!(queue.append(&record(2)), Err(SpillError::Full)));
+        assert_eq!(load(&stats), (2, 0, 1, 2));
+
+        // Al reproducir se libera espacio para nuevos registros
+        queue.read_oldest().unwrap();
+        queue.complete_oldest(2, 0, &[]).unwrap();
+        queue.append(&record(3)).unwrap();
+        assert_eq!(load(&stats), (3, 2, 1, 1));
+    }
+
+    #[test]
+    fn reopens_pending_segments_after_a_restart() {
+        let dir = TestDir::new("reopen");
+        {
+            let (mut queue, _) = open(&dir, 1_000, 1_000);
+            queue.append(&record(0)).unwrap();
+            queue.append(&record(1)).unwrap();
+        }
+
+        let (mut queue, stats) = open(&dir, 1_000, 1_000);
+        assert!(queue.has_pending());
+        assert_eq!(load(&stats), (0, 0, 0, 2));
+
+        // Lo nuevo va a otro segmento: el anterior se reproduce primero
+        queue.append(&record(2)).unwrap();
+        assert_eq!(segments(&dir), 2);
+        assert_eq!(queue.read_oldest().unwrap(), Some(vec![record(0), record(1)]));
+        queue.complete_oldest(2, 0, &[]).unwrap();
+        assert_eq!(queue.read_oldest().unwrap(), Some(vec![record(2)]));
+    }
+
+    #[test]
+    fn partial_completion_keeps_the_remaining_records() {
+        let dir = TestDir::new("partial");
+        let (mut queue, stats) = open(&dir, 1_000, 1_000);
+        for i in 0..4 {
+            queue.append(&record(i)).unwrap();
+        }
+
+        let records = queue.read_oldest().unwrap().unwrap();
+        queue.complete_oldest(1, 1, &records[2..]).unwrap();
+        assert_eq!(load(&stats), (4, 1, 1, 2));
+        assert_eq!(queue.read_oldest().unwrap(), Some(vec![record(2), record(3)]));
+
+        // El segmento reescrito sobrevive a un reinicio
+        drop(queue);
+        let (mut queue, stats) = open(&dir, 1_000, 1_000);
+        assert_eq!(load(&stats), (0, 0, 0, 2));
+        assert_eq!(queue.read_oldest().unwrap(), Some(vec![record(2), record(3)]));
+        queue.complete_oldest(2, 0, &[]).unwrap();
+        assert!(!queue.has_pending());
+    }
+
+    #[test]
+    fn skips_unreadable_lines() {
+        let dir = TestDir::new("torn_write");
+        fs::create_dir_all(&dir.0).unwrap();
+        let mut content = b"\"record-0\"\n".to_vec();
+        content.extend_from_slice(b"\"rec\xff\xfe\n");
+        content.extend_from_slice(b"{\"truncated\n");
+        content.extend_from_slice(b"\"record-1\"\n");
+        fs::write(segment_path(&dir.0, 1), content).unwrap();
+
+        let (mut queue, stats) = open(&dir, 1_000, 1_000);
+        assert_eq!(load(&stats), (0, 0, 0, 4));
+        assert_eq!(queue.read_oldest().unwrap(), Some(vec![record(0), record(1)]));
+        assert_eq!(load(&stats), (0, 0, 2, 2));
+
+        queue.complete_oldest(2, 0, &[]).unwrap();
+        assert!(!queue.has_pending());
+    }
+}
@@ -0,0 +1 @@
+pub mod domain;
\ No newline at end of file
@@ -0,0 +1,150 @@
+//! Dominio del supervisor de tareas: estado de salud de cada actor y extremos de canal
+//! que sobreviven a los reinicios.
+
+
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::{mpsc, watch, Mutex};
+use tracing::warn;
+
+
+/// Receptor compartido entre las sucesivas instancias de una tarea.
+///
+/// Si la tarea entra en pánico, el supervisor conserva el receptor (y los mensajes aún
+/// encolados), por lo que los emisores del resto del sistema no notan el reinicio.
+pub type SharedReceiver<T> = Arc<Mutex<mpsc::Receiver<T>>>;
+
+
+/// Envuelve un receptor para que pueda reutilizarse tras un reinicio.
+pub fn shared<T>(rx: mpsc::Receiver<T>) -> SharedReceiver<T> {
+    Arc::new(Mutex::new(rx))
+}
+
+
+/// Estado de una tarea supervisada.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    /// La instancia actual está en ejecución.
+    Running,
+    /// La instancia anterior entró en pánico; se espera el backoff antes de reiniciarla.
+    Restarting,
+    /// La tarea terminó normalmente (canales cerrados durante el apagado).
+    Finished,
+    /// Se agotaron los reinicios o falló durante el apagado; no se volverá a lanzar.
+    Failed,
+}
+
+
+/// Salud observable de una tarea supervisada.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskHealth {
+    pub state: TaskState,
+    /// Si es crítica, agotar sus reinicios termina el proceso.
+    pub critical: bool,
+    /// Reinicios realizados desde el arranque.
+    pub restarts: u32,
+    /// Momento en que arrancó la instancia actual.
+    pub started_at: DateTime<Utc>,
+    /// Mensaje del último pánico.
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Última vez que la tarea reportó actividad con [`TaskRegistry::beat`].
+    pub last_beat_at: Option<DateTime<Utc>>,
+}
+
+
+impl TaskHealth {
+
+    /// Indica si la tarea está viva o en vías de recuperarse.
+    pub fn is_alive(&self) -> bool {
+        matches!(self.state, TaskState::Running | TaskState::Restarting)
+    }
+
+    pub(crate) fn set_running(&mut self) {
+        self.state = TaskState::Running;
+        self.started_at = Utc::now();
+    }
+
+    pub(crate) fn set_finished(&mut self) {
+        self.state = TaskState::Finished;
+    }
+
+    pub(crate) fn set_restarting(&mut self, error: String) {
+        self.state = TaskState::Restarting;
+        self.restarts += 1;
+        self.last_error = Some(error);
+        self.last_error_at = Some(Utc::now());
+    }
+
+    pub(crate) fn set_failed(&mut self, error: String) {
+        self.state = TaskState::Failed;
+        self.last_error = Some(error);
+        self.last_error_at = Some(Utc::now());
+    }
+}
+
+
+/// Registro de salud de todas las tareas supervisadas, compartido vía `AppContext`.
+#[derive(Debug, Clone, Default)]
+pub struct TaskRegistry {
+    tasks: Arc<DashMap<String, watch::Sender<TaskHealth>>>,
+}
+
+
+impl TaskRegistry {
+
+    /// Da de alta una tarea y devuelve el extremo con el que el supervisor publica su estado.
+    pub(crate) fn register(&self, name: &str, critical: bool) -> watch::Sender<TaskHealth> {
+        let health = TaskHealth {
+            state: TaskState::Running,
+            critical,
+            restarts: 0,
+            started_at: Utc::now(),
+            last_error: None,
+            last_error_at: None,
+            last_beat_at: None,
+        };
+        let tx = watch::Sender::new(health);
+        self.tasks.insert(name.to_string(), tx.clone());
+        tx
+    }
+
+    /// Copia del estado de todas las tareas, ordenada por nombre.
+    pub fn snapshot(&self) -> Vec<(String, TaskHealth)> {
+        let mut tasks: Vec<(String, TaskHealth)> = self.tasks.iter()
+            .map(|entry| (entry.key().clone(), entry.value().borrow().clone()))
+            .collect();
+        tasks.sort_by(|a, b| a.0.cmp(&b.0));
+        tasks
+    }
+
+    /// Estado actual de una tarea, si está registrada.
+    pub fn get(&self, name: &str) -> Option<TaskHealth> {
+        self.tasks.get(name).map(|tx| tx.borrow().clone())
+    }
+
+    /// Registra actividad de una tarea de ciclo periódico (ej. cada tick del sweeper).
+    ///
+    /// No notifica a los observadores de `wait_finished`: solo cambia el estado cuando la
+    /// tarea arranca, falla o termina.
+    pub fn beat(&self, name: &str) {
+        if let Some(tx) = self.tasks.get(name) {
+            tx.send_if_modified(|health| {
+                health.last_beat_at = Some(Utc::now());
+                false
+            });
+        }
+    }
+
+    /// Espera a que la tarea deje de ejecutarse definitivamente (`Finished` o `Failed`).
+    pub async fn wait_finished(&self, name: &str) {
+        let Some(mut rx) = self.tasks.get(name).map(|tx| tx.subscribe()) else {
+            warn!("Warning: la tarea {} no está registrada en el supervisor", name);
+            return;
+        };
+        let _ = rx.wait_for(|health| !health.is_alive()).await;
+    }
+}
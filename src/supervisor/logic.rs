@@ -0,0 +1,121 @@
+//! Supervisor de tareas (Actores).
+//!
+//! Cada `start_*` lanza su tarea a través de [`supervise`] en lugar de un `tokio::spawn`
+//! suelto. El supervisor es dueño del `JoinHandle`, detecta los pánicos y vuelve a crear
+//! la tarea con backoff, reutilizando sus receptores ([`SharedReceiver`]) y clones de sus
+//! emisores. El estado de cada tarea se publica en `AppContext::tasks`.
+//!
+//! # Política
+//! * **Fin normal:** la tarea queda `Finished` y el `JoinHandle` devuelve su resultado.
+//! * **Pánico:** se reinicia tras el backoff. Si la instancia estuvo estable al menos
+//!   `STABLE_AFTER_SECS`, la cuenta de reinicios consecutivos vuelve a cero.
+//! * **Reinicios agotados** (`SUPERVISOR_MAX_RESTARTS`): una tarea crítica termina el proceso
+//!   con código 1 para que el orquestador (Docker, systemd) lo levante de nuevo; una tarea
+//!   no crítica queda `Failed`.
+//! * **Apagado:** un pánico durante el apagado no se reintenta.
+//!
+//! [`SharedReceiver`]: crate::supervisor::domain::SharedReceiver
+
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::task::{JoinError, JoinHandle};
+use tracing::{error, info, warn};
+use crate::context::domain::AppContext;
+use crate::grpc_service::domain::Backoff;
+use crate::system::domain::supervisor_const::{INITIAL_BACKOFF_MS, STABLE_AFTER_SECS};
+
+
+/// Lanza una tarea supervisada.
+///
+/// # Argumentos
+/// * `name`: Nombre con el que se publica su salud.
+/// * `critical`: Si agotar los reinicios debe terminar el proceso.
+/// * `app_context`: Contexto global (registro de tareas, señal de apagado y configuración).
+/// * `factory`: Crea una nueva instancia de la tarea; se invoca en el arranque y en cada reinicio.
+///
+/// # Retorno
+/// `JoinHandle` con el resultado de la tarea, o `None` si quedó `Failed`.
+pub fn supervise<T, F, Fut>(name: impl Into<String>,
+                            critical: bool,
+                            app_context: &AppContext,
+                            mut factory: F) -> JoinHandle<Option<T>>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let name = name.into();
+    let health = app_context.tasks.register(&name, critical);
+    let shutdown = app_context.shutdown.clone();
+    let max_restarts = app_context.system.supervisor_max_restarts;
+    let mut backoff = Backoff::new(Duration::from_millis(INITIAL_BACKOFF_MS),
+                                   Duration::from_secs(app_context.system.supervisor_backoff_max_secs));
+
+    tokio::spawn(async move {
+        loop {
+            health.send_modify(|h| h.set_running());
+            let started = Instant::now();
+
+            let reason = match tokio::spawn(factory()).await {
+                Ok(output) => {
+                    info!("Info: tarea {} finalizada", name);
+                    health.send_modify(|h| h.set_finished());
+                    return Some(output);
+                }
+                Err(e) => panic_reason(e),
+            };
+
+            if started.elapsed() >= Duration::from_secs(STABLE_AFTER_SECS) {
+                backoff.reset();
+            }
+
+            if shutdown.is_triggered() {
+                error!("Error: la tarea {} falló durante el apagado. {}", name, reason);
+                health.send_modify(|h| h.set_failed(reason));
+                return None;
+            }
+
+            if backoff.attempt() >= max_restarts {
+                health.send_modify(|h| h.set_failed(reason.clone()));
+                if critical {
+                    error!("Error: la tarea crítica {} no pudo recuperarse tras {} reinicios. Terminando el proceso. {}",
+                        name, max_restarts, reason);
+                    std::process::exit(1);
+                }
+                error!("Error: la tarea {} no pudo recuperarse tras {} reinicios y queda detenida. {}",
+                    name, max_restarts, reason);
+                return None;
+            }
+
+            let delay = backoff.next_delay();
+            error!("Error: la tarea {} falló. Reinicio {} en {:?}. {}", name, backoff.attempt(), delay, reason);
+            health.send_modify(|h| h.set_restarting(reason));
+
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {},
+                _ = shutdown.wait() => {
+                    warn!("Warning: apagado en curso, la tarea {} no se reinicia", name);
+                    health.send_modify(|h| h.set_failed("apagado durante el reinicio".to_string()));
+                    return None;
+                }
+            }
+        }
+    })
+}
+
+
+/// Extrae el mensaje de pánico de una tarea.
+fn panic_reason(error: JoinError) -> String {
+    if !error.is_panic() {
+        return format!("tarea cancelada: {error}");
+    }
+    let payload = error.into_panic();
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        format!("pánico: {message}")
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        format!("pánico: {message}")
+    } else {
+        "pánico sin mensaje".to_string()
+    }
+}
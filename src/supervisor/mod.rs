@@ -0,0 +1,2 @@
+pub mod domain;
+pub mod logic;
\ No newline at end of file
@@ -12,9 +12,15 @@
 
 
 use std::env;
+use std::time::Duration;
 use tracing::info;
 use tracing_subscriber::{fmt, EnvFilter};
+use crate::archive::domain::{ArchiveScope, ArchiveSettings};
+use crate::bucket::aggregation::AggregationSettings;
+use crate::bucket::domain::{BucketConfig, BucketLimits, BucketSettings, LatePolicy, OverflowPolicy};
 use crate::grpc::ToDataSaver;
+use crate::grpc_service::domain::{AuthMode, GrpcEndpoint, GrpcMode};
+use crate::spill::domain::FsyncPolicy;
 
 
 /// Representa la configuración global del sistema y el estado del entorno.
@@ -32,6 +38,24 @@ pub struct System {
     /// Por defecto: `10`.
     pub db_pool_size: u32,
 
+    /// Filas a partir de las cuales un lote se inserta con `COPY` binario en lugar de
+    /// `INSERT` multi-fila (`DB_COPY_THRESHOLD_ROWS`).
+    /// Por defecto: `2000`.
+    pub db_copy_threshold_rows: usize,
+
+    /// Filas que acumula `dba_task` antes de insertarlas en una transacción (`DB_BATCH_MAX_ROWS`).
+    /// Por defecto: `500`.
+    pub db_batch_max_rows: usize,
+
+    /// Milisegundos máximos que un mensaje espera en el buffer de `dba_task`
+    /// (`DB_BATCH_MAX_AGE_MS`).
+    /// Por defecto: `200`.
+    pub db_batch_max_age_ms: u64,
+
+    /// Rol gRPC del servicio (`GRPC_MODE`: `client` o `server`).
+    /// Por defecto: `client`.
+    pub grpc_mode: GrpcMode,
+
     /// Host donde escuchará o se conectará el servicio gRPC.
     /// Por defecto: `localhost`.
     pub grpc_host: String,
@@ -40,6 +64,127 @@ pub struct System {
     /// Por defecto: `50052`.
     pub grpc_port: u16,
 
+    /// Edges a los que se conecta el modo cliente (`GRPC_ENDPOINTS`, `host:puerto` separados por comas).
+    /// Cada uno tiene su propia conexión y backoff.
+    /// Por defecto: `grpc_host:grpc_port`.
+    pub grpc_endpoints: Vec<GrpcEndpoint>,
+
+    /// Habilita TLS en el canal gRPC saliente (`GRPC_TLS`).
+    /// Por defecto: `false`.
+    pub grpc_tls_enabled: bool,
+
+    /// Ruta al bundle PEM de CAs de confianza. Si no se define, se usan las raíces del sistema.
+    pub grpc_tls_ca_cert: Option<String>,
+
+    /// Ruta al certificado PEM del cliente (mTLS). Requiere `grpc_tls_client_key`.
+    pub grpc_tls_client_cert: Option<String>,
+
+    /// Ruta a la clave privada PEM del cliente (mTLS). Requiere `grpc_tls_client_cert`.
+    pub grpc_tls_client_key: Option<String>,
+
+    /// Nombre de dominio (SNI) a validar en el certificado del Edge, si difiere de `grpc_host`.
+    pub grpc_tls_domain: Option<String>,
+
+    /// Esquema de autenticación hacia el Edge (`GRPC_AUTH_MODE`: `none`, `bearer`, `hmac`).
+    /// Por defecto: `none`.
+    pub grpc_auth_mode: AuthMode,
+
+    /// Secreto de autenticación en línea. Se ignora si se define `grpc_auth_token_file`.
+    pub grpc_auth_token: Option<String>,
+
+    /// Archivo con el secreto de autenticación. Se relee automáticamente cuando cambia.
+    pub grpc_auth_token_file: Option<String>,
+
+    /// Identificador del cliente (`x-client-id`), firmado en modo `hmac`.
+    /// Por defecto: `data_saver`.
+    pub grpc_auth_client_id: String,
+
+    /// Secretos de cada Edge del servidor de ingesta (`GRPC_AUTH_CLIENTS_FILE`): objeto JSON
+    /// `{"client_id": "secreto"}`. Requerido con `GRPC_MODE=server` y autenticación habilitada.
+    pub grpc_auth_clients_file: Option<String>,
+
+    /// Espera base del backoff exponencial de reconexión gRPC, en milisegundos.
+    /// Por defecto: `500`.
+    pub grpc_backoff_initial_ms: u64,
+
+    /// Espera máxima del backoff de reconexión gRPC, en segundos.
+    /// Por defecto: `60`.
+    pub grpc_backoff_max_secs: u64,
+
+    /// Segundos que el stream debe permanecer en `Work` para reiniciar el backoff.
+    /// Por defecto: `60`.
+    pub grpc_backoff_reset_secs: u64,
+
+    /// Host donde escucha el servidor HTTP de observabilidad (`/metrics`).
+    /// Por defecto: `0.0.0.0`.
+    pub http_host: String,
+
+    /// Puerto del servidor HTTP de observabilidad.
+    /// Por defecto: `9090`.
+    pub http_port: u16,
+
+    /// Token que exigen las rutas de administración del servidor HTTP
+    /// (`authorization: Bearer <token>`). Sin token, esas rutas no se exponen.
+    /// Por defecto: sin token.
+    pub http_admin_token: Option<String>,
+
+    /// Plazo máximo en segundos para vaciar el pipeline al apagar (SIGINT/SIGTERM).
+    /// Por defecto: `30` segundos.
+    pub shutdown_deadline_secs: u64,
+
+    /// Reinicios consecutivos que el supervisor intenta antes de dar una tarea por perdida.
+    /// Por defecto: `5`.
+    pub supervisor_max_restarts: u32,
+
+    /// Espera máxima del backoff entre reinicios de una tarea, en segundos.
+    /// Por defecto: `30`.
+    pub supervisor_backoff_max_secs: u64,
+
+    /// Ventanas de agregación: globales (`BUCKET_WINDOW_SECS`, `BUCKET_ALLOWED_LATENESS_SECS`,
+    /// `SWEEPER_INTERVAL_SECS`), excepciones por red (`BUCKET_NETWORK_OVERRIDES`) y plazo de
+    /// inactividad (`BUCKET_IDLE_TIMEOUT_SECS`).
+    /// Por defecto: ventanas de `50` segundos, `10` segundos de demora tolerada, revisadas cada
+    /// `5` segundos y cerradas tras `60` segundos sin muestras de la red.
+    pub bucket: BucketSettings,
+
+    /// Tratamiento de las muestras que llegan con su bucket ya cerrado (`LATE_DATA_POLICY`:
+    /// `table` o `drop`).
+    /// Por defecto: `table`.
+    pub late_data_policy: LatePolicy,
+
+    /// Archivo JSON con los rangos plausibles por sensor, global y por red
+    /// (`PLAUSIBILITY_CONFIG`). Se relee cuando cambia. Sin archivo se usan los rangos históricos.
+    pub plausibility_config: Option<String>,
+
+    /// Cotas de `bucket_map`: buckets abiertos por red (`BUCKET_MAX_OPEN_PER_NETWORK`), muestras
+    /// totales (`BUCKET_MAX_SAMPLES`), márgenes de timestamp respecto del reloj local
+    /// (`BUCKET_MAX_FUTURE_SECS`, `BUCKET_MAX_PAST_SECS`) y política al llenarse
+    /// (`BUCKET_OVERFLOW_POLICY`: `force_close` o `reject`).
+    /// Por defecto: `10` buckets por red, `200000` muestras, `300` segundos hacia adelante,
+    /// `86400` hacia atrás y `force_close`.
+    pub bucket_limits: BucketLimits,
+
+    /// Archivo crudo de mediciones: redes archivadas (`RAW_ARCHIVE_NETWORKS`: `*`, lista separada
+    /// por comas o vacío para deshabilitarlo), retención (`RAW_ARCHIVE_RETENTION_DAYS`), tamaño
+    /// de lote (`RAW_ARCHIVE_BATCH_SIZE`) e intervalo de vaciado (`RAW_ARCHIVE_FLUSH_SECS`).
+    /// Por defecto: deshabilitado, `30` días, `500` mediciones y `5` segundos.
+    pub raw_archive: ArchiveSettings,
+
+    /// Archivo donde el sweeper guarda los buckets abiertos para restaurarlos al reiniciar
+    /// (`BUCKET_CHECKPOINT_FILE`). `None` si `BUCKET_CHECKPOINT=false`.
+    /// Por defecto: `./buckets.checkpoint.json`.
+    pub bucket_checkpoint_file: Option<String>,
+
+    /// Intervalo mínimo entre escrituras del checkpoint por muestras nuevas
+    /// (`BUCKET_CHECKPOINT_INTERVAL_SECS`). Los cierres de buckets se escriben en el mismo tick.
+    /// Por defecto: `30` segundos.
+    pub bucket_checkpoint_interval_secs: u64,
+
+    /// Estrategia de agregación global (`AGGREGATION_STRATEGY`) y excepciones por red y
+    /// sensor (`AGGREGATION_OVERRIDES`).
+    /// Por defecto: `iqr_mean` para todos los sensores.
+    pub aggregation: AggregationSettings,
+
     /// Intervalo en segundos para enviar señales de vida (Heartbeat).
     /// Por defecto: `30` segundos.
     pub heartbeat_interval_secs: u64,
@@ -51,6 +196,32 @@ pub struct System {
     /// Nivel de detalle de los logs (ej. `info`, `debug`, `warn`).
     /// Se autoconfigura según el `environment` si no se especifica.
     pub rust_log: String,
+
+    /// Directorio de la cola de desborde en disco (ver `spill::domain`).
+    /// Por defecto: `./spill`.
+    pub spill_dir: String,
+
+    /// Tamaño máximo de cada segmento de la cola de desborde, en bytes.
+    /// Por defecto: `8388608` (8 MiB).
+    pub spill_max_segment_bytes: u64,
+
+    /// Tamaño máximo total de la cola de desborde, en bytes. Al superarlo se descartan datos.
+    /// Por defecto: `536870912` (512 MiB).
+    pub spill_max_total_bytes: u64,
+
+    /// Política de `fsync` de la cola de desborde (`SPILL_FSYNC`: `always`, `interval`, `never`).
+    /// Por defecto: `interval` cada `SPILL_FSYNC_INTERVAL_MS` (`1000`).
+    pub spill_fsync: FsyncPolicy,
+
+    /// Intervalo en segundos entre intentos de reproducir la cola de desborde.
+    /// Por defecto: `10` segundos.
+    pub spill_replay_interval_secs: u64,
+
+    /// Token del bot de Telegram que envía las alertas. **Requerido**.
+    pub bot_token: String,
+
+    /// Chat de Telegram que recibe las alertas. **Requerido**.
+    pub chat_id: String,
 }
 
 
@@ -67,40 +238,263 @@ impl System {
     /// * Si `DATABASE_URL` no está definida.
     /// * Si las variables numéricas (`PORT`, `POOL_SIZE`) no son números válidos.
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        if env::var("ENVIRONMENT").unwrap_or_else(|_| "development".into()) == "development" {
+            dotenv::dotenv().ok();
+        }
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Carga la configuración leyendo cada variable con `var`, con los mismos valores por
+    /// defecto y validaciones que [`System::new`]. Permite armar la configuración de los tests
+    /// sin modificar el entorno del proceso.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, Box<dyn std::error::Error>> {
 
         info!("Info: creando objeto system");
 
-        let environment = env::var("ENVIRONMENT")
-            .unwrap_or_else(|_| "development".into());
+        // Una variable vacía cuenta como ausente
+        let optional_var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
 
-        if environment == "development" {
-            dotenv::dotenv().ok();
-        }
+        let environment = var("ENVIRONMENT")
+            .unwrap_or_else(|| "development".into());
+
+        let grpc_host = var("GRPC_HOST")
+            .unwrap_or("localhost".to_string());
+
+        let grpc_port = var("GRPC_PORT")
+            .unwrap_or("50052".to_string())
+            .parse()
+            .expect("GRPC_PORT debe ser un número");
+
+        let grpc_endpoints = match optional_var("GRPC_ENDPOINTS") {
+            Some(list) => GrpcEndpoint::parse_list(&list)
+                .expect("GRPC_ENDPOINTS debe ser una lista de host:puerto separados por comas"),
+            None => vec![GrpcEndpoint { host: grpc_host.clone(), port: grpc_port }],
+        };
+
+        let bucket_default = BucketConfig {
+            window_secs: var("BUCKET_WINDOW_SECS")
+                .unwrap_or("50".to_string())
+                .parse()
+                .expect("BUCKET_WINDOW_SECS debe ser un número"),
+            lateness_secs: var("BUCKET_ALLOWED_LATENESS_SECS")
+                .unwrap_or("10".to_string())
+                .parse()
+                .expect("BUCKET_ALLOWED_LATENESS_SECS debe ser un número"),
+            sweep_secs: var("SWEEPER_INTERVAL_SECS")
+                .unwrap_or("5".to_string())
+                .parse()
+                .expect("SWEEPER_INTERVAL_SECS debe ser un número"),
+        };
+
+        let bucket_idle_secs = var("BUCKET_IDLE_TIMEOUT_SECS")
+            .unwrap_or("60".to_string())
+            .parse()
+            .expect("BUCKET_IDLE_TIMEOUT_SECS debe ser un número");
+
+        let bucket = BucketSettings::new(bucket_default, bucket_idle_secs, optional_var("BUCKET_NETWORK_OVERRIDES").as_deref())
+            .expect("BUCKET_* inválidos: BUCKET_NETWORK_OVERRIDES debe ser red=ventana:demora[:sweep] separados por comas");
+
+        let bucket_limits = BucketLimits {
+            max_open_per_network: var("BUCKET_MAX_OPEN_PER_NETWORK")
+                .unwrap_or("10".to_string())
+                .parse()
+                .expect("BUCKET_MAX_OPEN_PER_NETWORK debe ser un número"),
+            max_samples: var("BUCKET_MAX_SAMPLES")
+                .unwrap_or("200000".to_string())
+                .parse()
+                .expect("BUCKET_MAX_SAMPLES debe ser un número"),
+            max_future_secs: var("BUCKET_MAX_FUTURE_SECS")
+                .unwrap_or("300".to_string())
+                .parse()
+                .expect("BUCKET_MAX_FUTURE_SECS debe ser un número"),
+            max_past_secs: var("BUCKET_MAX_PAST_SECS")
+                .unwrap_or("86400".to_string())
+                .parse()
+                .expect("BUCKET_MAX_PAST_SECS debe ser un número"),
+            overflow: OverflowPolicy::from_config(&var("BUCKET_OVERFLOW_POLICY").unwrap_or("force_close".to_string()))
+                .expect("BUCKET_OVERFLOW_POLICY debe ser force_close o reject"),
+        };
+        bucket_limits.validate().expect("BUCKET_MAX_* inválidos");
+
+        let raw_archive = ArchiveSettings {
+            scope: ArchiveScope::parse(optional_var("RAW_ARCHIVE_NETWORKS").as_deref()),
+            retention_days: var("RAW_ARCHIVE_RETENTION_DAYS")
+                .unwrap_or("30".to_string())
+                .parse()
+                .expect("RAW_ARCHIVE_RETENTION_DAYS debe ser un número"),
+            batch_size: var("RAW_ARCHIVE_BATCH_SIZE")
+                .unwrap_or("500".to_string())
+                .parse()
+                .expect("RAW_ARCHIVE_BATCH_SIZE debe ser un número"),
+            flush_secs: var("RAW_ARCHIVE_FLUSH_SECS")
+                .unwrap_or("5".to_string())
+                .parse()
+                .expect("RAW_ARCHIVE_FLUSH_SECS debe ser un número"),
+        };
+        raw_archive.validate().expect("RAW_ARCHIVE_* inválidos");
+
+        let bucket_checkpoint_file = var("BUCKET_CHECKPOINT")
+            .unwrap_or("true".to_string())
+            .parse::<bool>()
+            .expect("BUCKET_CHECKPOINT debe ser true o false")
+            .then(|| var("BUCKET_CHECKPOINT_FILE").unwrap_or("./buckets.checkpoint.json".to_string()));
+
+        let aggregation = AggregationSettings::new(
+            &var("AGGREGATION_STRATEGY").unwrap_or("iqr_mean".to_string()),
+            optional_var("AGGREGATION_OVERRIDES").as_deref(),
+        ).expect("AGGREGATION_* inválidos: AGGREGATION_OVERRIDES debe ser red.sensor=estrategia separados por comas");
 
         Ok(System {
-            database_url: env::var("DATABASE_URL")
+            database_url: var("DATABASE_URL")
                 .expect("DATABASE_URL no está configurada"),
 
-            db_pool_size: env::var("DB_POOL_SIZE")
+            db_pool_size: var("DB_POOL_SIZE")
                 .unwrap_or("10".to_string())
                 .parse()
                 .expect("DB_POOL_SIZE debe ser un número"),
 
-            grpc_host: env::var("GRPC_HOST")
-                .unwrap_or("localhost".to_string()),
+            db_copy_threshold_rows: var("DB_COPY_THRESHOLD_ROWS")
+                .unwrap_or("2000".to_string())
+                .parse()
+                .expect("DB_COPY_THRESHOLD_ROWS debe ser un número"),
+
+            db_batch_max_rows: var("DB_BATCH_MAX_ROWS")
+                .unwrap_or("500".to_string())
+                .parse()
+                .expect("DB_BATCH_MAX_ROWS debe ser un número"),
 
-            grpc_port: env::var("GRPC_PORT")
-                .unwrap_or("50052".to_string())
+            db_batch_max_age_ms: var("DB_BATCH_MAX_AGE_MS")
+                .unwrap_or("200".to_string())
                 .parse()
-                .expect("GRPC_PORT debe ser un número"),
+                .expect("DB_BATCH_MAX_AGE_MS debe ser un número"),
+
+            grpc_mode: GrpcMode::from_config(&var("GRPC_MODE").unwrap_or("client".to_string()))
+                .expect("GRPC_MODE debe ser client o server"),
+
+            grpc_host,
 
-            heartbeat_interval_secs: env::var("HEARTBEAT_INTERVAL_SECS")
+            grpc_port,
+
+            grpc_endpoints,
+
+            grpc_tls_enabled: var("GRPC_TLS")
+                .unwrap_or("false".to_string())
+                .parse()
+                .expect("GRPC_TLS debe ser true o false"),
+
+            grpc_tls_ca_cert: optional_var("GRPC_TLS_CA_CERT"),
+
+            grpc_tls_client_cert: optional_var("GRPC_TLS_CLIENT_CERT"),
+
+            grpc_tls_client_key: optional_var("GRPC_TLS_CLIENT_KEY"),
+
+            grpc_tls_domain: optional_var("GRPC_TLS_DOMAIN"),
+
+            grpc_auth_mode: AuthMode::from_config(&var("GRPC_AUTH_MODE").unwrap_or("none".to_string()))
+                .expect("GRPC_AUTH_MODE debe ser none, bearer o hmac"),
+
+            grpc_auth_token: optional_var("GRPC_AUTH_TOKEN"),
+
+            grpc_auth_token_file: optional_var("GRPC_AUTH_TOKEN_FILE"),
+
+            grpc_auth_client_id: var("GRPC_AUTH_CLIENT_ID")
+                .unwrap_or("data_saver".to_string()),
+
+            grpc_auth_clients_file: optional_var("GRPC_AUTH_CLIENTS_FILE"),
+
+            grpc_backoff_initial_ms: var("GRPC_BACKOFF_INITIAL_MS")
+                .unwrap_or("500".to_string())
+                .parse()
+                .expect("GRPC_BACKOFF_INITIAL_MS debe ser un número"),
+
+            grpc_backoff_max_secs: var("GRPC_BACKOFF_MAX_SECS")
+                .unwrap_or("60".to_string())
+                .parse()
+                .expect("GRPC_BACKOFF_MAX_SECS debe ser un número"),
+
+            grpc_backoff_reset_secs: var("GRPC_BACKOFF_RESET_SECS")
+                .unwrap_or("60".to_string())
+                .parse()
+                .expect("GRPC_BACKOFF_RESET_SECS debe ser un número"),
+
+            http_host: var("HTTP_HOST")
+                .unwrap_or("0.0.0.0".to_string()),
+
+            http_port: var("HTTP_PORT")
+                .unwrap_or("9090".to_string())
+                .parse()
+                .expect("HTTP_PORT debe ser un número"),
+
+            http_admin_token: optional_var("HTTP_ADMIN_TOKEN"),
+
+            shutdown_deadline_secs: var("SHUTDOWN_DEADLINE_SECS")
+                .unwrap_or("30".to_string())
+                .parse()
+                .expect("SHUTDOWN_DEADLINE_SECS debe ser un número"),
+
+            supervisor_max_restarts: var("SUPERVISOR_MAX_RESTARTS")
+                .unwrap_or("5".to_string())
+                .parse()
+                .expect("SUPERVISOR_MAX_RESTARTS debe ser un número"),
+
+            supervisor_backoff_max_secs: var("SUPERVISOR_BACKOFF_MAX_SECS")
+                .unwrap_or("30".to_string())
+                .parse()
+                .expect("SUPERVISOR_BACKOFF_MAX_SECS debe ser un número"),
+
+            bucket,
+
+            plausibility_config: optional_var("PLAUSIBILITY_CONFIG"),
+
+            aggregation,
+
+            bucket_limits,
+
+            raw_archive,
+
+            bucket_checkpoint_file,
+
+            bucket_checkpoint_interval_secs: var("BUCKET_CHECKPOINT_INTERVAL_SECS")
+                .unwrap_or("30".to_string())
+                .parse()
+                .expect("BUCKET_CHECKPOINT_INTERVAL_SECS debe ser un número"),
+
+            late_data_policy: LatePolicy::from_config(&var("LATE_DATA_POLICY").unwrap_or("table".to_string()))
+                .expect("LATE_DATA_POLICY debe ser table o drop"),
+
+            heartbeat_interval_secs: var("HEARTBEAT_INTERVAL_SECS")
                 .unwrap_or("30".to_string())
                 .parse()
                 .expect("HEARTBEAT_INTERVAL_SECS debe ser un número"),
 
-            rust_log: env::var("RUST_LOG")
-                .unwrap_or_else(|_| {
+            spill_dir: var("SPILL_DIR")
+                .unwrap_or("./spill".to_string()),
+
+            spill_max_segment_bytes: var("SPILL_MAX_SEGMENT_BYTES")
+                .unwrap_or("8388608".to_string())
+                .parse()
+                .expect("SPILL_MAX_SEGMENT_BYTES debe ser un número"),
+
+            spill_max_total_bytes: var("SPILL_MAX_TOTAL_BYTES")
+                .unwrap_or("536870912".to_string())
+                .parse()
+                .expect("SPILL_MAX_TOTAL_BYTES debe ser un número"),
+
+            spill_fsync: FsyncPolicy::from_config(
+                &var("SPILL_FSYNC").unwrap_or("interval".to_string()),
+                Duration::from_millis(var("SPILL_FSYNC_INTERVAL_MS")
+                    .unwrap_or("1000".to_string())
+                    .parse()
+                    .expect("SPILL_FSYNC_INTERVAL_MS debe ser un número")),
+            ).expect("SPILL_FSYNC debe ser always, interval o never"),
+
+            spill_replay_interval_secs: var("SPILL_REPLAY_INTERVAL_SECS")
+                .unwrap_or("10".to_string())
+                .parse()
+                .expect("SPILL_REPLAY_INTERVAL_SECS debe ser un número"),
+
+            rust_log: var("RUST_LOG")
+                .unwrap_or_else(|| {
                     match environment.as_str() {
                         "development" => "debug".to_string(),
                         "staging" => "info".to_string(),
@@ -108,6 +502,12 @@ impl System {
                     }
                 }),
 
+            bot_token: var("BOT_TOKEN")
+                .expect("BOT_TOKEN no está configurado"),
+
+            chat_id: var("CHAT_ID")
+                .expect("CHAT_ID no está configurado"),
+
             environment,
         })
     }
@@ -118,14 +518,32 @@ impl System {
 ///
 /// Se utiliza para desacoplar la recepción de datos gRPC de su procesamiento.
 pub enum InternalEvent {
-    IncomingMessage(ToDataSaver)
+    /// Mensaje de un Edge. `endpoint` identifica su origen: el `host:port` del Edge en modo
+    /// cliente; en modo servidor, el cliente autenticado o, sin autenticación, la IP del peer.
+    IncomingMessage { endpoint: String, message: ToDataSaver },
 }
 
 
 /// Categorización de errores operativos del sistema.
 #[derive(Debug)]
 pub enum ErrorType {
-    Endpoint,
+    /// No se pudo crear o conectar el endpoint gRPC. Contiene el detalle del fallo.
+    Endpoint(String),
+    /// La configuración TLS es inválida (archivos faltantes, PEM ilegible, etc.).
+    Tls(String),
+    /// La configuración de autenticación es inválida (token faltante o ilegible).
+    Auth(String),
+}
+
+
+impl std::fmt::Display for ErrorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorType::Endpoint(detail) => write!(f, "endpoint gRPC: {detail}"),
+            ErrorType::Tls(detail) => write!(f, "TLS gRPC: {detail}"),
+            ErrorType::Auth(detail) => write!(f, "autenticación gRPC: {detail}"),
+        }
+    }
 }
 
 
@@ -164,4 +582,26 @@ pub mod grpc_service_const {
     pub const TIMEOUT_SECS: u64 = 10;
     pub const KEEP_ALIVE_TIMEOUT_SECS: u64 = 30;
     pub const KEEP_ALIVE_INTERVAL_SECS: u64 = 15;
-}
\ No newline at end of file
+    /// Diferencia máxima, en segundos, entre el `x-timestamp` de una firma HMAC entrante y el
+    /// reloj local.
+    pub const AUTH_MAX_CLOCK_SKEW_SECS: i64 = 300;
+}
+
+
+/// Constantes de los endpoints de salud (`/healthz`, `/readyz`).
+pub mod health_const {
+    /// Plazo para que la base de datos responda `SELECT 1`, en segundos.
+    pub const DB_PING_TIMEOUT_SECS: u64 = 2;
+    /// Antigüedad mínima tolerada del último tick del sweeper, en segundos. Si el sweeper
+    /// late más lento, se toleran tres de sus intervalos.
+    pub const SWEEPER_STALE_SECS: i64 = 30;
+}
+
+
+/// Constantes del supervisor de tareas.
+pub mod supervisor_const {
+    /// Espera base del backoff entre reinicios, en milisegundos.
+    pub const INITIAL_BACKOFF_MS: u64 = 500;
+    /// Segundos que una instancia debe ejecutarse para reiniciar la cuenta de reinicios.
+    pub const STABLE_AFTER_SECS: u64 = 60;
+}
@@ -0,0 +1,9 @@
+//! Tests de punta a punta del pipeline, sin base de datos.
+//!
+//! Cada test levanta `message_download`, `bucket_task`, `sweeper_task` y `dba_task` con los
+//! mismos `start_*` que `main`, sobre un `MemoryStorage`, y envía mensajes del Edge por el
+//! canal que en producción alimenta la capa gRPC.
+
+
+mod support;
+mod pipeline;
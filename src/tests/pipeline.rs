@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use chrono::Utc;
+use crate::grpc::MeasurementBatch;
+use crate::grpc::to_data_saver::Payload;
+use crate::tests::support::{measurement, metric, monitor, now, test_system, with_window, Pipeline, NETWORK};
+use crate::weather::domain::Weather;
+
+
+fn ids(entries: &[(&str, &[u64])]) -> BTreeMap<String, Vec<u64>> {
+    entries.iter().map(|(sender, ids)| (sender.to_string(), ids.to_vec())).collect()
+}
+
+
+#[tokio::test]
+async fn event_messages_are_written_in_one_batch_and_acked() {
+    let mut system = test_system();
+    system.db_batch_max_rows = 4;
+    system.db_batch_max_age_ms = 60_000;
+    let pipeline = Pipeline::start(system).await;
+
+    let ts = now();
+    pipeline.send(monitor("hub-a", 1, ts)).await;
+    pipeline.send(monitor("hub-b", 1, ts)).await;
+    pipeline.send(metric("hub-a", 2, ts)).await;
+    pipeline.send(monitor("hub-a", 3, ts + 1)).await;
+
+    let recorded = pipeline.wait_for(|recorded| !recorded.batches.is_empty()).await;
+    let stopped = pipeline.stop().await;
+
+    assert_eq!(recorded.batches.len(), 1);
+    assert_eq!(recorded.batches[0].monitor.len(), 3);
+    assert_eq!(recorded.batches[0].metrics.len(), 1);
+    assert_eq!(stopped.recorded.batches.len(), 1);
+    assert_eq!(stopped.acked(), ids(&[("hub-a", &[1, 2, 3]), ("hub-b", &[1])]));
+    assert!(stopped.nacked().is_empty());
+}
+
+
+#[tokio::test]
+async fn pending_rows_are_written_on_shutdown() {
+    let mut system = test_system();
+    // Lo acumulado se vacía por antigüedad aunque los canales ya se hayan cerrado
+    system.db_batch_max_rows = 100;
+    system.db_batch_max_age_ms = 200;
+    let pipeline = Pipeline::start(system).await;
+
+    pipeline.send(monitor("hub-a", 1, now())).await;
+    pipeline.send_weather(Weather { timestamp: Utc::now(), temperature_2m: 18.5, relative_humidity_2m: 60.0 }).await;
+    let stopped = pipeline.stop().await;
+
+    assert_eq!(stopped.recorded.batches.len(), 1);
+    assert_eq!(stopped.recorded.weather.len(), 1);
+    assert_eq!(stopped.acked(), ids(&[("hub-a", &[1])]));
+}
+
+
+#[tokio::test]
+async fn open_bucket_is_aggregated_and_acked_on_shutdown() {
+    let mut system = test_system();
+    with_window(&mut system, 60);
+    let pipeline = Pipeline::start(system).await;
+
+    let window = now() - now().rem_euclid(60) - 600;
+    pipeline.send(Payload::MeasurementBatch(MeasurementBatch {
+        measurements: vec![measurement("hub-a", 1, window + 5, 20.0), measurement("hub-a", 2, window + 15, 21.0)],
+    })).await;
+    pipeline.send(Payload::Measurement(measurement("hub-b", 1, window + 25, 22.0))).await;
+    let stopped = pipeline.stop().await;
+
+    assert_eq!(stopped.flushed, 1);
+    assert_eq!(stopped.recorded.telemetry.len(), 1);
+    let telemetry = &stopped.recorded.telemetry[0];
+    assert_eq!(telemetry.network_id, NETWORK);
+    assert_eq!(telemetry.timestamp, window);
+    assert_eq!(telemetry.window_secs, 60);
+    assert_eq!(telemetry.pulse_counter_total, 3);
+    assert_eq!(telemetry.senders, vec!["hub-a".to_string(), "hub-b".to_string()]);
+    let temperature = telemetry.temperature.expect("sin temperatura agregada");
+    assert!((20.0..=22.0).contains(&temperature), "temperatura fuera de rango: {temperature}");
+    assert_eq!(stopped.acked(), ids(&[("hub-a", &[1, 2]), ("hub-b", &[1])]));
+}
+
+
+#[tokio::test]
+async fn watermark_closes_windows_and_late_samples_are_recorded() {
+    let mut system = test_system();
+    with_window(&mut system, 60);
+    let pipeline = Pipeline::start(system).await;
+
+    let window = now() - now().rem_euclid(60) - 600;
+    pipeline.send(Payload::Measurement(measurement("hub-a", 1, window + 10, 20.0))).await;
+    // Lleva el watermark dos ventanas más adelante: la primera queda cerrada
+    pipeline.send(Payload::Measurement(measurement("hub-a", 2, window + 130, 21.0))).await;
+
+    let recorded = pipeline.wait_for(|recorded| !recorded.telemetry.is_empty()).await;
+    assert_eq!(recorded.telemetry[0].timestamp, window);
+
+    pipeline.send(Payload::Measurement(measurement("hub-a", 3, window + 20, 22.0))).await;
+    let recorded = pipeline.wait_for(|recorded| !recorded.late_samples.is_empty()).await;
+    assert_eq!(recorded.late_samples[0].bucket_ts, window);
+    assert_eq!(recorded.late_samples[0].measurement.metadata.sequence_id, 3);
+
+    let stopped = pipeline.stop().await;
+    let windows: Vec<i64> = stopped.recorded.telemetry.iter().map(|telemetry| telemetry.timestamp).collect();
+    assert_eq!(windows, vec![window, window + 120]);
+    assert_eq!(stopped.acked(), ids(&[("hub-a", &[1, 2, 3])]));
+}
+
+
+#[tokio::test]
+async fn sample_cap_closes_the_oldest_buckets_in_one_pass() {
+    let mut system = test_system();
+    with_window(&mut system, 60);
+    system.bucket_limits.max_samples = 10;
+    let pipeline = Pipeline::start(system).await;
+
+    // Una red por ventana, para que el watermark no cierre ninguna: net-3 es la más antigua
+    let window = now() - now().rem_euclid(60) - 600;
+    let on = |network: usize, sequence_id: u64| {
+        let mut sample = measurement("hub-a", sequence_id, window - network as i64 * 60 + 5, 20.0);
+        sample.network = format!("net-{network}");
+        Payload::Measurement(sample)
+    };
+    let mut sequence_id = 0;
+    for (network, samples) in [(0, 3), (1, 3), (2, 3), (3, 1)] {
+        for _ in 0..samples {
+            sequence_id += 1;
+            pipeline.send(on(network, sequence_id)).await;
+        }
+    }
+    // Con el tope alcanzado se baja al 90 %: se cierran net-3 y net-2 de una vez
+    pipeline.send(on(0, sequence_id + 1)).await;
+
+    let recorded = pipeline.wait_for(|recorded| recorded.telemetry.len() >= 2).await;
+    let mut forced: Vec<&str> = recorded.telemetry.iter().map(|telemetry| telemetry.network_id.as_str()).collect();
+    forced.sort_unstable();
+    assert_eq!(forced, vec!["net-2", "net-3"]);
+
+    let stopped = pipeline.stop().await;
+    assert_eq!(stopped.recorded.telemetry.len(), 4);
+    assert_eq!(stopped.acked()["hub-a"].len(), 11);
+}
+
+
+#[tokio::test]
+async fn network_cap_closes_its_oldest_bucket() {
+    let mut system = test_system();
+    with_window(&mut system, 60);
+    system.bucket.default.lateness_secs = 600;
+    system.bucket_limits.max_open_per_network = 2;
+    let pipeline = Pipeline::start(system).await;
+
+    let window = now() - now().rem_euclid(60) - 300;
+    for (sequence_id, offset) in [(1, 0), (2, 60), (3, 120)] {
+        pipeline.send(Payload::Measurement(measurement("hub-a", sequence_id, window + offset + 5, 20.0))).await;
+    }
+
+    let recorded = pipeline.wait_for(|recorded| !recorded.telemetry.is_empty()).await;
+    assert_eq!(recorded.telemetry[0].timestamp, window);
+    assert_eq!(pipeline.app_context.open_buckets.get(NETWORK).map(|open| *open), Some(2));
+
+    let stopped = pipeline.stop().await;
+    assert_eq!(stopped.recorded.telemetry.len(), 3);
+}
+
+
+#[tokio::test]
+async fn samples_from_the_future_are_nacked() {
+    let mut system = test_system();
+    with_window(&mut system, 60);
+    system.bucket_limits.max_future_secs = 300;
+    let pipeline = Pipeline::start(system).await;
+
+    pipeline.send(Payload::Measurement(measurement("hub-a", 7, now() + 3600, 20.0))).await;
+    let stopped = pipeline.stop().await;
+
+    assert!(stopped.recorded.telemetry.is_empty());
+    assert!(stopped.acked().is_empty());
+    let nacked = stopped.nacked();
+    assert_eq!(nacked.len(), 1);
+    assert_eq!((nacked[0].0.as_str(), nacked[0].1), ("hub-a", 7));
+    assert!(nacked[0].2.contains("future"), "motivo inesperado: {}", nacked[0].2);
+}
+
+
+#[tokio::test]
+async fn rejected_single_rows_are_nacked_instead_of_spilled() {
+    let mut system = test_system();
+    system.db_batch_max_rows = 1;
+    let pipeline = Pipeline::start(system).await;
+    pipeline.storage.reject_sender("hub-bad");
+    // Con la señal ya disparada `dba_task` no reintenta
+    pipeline.app_context.shutdown.trigger();
+
+    pipeline.send(monitor("hub-bad", 1, now())).await;
+    pipeline.send(monitor("hub-a", 2, now())).await;
+    let stopped = pipeline.stop().await;
+
+    assert_eq!(stopped.recorded.batches.len(), 1);
+    assert_eq!(stopped.drain.spilled, 0);
+    assert_eq!(stopped.acked(), ids(&[("hub-a", &[2])]));
+    let nacked = stopped.nacked();
+    assert_eq!(nacked.len(), 1);
+    assert_eq!((nacked[0].0.as_str(), nacked[0].1), ("hub-bad", 1));
+}
+
+
+#[tokio::test]
+async fn rejected_telemetry_is_nacked_instead_of_spilled() {
+    let mut system = test_system();
+    with_window(&mut system, 60);
+    let pipeline = Pipeline::start(system).await;
+    pipeline.storage.reject_sender("hub-bad");
+    pipeline.app_context.shutdown.trigger();
+
+    let window = now() - now().rem_euclid(60) - 600;
+    pipeline.send(Payload::MeasurementBatch(MeasurementBatch {
+        measurements: vec![measurement("hub-a", 1, window + 5, 20.0), measurement("hub-bad", 2, window + 15, 21.0)],
+    })).await;
+    let stopped = pipeline.stop().await;
+
+    assert!(stopped.recorded.telemetry.is_empty());
+    assert_eq!(stopped.drain.spilled, 0);
+    assert!(stopped.acked().is_empty());
+    let mut nacked: Vec<(String, u64)> = stopped.nacked().into_iter()
+        .map(|(sender, sequence_id, _)| (sender, sequence_id))
+        .collect();
+    nacked.sort();
+    assert_eq!(nacked, vec![("hub-a".to_string(), 1), ("hub-bad".to_string(), 2)]);
+}
+
+
+#[tokio::test]
+async fn rejected_rows_are_isolated_from_their_batch() {
+    let mut system = test_system();
+    system.db_batch_max_rows = 2;
+    system.db_batch_max_age_ms = 60_000;
+    let pipeline = Pipeline::start(system).await;
+    pipeline.storage.reject_sender("hub-bad");
+
+    pipeline.send(monitor("hub-a", 1, now())).await;
+    pipeline.send(monitor("hub-bad", 2, now())).await;
+    let stopped = pipeline.stop().await;
+
+    assert_eq!(stopped.recorded.batches.len(), 1);
+    assert_eq!(stopped.recorded.batches[0].monitor[0].metadata.sender_user_id, "hub-a");
+    assert_eq!(stopped.acked(), ids(&[("hub-a", &[1])]));
+    assert_eq!(stopped.nacked().len(), 1);
+}
+
+
+#[tokio::test]
+async fn unavailable_storage_spills_during_shutdown_without_acks() {
+    let mut system = test_system();
+    system.db_batch_max_rows = 1;
+    let pipeline = Pipeline::start(system).await;
+    pipeline.storage.set_available(false);
+    // Con la señal ya disparada `dba_task` no reintenta: escribe en la cola de desborde
+    pipeline.app_context.shutdown.trigger();
+
+    pipeline.send(monitor("hub-a", 1, now())).await;
+    pipeline.send(monitor("hub-a", 2, now())).await;
+    let stopped = pipeline.stop().await;
+
+    assert!(stopped.recorded.batches.is_empty());
+    assert_eq!(stopped.drain.spilled, 2);
+    assert_eq!(stopped.drain.persisted, 0);
+    assert!(stopped.acked().is_empty());
+    assert!(stopped.nacked().is_empty());
+}
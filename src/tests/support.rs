@@ -0,0 +1,267 @@
+//! Armado del pipeline y mensajes de prueba.
+
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Instant};
+use crate::bucket::domain::BucketConfig;
+use crate::bucket::logic::{start_bucket, start_sweeper};
+use crate::context::domain::AppContext;
+use crate::database::logic::start_dba;
+use crate::database::memory::{MemoryStorage, Recorded};
+use crate::database::storage::Storage;
+use crate::grpc::to_data_saver::Payload;
+use crate::grpc::{Measurement, Metadata, Monitor, SystemMetrics, ToDataSaver};
+use crate::message::domain::Message;
+use crate::message::logic::start_message_download;
+use crate::shutdown::domain::DrainReport;
+use crate::system::domain::{InternalEvent, System};
+use crate::weather::domain::Weather;
+
+
+/// Red de todas las muestras de prueba.
+pub const NETWORK: &str = "net-test";
+
+/// Plazo máximo para que el pipeline alcance el estado esperado o termine de vaciarse.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Variables de la configuración de prueba. Las obligatorias reciben un valor que los tests
+/// no usan; el resto toma su valor por defecto.
+const TEST_VARS: &[(&str, &str)] = &[
+    ("ENVIRONMENT", "test"),
+    ("DATABASE_URL", "postgres://test@localhost/test"),
+    ("BOT_TOKEN", "test"),
+    ("CHAT_ID", "0"),
+];
+
+static SPILL_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Configuración con los valores por defecto de producción y sin checkpoint de buckets. No
+/// lee el entorno del proceso ni el `.env` del desarrollador.
+pub fn test_system() -> System {
+    let mut system = System::from_vars(|name| {
+        TEST_VARS.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+    }).expect("Error: configuración de prueba inválida");
+    system.bucket_checkpoint_file = None;
+    system
+}
+
+
+/// Directorio propio de la cola de desborde de un pipeline de prueba. Se borra al soltarse,
+/// aunque el test falle antes de [`Pipeline::stop`].
+struct SpillDir(PathBuf);
+
+impl SpillDir {
+    fn new() -> Self {
+        Self(env::temp_dir().join(format!("data_saver_test_{}_{}", std::process::id(), SPILL_DIRS.fetch_add(1, Ordering::Relaxed))))
+    }
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+
+/// Ventana de `window_secs` sin demora, revisada cada segundo. Los buckets se cierran por
+/// watermark o en el apagado, nunca por inactividad de la red.
+pub fn with_window(system: &mut System, window_secs: i64) {
+    system.bucket.default = BucketConfig { window_secs, lateness_secs: 0, sweep_secs: 1 };
+    system.bucket.networks.clear();
+    system.bucket.idle_secs = 3600;
+}
+
+
+/// Lo que dejó el pipeline al terminar de vaciarse.
+#[derive(Debug)]
+pub struct Stopped {
+    pub recorded: Recorded,
+    /// Buckets que el sweeper cerró durante el apagado.
+    pub flushed: usize,
+    pub drain: DrainReport,
+    /// Ack, Nack y demás mensajes que `dba_task` envió hacia el Edge.
+    pub outbound: Vec<Message>,
+}
+
+
+impl Stopped {
+
+    /// `sequence_id` confirmados con Ack, por emisor y ordenados.
+    pub fn acked(&self) -> BTreeMap<String, Vec<u64>> {
+        let mut acked: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        for msg in &self.outbound {
+            if let Message::Ack(ack) = msg {
+                acked.entry(ack.metadata.destination_id.clone()).or_default().extend(&ack.sequence_ids);
+            }
+        }
+        acked.values_mut().for_each(|ids| ids.sort_unstable());
+        acked
+    }
+
+    /// `(emisor, sequence_id, motivo)` de cada entrega rechazada con Nack.
+    pub fn nacked(&self) -> Vec<(String, u64, String)> {
+        self.outbound.iter()
+            .filter_map(|msg| match msg {
+                Message::Nack(nack) => Some(nack),
+                _ => None,
+            })
+            .flat_map(|nack| nack.sequence_ids.iter()
+                .map(|id| (nack.metadata.destination_id.clone(), *id, nack.reason.clone())))
+            .collect()
+    }
+}
+
+
+/// `message_download`, `bucket_task`, `sweeper_task` y `dba_task` conectados como en `main`,
+/// con la persistencia en memoria y una cola de desborde propia.
+pub struct Pipeline {
+    pub app_context: AppContext,
+    pub storage: Arc<MemoryStorage>,
+    tx_edge: mpsc::Sender<InternalEvent>,
+    tx_weather: mpsc::Sender<Weather>,
+    rx_outbound: mpsc::Receiver<Message>,
+    sweeper: JoinHandle<Option<usize>>,
+    dba: JoinHandle<Option<DrainReport>>,
+    spill_dir: SpillDir,
+}
+
+
+impl Pipeline {
+
+    pub async fn start(mut system: System) -> Self {
+        let spill_dir = SpillDir::new();
+        system.spill_dir = spill_dir.0.to_string_lossy().into_owned();
+        let storage = Arc::new(MemoryStorage::default());
+        let repo = storage.clone();
+        let app_context = AppContext::with_storage(system, |_, _| async move {
+            repo as Arc<dyn Storage>
+        }).await;
+
+        let (tx_edge, rx_edge) = mpsc::channel(100);
+        let (tx_to_dba, rx_dba) = mpsc::channel(100);
+        let (tx_to_bucket, rx_bucket) = mpsc::channel(100);
+        let (tx_unbucketed, rx_unbucketed) = mpsc::channel(100);
+        let (tx_telemetry, rx_telemetry) = mpsc::channel(100);
+        let (tx_weather, rx_weather) = mpsc::channel(10);
+        let (tx_outbound, rx_outbound) = mpsc::channel(100);
+
+        start_message_download(tx_to_dba, tx_to_bucket, rx_edge, app_context.clone());
+        let dba = start_dba(rx_dba, rx_telemetry, rx_unbucketed, rx_weather, tx_outbound, app_context.clone());
+        start_bucket(rx_bucket, tx_unbucketed, tx_telemetry.clone(), None, app_context.clone());
+        let sweeper = start_sweeper(tx_telemetry, app_context.clone());
+
+        Self { app_context, storage, tx_edge, tx_weather, rx_outbound, sweeper, dba, spill_dir }
+    }
+
+    /// Entrega un mensaje como si llegara de un Edge.
+    pub async fn send(&self, payload: Payload) {
+        let event = InternalEvent::IncomingMessage {
+            endpoint: "test".to_string(),
+            message: ToDataSaver { payload: Some(payload) },
+        };
+        self.tx_edge.send(event).await.expect("Error: message_download se cerró");
+    }
+
+    pub async fn send_weather(&self, weather: Weather) {
+        self.tx_weather.send(weather).await.expect("Error: dba_task se cerró");
+    }
+
+    /// Espera a que lo persistido cumpla `condition` y lo devuelve.
+    pub async fn wait_for(&self, condition: impl Fn(&Recorded) -> bool) -> Recorded {
+        let deadline = Instant::now() + WAIT_TIMEOUT;
+        loop {
+            let recorded = self.storage.recorded();
+            if condition(&recorded) {
+                return recorded;
+            }
+            assert!(Instant::now() < deadline, "no se alcanzó el estado esperado: {recorded:?}");
+            sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    /// Apagado ordenado como en `graceful_shutdown`: se dispara la señal, se cierran las
+    /// entradas y se espera a que el sweeper y `dba_task` terminen de vaciarse.
+    pub async fn stop(self) -> Stopped {
+        let Self { app_context, storage, tx_edge, tx_weather, mut rx_outbound, sweeper, dba, spill_dir } = self;
+
+        app_context.shutdown.trigger();
+        drop(tx_edge);
+        drop(tx_weather);
+
+        let flushed = timeout(WAIT_TIMEOUT, sweeper).await
+            .expect("el sweeper no terminó a tiempo")
+            .expect("el sweeper falló")
+            .unwrap_or_default();
+        let drain = timeout(WAIT_TIMEOUT, dba).await
+            .expect("dba_task no terminó a tiempo")
+            .expect("dba_task falló")
+            .unwrap_or_default();
+
+        let mut outbound = Vec::new();
+        while let Ok(msg) = rx_outbound.try_recv() {
+            outbound.push(msg);
+        }
+
+        drop(spill_dir);
+
+        Stopped { recorded: storage.recorded(), flushed, drain, outbound }
+    }
+}
+
+
+fn metadata(sender: &str, sequence_id: u64, timestamp: i64) -> Option<Metadata> {
+    Some(Metadata {
+        sender_user_id: sender.to_string(),
+        destination_id: "data_saver".to_string(),
+        timestamp,
+        sequence_id,
+    })
+}
+
+
+pub fn measurement(sender: &str, sequence_id: u64, timestamp: i64, temperature: f32) -> Measurement {
+    Measurement {
+        metadata: metadata(sender, sequence_id, timestamp),
+        network: NETWORK.to_string(),
+        pulse_counter: 1,
+        pulse_max_duration: 10,
+        temperature,
+        humidity: 50.0,
+        co2_ppm: 450.0,
+        sample: sequence_id as u32,
+    }
+}
+
+
+pub fn monitor(sender: &str, sequence_id: u64, timestamp: i64) -> Payload {
+    Payload::Monitor(Monitor {
+        metadata: metadata(sender, sequence_id, timestamp),
+        network: NETWORK.to_string(),
+        ..Default::default()
+    })
+}
+
+
+pub fn metric(sender: &str, sequence_id: u64, timestamp: i64) -> Payload {
+    Payload::Metric(SystemMetrics {
+        metadata: metadata(sender, sequence_id, timestamp),
+        uptime_seconds: 60,
+        ..Default::default()
+    })
+}
+
+
+pub fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
@@ -1,6 +1,6 @@
 use chrono::{DateTime, Utc};
 use reqwest::{Client, Error};
-use serde::{Deserialize};
+use serde::{Deserialize, Serialize};
 
 
 #[derive(Deserialize, Debug)]
@@ -14,7 +14,7 @@ pub struct CurrentWeather {
     pub relative_humidity_2m: f32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Weather {
     pub timestamp: DateTime<Utc>,
     pub temperature_2m: f32,
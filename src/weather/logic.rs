@@ -1,11 +1,15 @@
 use chrono::Utc;
+use crate::context::domain::AppContext;
+use crate::shutdown::domain::ShutdownSignal;
+use crate::supervisor::logic::supervise;
 use crate::weather::domain::{OpenMeteoClient, Weather};
 use tokio::time::Duration;
 use tokio::sync::mpsc;
 use tracing::{error, info};
 
 
-pub async fn weather_worker(tx_to_dba: mpsc::Sender<Weather>) {
+pub async fn weather_worker(tx_to_dba: mpsc::Sender<Weather>,
+                            shutdown: ShutdownSignal) {
     
     let meteo_client = OpenMeteoClient::new();
 
@@ -13,7 +17,13 @@ pub async fn weather_worker(tx_to_dba: mpsc::Sender<Weather>) {
     let mut interval = tokio::time::interval(Duration::from_secs(300));
 
     loop {
-        interval.tick().await;
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown.wait() => {
+                info!("Info: apagado en curso, weather_worker finalizado");
+                break;
+            }
+        }
 
         match meteo_client.fetch_weather().await {
             Ok(weather) => {
@@ -34,10 +44,11 @@ pub async fn weather_worker(tx_to_dba: mpsc::Sender<Weather>) {
 }
 
 
-pub fn start_weather_worker(tx_to_dba: mpsc::Sender<Weather>) {
+pub fn start_weather_worker(tx_to_dba: mpsc::Sender<Weather>,
+                            app_context: AppContext) {
     
     info!("Info: iniciando tarea weather_worker");
-    tokio::spawn(async move {
-        weather_worker(tx_to_dba).await;
+    supervise("weather_worker", false, &app_context.clone(), move || {
+        weather_worker(tx_to_dba.clone(), app_context.shutdown.clone())
     });
 }
\ No newline at end of file